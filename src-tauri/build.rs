@@ -1,3 +1,71 @@
+// Declaring our commands means only capabilities that allow them can call them, our remote
+// streaming pages get none
+const COMMANDS: &[&str] = &[
+    "create_tab",
+    "switch_source",
+    "switch_tab",
+    "close_tab",
+    "play_tab",
+    "pause_tab",
+    "next_track",
+    "previous_track",
+    "get_position",
+    "get_sources",
+    "get_all_sources",
+    "show_home",
+    "emit_backend_state",
+    "seek",
+    "seek_relative",
+    "set_volume",
+    "set_muted",
+    "set_rate",
+    "get_tab_metadata",
+    "toggle_tab_mute",
+    "set_restore_muted",
+    "set_playback_policy",
+    "set_auto_resume",
+    "start_sleep_timer",
+    "extend_sleep_timer",
+    "cancel_sleep_timer",
+    "get_schedules",
+    "add_schedule",
+    "remove_schedule",
+    "set_schedule_enabled",
+    "get_hooks",
+    "add_hook",
+    "remove_hook",
+    "set_hook_enabled",
+    "start_focus_session",
+    "stop_focus_session",
+    "query_history",
+    "export_history",
+    "get_history_retention",
+    "set_history_retention",
+    "get_stats",
+    "get_scrobbler_config",
+    "set_scrobble_sources",
+    "connect_last_fm",
+    "connect_listen_brainz",
+    "disconnect_scrobbler",
+    "get_metadata_rules",
+    "set_metadata_rules",
+    "get_discord_config",
+    "set_discord_config",
+    "get_mqtt_config",
+    "set_mqtt_config",
+    "get_settings",
+    "update_settings",
+    "set_mini_player",
+    "enter_picture_in_picture",
+    "exit_picture_in_picture",
+    "get_now_playing_export",
+    "set_now_playing_export",
+];
+
 fn main() {
-    tauri_build::build()
+    tauri_build::try_build(
+        tauri_build::Attributes::new()
+            .app_manifest(tauri_build::AppManifest::new().commands(COMMANDS)),
+    )
+    .expect("failed to run tauri-build");
 }
@@ -1,10 +1,17 @@
 // Base media monitor - injected into all streaming service webviews
-// TAB_LABEL and SOURCE_ID are set by the wrapper in scripts.rs
+// TAB_LABEL, SOURCE_ID and START_MUTED are set by the wrapper in media_sources.rs
 
 (function setupMediaMonitor() {
   const POLL_INTERVAL = 2000;
   let trackedElements = new WeakSet();
   let lastState = null;
+  let tabMuted = START_MUTED;
+  // The volume each element had before we ducked or faded it
+  const baseVolumes = new WeakMap();
+  let duckFactor = 1;
+  let fadeFactor = 1;
+  // The video we're floating in our picture-in-picture window, and whether it had its own controls
+  let pip = null;
 
   function getMetadata() {
     // Service-specific metadata extractors override this via window.__medyia_getMetadata
@@ -14,14 +21,29 @@
     return { title: document.title, artist: null, artworkUrl: null };
   }
 
-  function emitState(playing) {
+  function isAudible(el) {
+    return !el.paused && !el.ended && !el.muted && el.volume > 0;
+  }
+
+  function anyPlaying() {
+    return [...document.querySelectorAll('video, audio')].some((el) => !el.paused && !el.ended);
+  }
+
+  function emitState(playing, ended = false) {
     const metadata = getMetadata();
+    const media = findActiveMedia();
+    const audible = playing && [...document.querySelectorAll('video, audio')].some(isAudible);
     const state = {
       label: TAB_LABEL,
       playing: playing,
+      ended: ended,
+      audible: audible,
+      muted: tabMuted,
       title: metadata.title || null,
       artist: metadata.artist || null,
       artworkUrl: metadata.artworkUrl || null,
+      url: location.href,
+      duration: media && Number.isFinite(media.duration) ? media.duration : null,
     };
 
     const stateKey = JSON.stringify(state);
@@ -39,13 +61,162 @@
 
     el.addEventListener('play', () => emitState(true));
     el.addEventListener('pause', () => emitState(false));
-    el.addEventListener('ended', () => emitState(false));
+    el.addEventListener('ended', () => emitState(false, true));
+    el.addEventListener('volumechange', () => emitState(anyPlaying()));
+
+    if (tabMuted) {
+      el.muted = true;
+    }
   }
 
   function scanForMedia() {
     document.querySelectorAll('video, audio').forEach(bindMediaElement);
   }
 
+  // Prefer the element that is actually audible over the first `querySelector` match
+  function findActiveMedia() {
+    const mediaElements = [...document.querySelectorAll('video, audio')];
+    return (
+      mediaElements.find(isAudible) ||
+      mediaElements.find((el) => !el.paused && !el.ended) ||
+      mediaElements.find((el) => el.currentSrc) ||
+      null
+    );
+  }
+
+  function applyVolumeScale() {
+    const scale = duckFactor * fadeFactor;
+    document.querySelectorAll('video, audio').forEach((el) => {
+      const volume = baseVolumes.has(el) ? baseVolumes.get(el) : el.volume;
+      if (scale < 1) {
+        baseVolumes.set(el, volume);
+        el.volume = volume * scale;
+      } else {
+        baseVolumes.delete(el);
+        el.volume = volume;
+      }
+    });
+  }
+
+  function requireActiveMedia() {
+    const el = findActiveMedia();
+    if (!el) throw new Error('No media element found');
+    return el;
+  }
+
+  // RPC handlers, service scripts can register their own in window.__medyia_handlers
+  window.__medyia_handlers = Object.assign(window.__medyia_handlers || {}, {
+    play: () => requireActiveMedia().play(),
+    pause: () => {
+      document.querySelectorAll('video, audio').forEach((el) => el.pause());
+    },
+    seek: ({ seconds }) => {
+      requireActiveMedia().currentTime = seconds;
+    },
+    seekRelative: ({ seconds }) => {
+      const el = requireActiveMedia();
+      el.currentTime = Math.max(0, el.currentTime + seconds);
+    },
+    setVolume: ({ volume }) => {
+      const el = requireActiveMedia();
+      volume = Math.min(1, Math.max(0, volume));
+      // While ducked or faded, the volume we set is the one we'll restore
+      if (baseVolumes.has(el)) {
+        baseVolumes.set(el, volume);
+        el.volume = volume * duckFactor * fadeFactor;
+      } else {
+        el.volume = volume;
+      }
+    },
+    // Tab level mute, applies to every current and future media element
+    setMuted: ({ muted }) => {
+      tabMuted = muted;
+      document.querySelectorAll('video, audio').forEach((el) => (el.muted = muted));
+      emitState(anyPlaying());
+    },
+    setRate: ({ rate }) => {
+      requireActiveMedia().playbackRate = rate;
+    },
+    setDucked: ({ factor }) => {
+      duckFactor = factor;
+      applyVolumeScale();
+    },
+    setFade: ({ factor }) => {
+      fadeFactor = factor;
+      applyVolumeScale();
+    },
+    click: ({ selector }) => {
+      const el = document.querySelector(selector);
+      if (!el) throw new Error(`No element found for ${selector}`);
+      el.click();
+    },
+    getPosition: () => {
+      const el = requireActiveMedia();
+      return {
+        currentTime: el.currentTime,
+        duration: Number.isFinite(el.duration) ? el.duration : null,
+        playbackRate: el.playbackRate,
+      };
+    },
+    getMetadata: () => getMetadata(),
+    exitFullscreen: async () => {
+      if (document.fullscreenElement) {
+        await document.exitFullscreen();
+      } else if (document.webkitFullscreenElement) {
+        document.webkitExitFullscreen();
+      }
+    },
+    // Our picture-in-picture window shows only the video, with its native controls
+    enterPictureInPicture: () => {
+      const el = requireActiveMedia();
+      if (el.tagName !== 'VIDEO') throw new Error('Only videos can float');
+      if (pip) window.__medyia_handlers.exitPictureInPicture();
+
+      const style = document.createElement('style');
+      style.textContent = `
+        html, body { overflow: hidden !important; background: #000 !important; }
+        body * { visibility: hidden !important; }
+        video[data-medyia-pip] {
+          visibility: visible !important;
+          position: fixed !important;
+          inset: 0 !important;
+          width: 100vw !important;
+          height: 100vh !important;
+          z-index: 2147483647 !important;
+          object-fit: contain !important;
+          background: #000 !important;
+        }`;
+      document.head.appendChild(style);
+      pip = { el, style, controls: el.controls };
+      el.setAttribute('data-medyia-pip', '');
+      el.controls = true;
+    },
+    exitPictureInPicture: () => {
+      if (!pip) return;
+      pip.el.removeAttribute('data-medyia-pip');
+      pip.el.controls = pip.controls;
+      pip.style.remove();
+      pip = null;
+    },
+  });
+
+  // Called by Rust (rpc.rs), the reply is reported back with the same id and token
+  window.__medyia_rpc = async function (id, token, method, params) {
+    let reply;
+    try {
+      const handler = window.__medyia_handlers[method];
+      if (!handler) throw new Error(`Unknown method: ${method}`);
+      const value = await handler(params);
+      reply = { id, label: TAB_LABEL, token, ok: true, value: value ?? null, error: null };
+    } catch (e) {
+      reply = { id, label: TAB_LABEL, token, ok: false, value: null, error: String(e?.message ?? e) };
+    }
+
+    if (window.__TAURI__) {
+      window.__TAURI__.event.emit('rpc-reply', reply);
+    }
+  };
+
   // Initial scan
   scanForMedia();
 
@@ -61,17 +232,33 @@
   // Fallback polling
   setInterval(() => {
     scanForMedia();
-    const mediaElements = document.querySelectorAll('video, audio');
-    let anyPlaying = false;
-    mediaElements.forEach((el) => {
-      if (!el.paused && !el.ended) {
-        anyPlaying = true;
-      }
-    });
     // Only emit on poll if state changed
-    emitState(anyPlaying);
+    emitState(anyPlaying());
   }, POLL_INTERVAL);
 
+  // Fullscreen observer — Rust takes our window fullscreen while the page is
+  function fullscreenElement() {
+    return document.fullscreenElement || document.webkitFullscreenElement || null;
+  }
+
+  function emitFullscreen() {
+    if (window.__TAURI__) {
+      window.__TAURI__.event.emit('tab-fullscreen', {
+        label: TAB_LABEL,
+        fullscreen: fullscreenElement() !== null,
+      });
+    }
+  }
+
+  document.addEventListener('fullscreenchange', emitFullscreen);
+  document.addEventListener('webkitfullscreenchange', emitFullscreen);
+  // Players that handle their own keys don't always leave fullscreen on Escape
+  document.addEventListener('keydown', (e) => {
+    if (e.key === 'Escape' && fullscreenElement()) {
+      window.__medyia_handlers.exitFullscreen();
+    }
+  });
+
   // Tab title observer — emits document.title changes to Rust
   let lastDocTitle = '';
   function emitTitleChange() {
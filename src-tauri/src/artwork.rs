@@ -0,0 +1,391 @@
+use crate::media_sources::MediaSource;
+use anyhow::{anyhow, bail};
+use log::{debug, info, warn};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tauri::http::header::CONTENT_TYPE;
+use tauri::http::{Request, Response, StatusCode, Uri};
+use tauri::{AppHandle, Manager, Runtime, UriSchemeContext, UriSchemeResponder, Wry};
+
+pub const SCHEME: &str = "medyia";
+const ARTWORK_HOST: &str = "artwork";
+const ICON_HOST: &str = "icon";
+
+const CACHE_DIR: &str = "artwork";
+/// We evict the least recently used artwork past this
+const MAX_CACHE_BYTES: u64 = 64 * 1024 * 1024;
+/// Anything bigger isn't artwork
+const MAX_ARTWORK_BYTES: u64 = 8 * 1024 * 1024;
+
+pub struct ArtworkCache {
+    client: reqwest::Client,
+    dir: PathBuf,
+    /// Hashes we're downloading, so every playback event doesn't start another download
+    downloading: Mutex<HashSet<String>>,
+}
+
+pub fn setup_artwork_cache(app: &AppHandle) -> anyhow::Result<()> {
+    let dir = app.path().app_cache_dir()?.join(CACHE_DIR);
+    fs::create_dir_all(&dir)?;
+
+    app.manage(ArtworkCache {
+        client: reqwest::Client::new(),
+        dir,
+        downloading: Mutex::new(HashSet::new()),
+    });
+
+    Ok(())
+}
+
+/// Our URL for a page's artwork URL, whether or not we've cached it yet
+pub fn artwork_url(url: &str) -> String {
+    scheme_url(ARTWORK_HOST, &hash(url))
+}
+
+pub fn icon_url(source: MediaSource) -> String {
+    scheme_url(ICON_HOST, &source.source_id())
+}
+
+/// Where the artwork behind one of our artwork URLs is on disk, for native consumers
+pub fn cached_file<R: Runtime>(app: &impl Manager<R>, artwork_url: &str) -> Option<PathBuf> {
+    let hash = artwork_url.rsplit('/').next()?;
+    let path = cache_path(&app.try_state::<ArtworkCache>()?.dir, hash)?;
+
+    path.is_file().then_some(path)
+}
+
+/// Download `url` into our cache, returning our URL for it unless someone else is already downloading it
+pub async fn cache(app: &AppHandle, url: &str) -> anyhow::Result<Option<String>> {
+    let cache = app
+        .try_state::<ArtworkCache>()
+        .ok_or_else(|| anyhow!("The artwork cache isn't available"))?;
+    let hash = hash(url);
+    let path = cache.dir.join(&hash);
+
+    if path.is_file() {
+        touch(&path);
+    } else if cache.downloading.lock().unwrap().insert(hash.clone()) {
+        let result = cache.download(url, &path).await;
+        cache.downloading.lock().unwrap().remove(&hash);
+        result?;
+
+        if let Err(e) = evict(&cache.dir, MAX_CACHE_BYTES) {
+            warn!("Failed to evict artwork: {e}");
+        }
+    } else {
+        return Ok(None);
+    }
+
+    Ok(Some(scheme_url(ARTWORK_HOST, &hash)))
+}
+
+/// Our URL for `url` when we can cache it, otherwise the page's URL
+pub async fn cached_or_original(app: &AppHandle, url: String) -> String {
+    match cache(app, &url).await {
+        Ok(Some(cached)) => cached,
+        Ok(None) => url,
+        Err(e) => {
+            debug!("Couldn't cache artwork {url}: {e}");
+            url
+        }
+    }
+}
+
+/// Serves `medyia://artwork/<hash>` from our cache and `medyia://icon/<source>` from our bundled icons
+pub fn handle_request(
+    ctx: UriSchemeContext<'_, Wry>,
+    request: Request<Vec<u8>>,
+    responder: UriSchemeResponder,
+) {
+    let app = ctx.app_handle().clone();
+    tauri::async_runtime::spawn(async move {
+        let response = match serve(&app, &request) {
+            Ok(Some((content_type, body))) => Response::builder()
+                .header(CONTENT_TYPE, content_type)
+                .body(body),
+            Ok(None) => Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Vec::new()),
+            Err(e) => {
+                warn!("Failed to serve {}: {e}", request.uri());
+                Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Vec::new())
+            }
+        };
+
+        match response {
+            Ok(response) => responder.respond(response),
+            Err(e) => warn!("Failed to build a response for {}: {e}", request.uri()),
+        }
+    });
+}
+
+fn serve(
+    app: &AppHandle,
+    request: &Request<Vec<u8>>,
+) -> anyhow::Result<Option<(&'static str, Vec<u8>)>> {
+    match segments(request.uri()).as_slice() {
+        [ARTWORK_HOST, hash] => {
+            let Some(cache) = app.try_state::<ArtworkCache>() else {
+                return Ok(None);
+            };
+            let Some(path) = cache_path(&cache.dir, hash) else {
+                return Ok(None);
+            };
+            if !path.is_file() {
+                return Ok(None);
+            }
+
+            let body = fs::read(&path)?;
+            touch(&path);
+
+            Ok(Some((content_type(&body), body)))
+        }
+        [ICON_HOST, source_id] => Ok(MediaSource::ALL
+            .iter()
+            .find(|s| s.source_id() == *source_id)
+            .map(|s| ("image/svg+xml", s.icon().to_vec()))),
+        _ => Ok(None),
+    }
+}
+
+/// Windows serves custom schemes as http://medyia.localhost/<host>/<path>
+fn segments(uri: &Uri) -> Vec<&str> {
+    uri.host()
+        .filter(|host| !host.ends_with("localhost"))
+        .into_iter()
+        .chain(uri.path().split('/'))
+        .filter(|segment| !segment.is_empty())
+        .collect()
+}
+
+/// Our hashes are hex, anything else could escape our cache directory
+fn cache_path(dir: &Path, hash: &str) -> Option<PathBuf> {
+    (!hash.is_empty() && hash.chars().all(|c| c.is_ascii_hexdigit())).then(|| dir.join(hash))
+}
+
+impl ArtworkCache {
+    async fn download(&self, url: &str, path: &Path) -> anyhow::Result<()> {
+        debug!("Caching artwork {url}");
+
+        let response = self.client.get(url).send().await?.error_for_status()?;
+        if response
+            .content_length()
+            .is_some_and(|length| length > MAX_ARTWORK_BYTES)
+        {
+            bail!("Artwork {url} is too large");
+        }
+        let body = response.bytes().await?;
+        if body.len() as u64 > MAX_ARTWORK_BYTES {
+            bail!("Artwork {url} is too large");
+        }
+
+        // write somewhere else first so we never serve a partial file
+        let partial = path.with_extension("partial");
+        fs::write(&partial, &body)?;
+        fs::rename(&partial, path)?;
+
+        Ok(())
+    }
+}
+
+/// Remove the least recently used artwork in `dir` until it's under `max_bytes`
+fn evict(dir: &Path, max_bytes: u64) -> anyhow::Result<()> {
+    let mut entries = fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        // another download is still writing these, it'll rename them once it's done
+        .filter(|entry| entry.path().extension().is_none_or(|e| e != "partial"))
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            let used = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            Some((entry.path(), metadata.len(), used))
+        })
+        .collect::<Vec<_>>();
+
+    let mut total = entries.iter().map(|(_, size, _)| size).sum::<u64>();
+    if total <= max_bytes {
+        return Ok(());
+    }
+
+    entries.sort_by_key(|(_, _, used)| *used);
+    let mut removed = 0;
+    for (path, size, _) in entries {
+        if total <= max_bytes {
+            break;
+        }
+        fs::remove_file(&path)?;
+        total -= size;
+        removed += 1;
+    }
+    info!("Evicted {removed} cached artwork files");
+
+    Ok(())
+}
+
+fn scheme_url(host: &str, path: &str) -> String {
+    if cfg!(windows) {
+        format!("http://{SCHEME}.localhost/{host}/{path}")
+    } else {
+        format!("{SCHEME}://{host}/{path}")
+    }
+}
+
+fn hash(url: &str) -> String {
+    format!("{:x}", md5::compute(url))
+}
+
+/// We use modified times to find the least recently used artwork
+fn touch(path: &Path) {
+    if let Ok(file) = fs::File::options().write(true).open(path) {
+        file.set_modified(SystemTime::now()).ok();
+    }
+}
+
+fn content_type(body: &[u8]) -> &'static str {
+    match body {
+        [0x89, b'P', b'N', b'G', ..] => "image/png",
+        [0xFF, 0xD8, 0xFF, ..] => "image/jpeg",
+        [b'G', b'I', b'F', b'8', ..] => "image/gif",
+        [
+            b'R',
+            b'I',
+            b'F',
+            b'F',
+            _,
+            _,
+            _,
+            _,
+            b'W',
+            b'E',
+            b'B',
+            b'P',
+            ..,
+        ] => "image/webp",
+        _ if body.starts_with(b"<svg") || body.starts_with(b"<?xml") => "image/svg+xml",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// A fresh directory per test, they run in parallel
+    fn cache_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("medyia-artwork-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write(dir: &Path, name: &str, bytes: usize, used_secs: u64) {
+        let path = dir.join(name);
+        fs::write(&path, vec![0; bytes]).unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(used_secs))
+            .unwrap();
+    }
+
+    fn files(dir: &Path) -> Vec<String> {
+        let mut files = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn evicts_least_recently_used_until_under_the_limit() {
+        let cases = [
+            ("under", 300, vec!["aa", "bb", "cc"]),
+            // exactly at the limit is fine
+            ("at", 200, vec!["bb", "cc"]),
+            ("over", 150, vec!["cc"]),
+            ("nothing fits", 50, vec![]),
+        ];
+
+        for (name, max_bytes, expected) in cases {
+            let dir = cache_dir(name);
+            write(&dir, "bb", 100, 200);
+            write(&dir, "aa", 100, 100);
+            write(&dir, "cc", 100, 300);
+
+            evict(&dir, max_bytes).unwrap();
+            assert_eq!(files(&dir), expected, "{name}");
+
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn leaves_downloads_in_progress_alone() {
+        let dir = cache_dir("partial");
+        write(&dir, "aa.partial", 1000, 0);
+        write(&dir, "bb", 100, 100);
+        write(&dir, "cc", 100, 200);
+
+        evict(&dir, 100).unwrap();
+        assert_eq!(files(&dir), ["aa.partial", "cc"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn only_serves_hashes_from_our_cache() {
+        let dir = Path::new("/cache");
+        let cases = [
+            ("medyia://artwork/0123abcdef", Some("/cache/0123abcdef")),
+            (
+                "http://medyia.localhost/artwork/0123ABCDEF",
+                Some("/cache/0123ABCDEF"),
+            ),
+            ("medyia://artwork/..", None),
+            ("medyia://artwork/..%2F..%2Fsecret", None),
+            ("medyia://artwork/abc.partial", None),
+            ("medyia://artwork/../secret", None),
+            ("medyia://icon/YouTube", None),
+        ];
+
+        for (uri, expected) in cases {
+            let uri: Uri = uri.parse().unwrap();
+            let path = match segments(&uri).as_slice() {
+                [ARTWORK_HOST, hash] => cache_path(dir, hash),
+                _ => None,
+            };
+            assert_eq!(path.as_deref(), expected.map(Path::new), "{uri}");
+        }
+
+        assert_eq!(cache_path(dir, ""), None);
+    }
+
+    #[test]
+    fn content_types() {
+        let cases: [(&[u8], &str); 7] = [
+            (b"\x89PNG\r\n\x1a\n", "image/png"),
+            (b"\xFF\xD8\xFF\xE0", "image/jpeg"),
+            (b"GIF89a", "image/gif"),
+            (b"RIFF\0\0\0\0WEBPVP8 ", "image/webp"),
+            (
+                b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>",
+                "image/svg+xml",
+            ),
+            (b"<?xml version=\"1.0\"?><svg/>", "image/svg+xml"),
+            (b"RIFF\0\0\0\0WAVE", "application/octet-stream"),
+        ];
+
+        for (body, expected) in cases {
+            assert_eq!(content_type(body), expected, "{body:?}");
+        }
+        assert_eq!(content_type(b""), "application/octet-stream");
+    }
+}
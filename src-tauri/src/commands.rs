@@ -1,7 +1,33 @@
-use tauri::AppHandle;
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use tauri::{AppHandle, Webview};
 
+use crate::MAIN_WEBVIEW;
+use crate::artwork;
+use crate::controls::{
+    Click, GetMetadata, GetPosition, Pause, Play, Position, Seek, SeekRelative, SetMuted, SetRate,
+    SetVolume, TrackMetadata,
+};
+use crate::discord::{self, DiscordConfig};
+use crate::focus;
+use crate::history::{self, ExportFormat, HistoryEntry, HistoryQuery};
+use crate::hooks::{self, Hook, HookDefinition};
 use crate::media_sources::{MediaDefinition, MediaSource};
-use crate::state::{EnhancerAppStateManagerEmitter, TabKey};
+use crate::metadata::{self, MetadataRule};
+use crate::mini_player;
+use crate::mqtt::{self, MqttConfig};
+use crate::now_playing::{self, ExportConfig};
+use crate::picture_in_picture;
+use crate::playback::PlaybackPolicy;
+use crate::rpc;
+use crate::scheduler::{self, Schedule, ScheduleDefinition};
+use crate::scrobbler::{self, BackendKind, ScrobblerConfig};
+use crate::settings::{self, Settings};
+use crate::state::{
+    EnhancerAppStateManager, EnhancerAppStateManagerEmitter, FocusConfig, SleepTimerMode, TabKey,
+    TabKeyRef,
+};
+use crate::stats::{self, ListeningStats, StatsRange};
 use crate::webview_manager;
 
 #[tauri::command]
@@ -26,16 +52,306 @@ pub fn close_tab(app: AppHandle, key: TabKey) -> tauri::Result<()> {
     Ok(webview_manager::close_tab(&app, &key)?)
 }
 
+#[tauri::command]
+pub async fn play_tab(app: AppHandle, key: TabKey) -> tauri::Result<()> {
+    Ok(rpc::call(&app, &key, Play).await?)
+}
+
+#[tauri::command]
+pub async fn pause_tab(app: AppHandle, key: TabKey) -> tauri::Result<()> {
+    Ok(rpc::call(&app, &key, Pause).await?)
+}
+
+#[tauri::command]
+pub async fn next_track(app: AppHandle, key: TabKey) -> tauri::Result<()> {
+    let selector = tab_source(&app, &key)?.next_selector();
+    Ok(rpc::call(&app, &key, Click { selector }).await?)
+}
+
+#[tauri::command]
+pub async fn previous_track(app: AppHandle, key: TabKey) -> tauri::Result<()> {
+    let selector = tab_source(&app, &key)?.previous_selector();
+    Ok(rpc::call(&app, &key, Click { selector }).await?)
+}
+
+#[tauri::command]
+pub async fn get_position(app: AppHandle, key: TabKey) -> tauri::Result<Position> {
+    Ok(rpc::call(&app, &key, GetPosition).await?)
+}
+
+#[tauri::command]
+pub async fn seek(app: AppHandle, key: TabKey, seconds: f64) -> tauri::Result<()> {
+    Ok(rpc::call(&app, &key, Seek { seconds }).await?)
+}
+
+#[tauri::command]
+pub async fn seek_relative(app: AppHandle, key: TabKey, seconds: f64) -> tauri::Result<()> {
+    Ok(rpc::call(&app, &key, SeekRelative { seconds }).await?)
+}
+
+#[tauri::command]
+pub async fn set_volume(app: AppHandle, key: TabKey, volume: f64) -> tauri::Result<()> {
+    Ok(rpc::call(&app, &key, SetVolume { volume }).await?)
+}
+
+#[tauri::command]
+pub async fn set_muted(app: AppHandle, key: TabKey, muted: bool) -> tauri::Result<()> {
+    Ok(rpc::call(&app, &key, SetMuted { muted }).await?)
+}
+
+#[tauri::command]
+pub async fn set_rate(app: AppHandle, key: TabKey, rate: f64) -> tauri::Result<()> {
+    Ok(rpc::call(&app, &key, SetRate { rate }).await?)
+}
+
+#[tauri::command]
+pub async fn get_tab_metadata(app: AppHandle, key: TabKey) -> tauri::Result<TrackMetadata> {
+    let mut metadata = rpc::call(&app, &key, GetMetadata).await?;
+    if let Some(url) = metadata.artwork_url.take() {
+        metadata.artwork_url = Some(artwork::cached_or_original(&app, url).await);
+    }
+
+    Ok(metadata)
+}
+
+#[tauri::command]
+pub async fn toggle_tab_mute(app: AppHandle, key: TabKey) -> tauri::Result<bool> {
+    Ok(webview_manager::toggle_tab_mute(&app, &key).await?)
+}
+
+#[tauri::command]
+pub fn set_restore_muted(app: AppHandle, restore_muted: bool) -> tauri::Result<()> {
+    Ok(webview_manager::set_restore_muted(&app, restore_muted)?)
+}
+
+#[tauri::command]
+pub fn set_playback_policy(app: AppHandle, policy: PlaybackPolicy) -> tauri::Result<()> {
+    Ok(webview_manager::set_playback_policy(&app, policy)?)
+}
+
+#[tauri::command]
+pub fn set_auto_resume(app: AppHandle, source: MediaSource, enabled: bool) -> tauri::Result<()> {
+    Ok(webview_manager::set_auto_resume(&app, source, enabled)?)
+}
+
+#[tauri::command]
+pub fn start_sleep_timer(app: AppHandle, mode: SleepTimerMode) -> tauri::Result<()> {
+    Ok(webview_manager::start_sleep_timer(&app, mode)?)
+}
+
+#[tauri::command]
+pub fn extend_sleep_timer(app: AppHandle, amount: u32) -> tauri::Result<()> {
+    Ok(webview_manager::extend_sleep_timer(&app, amount)?)
+}
+
+#[tauri::command]
+pub fn cancel_sleep_timer(app: AppHandle) -> tauri::Result<()> {
+    Ok(webview_manager::cancel_sleep_timer(&app)?)
+}
+
+#[tauri::command]
+pub fn get_schedules(app: AppHandle) -> tauri::Result<Vec<Schedule>> {
+    Ok(scheduler::get_schedules(&app)?)
+}
+
+#[tauri::command]
+pub fn add_schedule(app: AppHandle, definition: ScheduleDefinition) -> tauri::Result<Schedule> {
+    Ok(scheduler::add_schedule(&app, definition)?)
+}
+
+#[tauri::command]
+pub fn remove_schedule(app: AppHandle, id: String) -> tauri::Result<()> {
+    Ok(scheduler::remove_schedule(&app, &id)?)
+}
+
+#[tauri::command]
+pub fn set_schedule_enabled(app: AppHandle, id: String, enabled: bool) -> tauri::Result<()> {
+    Ok(scheduler::set_schedule_enabled(&app, &id, enabled)?)
+}
+
+#[tauri::command]
+pub fn get_hooks(app: AppHandle) -> tauri::Result<Vec<Hook>> {
+    Ok(hooks::get_hooks(&app)?)
+}
+
+#[tauri::command]
+pub fn add_hook(app: AppHandle, definition: HookDefinition) -> tauri::Result<Hook> {
+    Ok(hooks::add_hook(&app, definition)?)
+}
+
+#[tauri::command]
+pub fn remove_hook(app: AppHandle, id: String) -> tauri::Result<()> {
+    Ok(hooks::remove_hook(&app, &id)?)
+}
+
+#[tauri::command]
+pub fn set_hook_enabled(app: AppHandle, id: String, enabled: bool) -> tauri::Result<()> {
+    Ok(hooks::set_hook_enabled(&app, &id, enabled)?)
+}
+
+#[tauri::command]
+pub async fn start_focus_session(app: AppHandle, config: FocusConfig) -> tauri::Result<()> {
+    Ok(focus::start_focus_session(&app, config).await?)
+}
+
+#[tauri::command]
+pub fn stop_focus_session(app: AppHandle) -> tauri::Result<()> {
+    Ok(focus::stop_focus_session(&app)?)
+}
+
+#[tauri::command]
+pub fn query_history(app: AppHandle, query: HistoryQuery) -> tauri::Result<Vec<HistoryEntry>> {
+    Ok(history::query(&app, &query)?)
+}
+
+/// This writes wherever it's told, so only our main page may ask
+#[tauri::command]
+pub fn export_history(
+    app: AppHandle,
+    webview: Webview,
+    query: HistoryQuery,
+    format: ExportFormat,
+    path: PathBuf,
+) -> tauri::Result<()> {
+    if webview.label() != MAIN_WEBVIEW {
+        return Err(anyhow::anyhow!("{} can't export our history", webview.label()).into());
+    }
+
+    Ok(history::export(&app, &query, format, &path)?)
+}
+
+#[tauri::command]
+pub fn get_history_retention(app: AppHandle) -> tauri::Result<Option<u32>> {
+    Ok(history::retention_days(&app)?)
+}
+
+#[tauri::command]
+pub fn set_history_retention(app: AppHandle, days: Option<u32>) -> tauri::Result<()> {
+    Ok(history::set_retention_days(&app, days)?)
+}
+
+#[tauri::command]
+pub fn get_stats(app: AppHandle, range: StatsRange) -> tauri::Result<ListeningStats> {
+    Ok(stats::get_stats(&app, range)?)
+}
+
+#[tauri::command]
+pub fn get_scrobbler_config(app: AppHandle) -> tauri::Result<ScrobblerConfig> {
+    Ok(scrobbler::get_config(&app)?)
+}
+
+#[tauri::command]
+pub fn set_scrobble_sources(app: AppHandle, sources: BTreeSet<MediaSource>) -> tauri::Result<()> {
+    Ok(scrobbler::set_sources(&app, sources)?)
+}
+
+#[tauri::command]
+pub async fn connect_last_fm(
+    app: AppHandle,
+    api_key: String,
+    api_secret: String,
+    username: String,
+    password: String,
+) -> tauri::Result<()> {
+    Ok(scrobbler::connect_last_fm(&app, api_key, api_secret, username, password).await?)
+}
+
+#[tauri::command]
+pub async fn connect_listen_brainz(
+    app: AppHandle,
+    token: String,
+    api_url: Option<String>,
+) -> tauri::Result<()> {
+    Ok(scrobbler::connect_listen_brainz(&app, token, api_url).await?)
+}
+
+#[tauri::command]
+pub fn disconnect_scrobbler(app: AppHandle, backend: BackendKind) -> tauri::Result<()> {
+    Ok(scrobbler::disconnect(&app, backend)?)
+}
+
+#[tauri::command]
+pub fn get_metadata_rules(app: AppHandle) -> tauri::Result<Vec<MetadataRule>> {
+    Ok(metadata::get_rules(&app)?)
+}
+
+#[tauri::command]
+pub fn set_metadata_rules(app: AppHandle, rules: Vec<MetadataRule>) -> tauri::Result<()> {
+    Ok(metadata::set_rules(&app, rules)?)
+}
+
+#[tauri::command]
+pub fn get_discord_config(app: AppHandle) -> tauri::Result<DiscordConfig> {
+    Ok(discord::get_config(&app)?)
+}
+
+#[tauri::command]
+pub fn set_discord_config(app: AppHandle, config: DiscordConfig) -> tauri::Result<()> {
+    Ok(discord::set_config(&app, config)?)
+}
+
+#[tauri::command]
+pub fn get_mqtt_config(app: AppHandle) -> tauri::Result<MqttConfig> {
+    Ok(mqtt::get_config(&app)?)
+}
+
+#[tauri::command]
+pub fn set_mqtt_config(app: AppHandle, config: MqttConfig) -> tauri::Result<()> {
+    Ok(mqtt::set_config(&app, config)?)
+}
+
+#[tauri::command]
+pub fn get_settings(app: AppHandle) -> Settings {
+    settings::get(&app)
+}
+
+#[tauri::command]
+pub fn update_settings(
+    app: AppHandle,
+    settings: Settings,
+    close_hidden_tabs: bool,
+) -> tauri::Result<()> {
+    Ok(settings::update(&app, settings, close_hidden_tabs)?)
+}
+
+#[tauri::command]
+pub fn set_mini_player(app: AppHandle, enabled: bool) -> tauri::Result<()> {
+    Ok(mini_player::set_mini_player(&app, enabled)?)
+}
+
+#[tauri::command]
+pub async fn enter_picture_in_picture(app: AppHandle, key: TabKey) -> tauri::Result<()> {
+    Ok(picture_in_picture::enter(&app, &key).await?)
+}
+
+#[tauri::command]
+pub fn exit_picture_in_picture(app: AppHandle) -> tauri::Result<()> {
+    Ok(picture_in_picture::exit(&app)?)
+}
+
+#[tauri::command]
+pub fn get_now_playing_export(app: AppHandle) -> tauri::Result<ExportConfig> {
+    Ok(now_playing::get_config(&app)?)
+}
+
+#[tauri::command]
+pub fn set_now_playing_export(app: AppHandle, config: ExportConfig) -> tauri::Result<()> {
+    Ok(now_playing::set_config(&app, config)?)
+}
+
 // #[tauri::command]
 // pub fn get_tabs(app: AppHandle) -> tauri::Result<Vec<TabState>> {
 //     app.tabs_state_mut(|tab_state| tab_state.get_ordered_tabs())
 // }
 
 #[tauri::command]
-pub fn get_sources() -> Vec<MediaDefinition> {
-    MediaSource::ALL
-        .iter()
-        .map(MediaSource::definition)
+pub fn get_sources(app: AppHandle) -> Vec<MediaDefinition> {
+    let settings = settings::get(&app);
+
+    settings
+        .visible_sources()
+        .into_iter()
+        .map(|source| source.definition().with_settings(&settings))
         .collect()
 }
 
@@ -46,8 +362,31 @@ pub fn get_sources() -> Vec<MediaDefinition> {
 //     state.active_tab_key.clone()
 // }
 
+/// Hidden sources too, so our settings can bring them back
+#[tauri::command]
+pub fn get_all_sources(app: AppHandle) -> Vec<MediaDefinition> {
+    let settings = settings::get(&app);
+
+    settings
+        .ordered_sources()
+        .into_iter()
+        .map(|source| source.definition().with_settings(&settings))
+        .collect()
+}
+
+#[tauri::command]
+pub fn show_home(app: AppHandle) -> tauri::Result<()> {
+    webview_manager::show_home(&app)?;
+    Ok(())
+}
+
 #[tauri::command]
 pub fn emit_backend_state(app: AppHandle) -> tauri::Result<()> {
     app.emit_app_state()?;
     Ok(())
 }
+
+fn tab_source(app: &AppHandle, key: TabKeyRef) -> anyhow::Result<MediaSource> {
+    app.app_state(|state| state.tab(key).map(|t| t.source))
+        .ok_or_else(|| anyhow::anyhow!("No tab found for {key}"))
+}
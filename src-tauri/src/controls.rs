@@ -0,0 +1,169 @@
+use crate::rpc::RpcRequest;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct Play;
+
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct Pause;
+
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct Seek {
+    pub seconds: f64,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct SeekRelative {
+    pub seconds: f64,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct SetVolume {
+    pub volume: f64,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct SetMuted {
+    pub muted: bool,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct SetRate {
+    pub rate: f64,
+}
+
+/// Scale the volume of every media element by `factor`, a factor of 1 restores the original volume
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct SetDucked {
+    pub factor: f64,
+}
+
+/// Scale the volume of every media element by `factor` on top of any ducking
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct SetFade {
+    pub factor: f64,
+}
+
+/// Click the page's own control, for things like skipping that media elements can't do
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct Click {
+    pub selector: &'static str,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct ExitFullscreen;
+
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct EnterPictureInPicture;
+
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct ExitPictureInPicture;
+
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct GetPosition;
+
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct GetMetadata;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackMetadata {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub artwork_url: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct Position {
+    pub current_time: f64,
+    /// Missing for live streams
+    pub duration: Option<f64>,
+    pub playback_rate: f64,
+}
+
+impl Position {
+    /// Wall clock time until we reach the end of the track
+    pub fn remaining(&self) -> Option<Duration> {
+        self.duration.map(|duration| {
+            let remaining = (duration - self.current_time).max(0.);
+            Duration::from_secs_f64(remaining / self.playback_rate.max(f64::EPSILON))
+        })
+    }
+}
+
+impl RpcRequest for Play {
+    const METHOD: &'static str = "play";
+    type Response = ();
+}
+
+impl RpcRequest for Pause {
+    const METHOD: &'static str = "pause";
+    type Response = ();
+}
+
+impl RpcRequest for Seek {
+    const METHOD: &'static str = "seek";
+    type Response = ();
+}
+
+impl RpcRequest for SeekRelative {
+    const METHOD: &'static str = "seekRelative";
+    type Response = ();
+}
+
+impl RpcRequest for SetVolume {
+    const METHOD: &'static str = "setVolume";
+    type Response = ();
+}
+
+impl RpcRequest for SetMuted {
+    const METHOD: &'static str = "setMuted";
+    type Response = ();
+}
+
+impl RpcRequest for SetRate {
+    const METHOD: &'static str = "setRate";
+    type Response = ();
+}
+
+impl RpcRequest for SetDucked {
+    const METHOD: &'static str = "setDucked";
+    type Response = ();
+}
+
+impl RpcRequest for SetFade {
+    const METHOD: &'static str = "setFade";
+    type Response = ();
+}
+
+impl RpcRequest for Click {
+    const METHOD: &'static str = "click";
+    type Response = ();
+}
+
+impl RpcRequest for ExitFullscreen {
+    const METHOD: &'static str = "exitFullscreen";
+    type Response = ();
+}
+
+impl RpcRequest for EnterPictureInPicture {
+    const METHOD: &'static str = "enterPictureInPicture";
+    type Response = ();
+}
+
+impl RpcRequest for ExitPictureInPicture {
+    const METHOD: &'static str = "exitPictureInPicture";
+    type Response = ();
+}
+
+impl RpcRequest for GetPosition {
+    const METHOD: &'static str = "getPosition";
+    type Response = Position;
+}
+
+impl RpcRequest for GetMetadata {
+    const METHOD: &'static str = "getMetadata";
+    type Response = TrackMetadata;
+}
@@ -0,0 +1,611 @@
+use crate::EnhancedResult;
+use crate::controls::{GetPosition, Position};
+use crate::media_sources::MediaSource;
+use crate::rpc;
+use crate::state::EnhancerAppStateManager;
+use chrono::Utc;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_store::StoreExt;
+use tokio::sync::watch;
+
+const STORE_PATH: &str = "medyia-discord.json";
+const CONFIG_KEY: &str = "config";
+
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(15);
+/// Discord rejects activity text outside of this length
+const MIN_TEXT_LENGTH: usize = 2;
+const MAX_TEXT_LENGTH: usize = 128;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscordConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// The Discord application we show our activity as
+    pub client_id: Option<String>,
+    /// Sources we never share
+    #[serde(default)]
+    pub hidden_sources: BTreeSet<MediaSource>,
+}
+
+/// What we're showing on Discord
+#[derive(Debug, Clone, PartialEq)]
+struct Presence {
+    source: MediaSource,
+    title: String,
+    artist: Option<String>,
+    /// Unix millis, from our position telemetry
+    start: Option<i64>,
+    end: Option<i64>,
+}
+
+pub struct DiscordPresence {
+    config: watch::Sender<DiscordConfig>,
+    presence: watch::Sender<Option<Presence>>,
+    /// Bumped on every update, so a position that arrives late can't undo a newer one
+    generation: AtomicU64,
+}
+
+pub fn setup_discord(app: &AppHandle) -> anyhow::Result<()> {
+    let config = get_config(app)?;
+    let (config, config_receiver) = watch::channel(config);
+    let (presence, presence_receiver) = watch::channel(None);
+    app.manage(DiscordPresence {
+        config,
+        presence,
+        generation: AtomicU64::new(0),
+    });
+
+    tauri::async_runtime::spawn(ipc::run(config_receiver, presence_receiver));
+
+    Ok(())
+}
+
+/// Show whatever is playing now, or clear our presence once nothing is
+pub fn update_presence(app: &AppHandle) {
+    let Some(discord) = app.try_state::<DiscordPresence>() else {
+        return;
+    };
+    if !discord.config.borrow().enabled {
+        return;
+    }
+
+    let generation = discord.next_generation();
+    let playing = app.app_state(|state| {
+        state
+            .playing_tab()
+            .map(|t| (t.key.clone(), t.source, t.title.clone(), t.artist.clone()))
+    });
+    let Some((label, source, Some(title), artist)) = playing else {
+        discord.set(generation, None);
+        return;
+    };
+    if discord.config.borrow().hidden_sources.contains(&source) {
+        discord.set(generation, None);
+        return;
+    }
+
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let position = rpc::call(&app, &label, GetPosition).await;
+        if let Err(e) = &position {
+            debug!("Couldn't get the position of {label}: {e}");
+        }
+        let (start, end) = position.ok().map_or((None, None), timestamps);
+
+        app.state::<DiscordPresence>().set(
+            generation,
+            Some(Presence {
+                source,
+                title,
+                artist,
+                start,
+                end,
+            }),
+        );
+    });
+}
+
+pub fn get_config(app: &AppHandle) -> anyhow::Result<DiscordConfig> {
+    let store = app.store(STORE_PATH)?;
+
+    match store.get(CONFIG_KEY) {
+        Some(config) => Ok(serde_json::from_value(config)?),
+        None => Ok(DiscordConfig::default()),
+    }
+}
+
+pub fn set_config(app: &AppHandle, config: DiscordConfig) -> anyhow::Result<()> {
+    let store = app.store(STORE_PATH)?;
+    store.set(CONFIG_KEY, serde_json::to_value(&config)?);
+    store.save()?;
+
+    if let Some(discord) = app.try_state::<DiscordPresence>() {
+        let playing = discord
+            .presence
+            .borrow()
+            .as_ref()
+            .map(|presence| presence.source);
+        if playing.is_some_and(|source| config.hidden_sources.contains(&source)) {
+            let generation = discord.next_generation();
+            discord.set(generation, None);
+        }
+        discord.config.send_replace(config);
+    }
+
+    Ok(())
+}
+
+impl DiscordPresence {
+    /// Start an update, anything still waiting on an older one is dropped
+    fn next_generation(&self) -> u64 {
+        self.generation.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// Show `updated` unless a newer update started since `generation`
+    fn set(&self, generation: u64, updated: Option<Presence>) {
+        self.presence.send_if_modified(|presence| {
+            // checked under the channel's lock so we can't race a newer update
+            if self.generation.load(Ordering::SeqCst) != generation {
+                return false;
+            }
+
+            let changed = *presence != updated;
+            *presence = updated;
+
+            changed
+        });
+    }
+}
+
+/// When the track started and will end, according to the page
+fn timestamps(position: Position) -> (Option<i64>, Option<i64>) {
+    let now = Utc::now().timestamp_millis();
+    let start = now - (position.current_time * 1000.) as i64;
+    let end = position
+        .duration
+        .filter(|d| d.is_finite())
+        .map(|duration| start + (duration * 1000.) as i64);
+
+    (Some(start), end)
+}
+
+impl Presence {
+    fn activity(&self) -> Value {
+        let mut activity = json!({
+            // listening
+            "type": 2,
+            "details": truncate(&self.title),
+            "state": truncate(self.artist.as_deref().unwrap_or(self.source.name())),
+            "assets": {
+                "large_text": truncate(self.source.name()),
+            },
+        });
+        if let Some(start) = self.start {
+            activity["timestamps"] = json!({ "start": start, "end": self.end });
+        }
+
+        activity
+    }
+}
+
+fn truncate(text: &str) -> String {
+    let mut text = text.chars().take(MAX_TEXT_LENGTH).collect::<String>();
+    while text.chars().count() < MIN_TEXT_LENGTH {
+        text.push(' ');
+    }
+
+    text
+}
+
+#[cfg(unix)]
+mod ipc {
+    use super::*;
+    use rand::distr::{Alphanumeric, SampleString};
+    use std::path::{Path, PathBuf};
+    use tauri::async_runtime::JoinHandle;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::UnixStream;
+    use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+    use tokio::time::sleep;
+
+    const OP_HANDSHAKE: u32 = 0;
+    const OP_FRAME: u32 = 1;
+    const OP_CLOSE: u32 = 2;
+
+    /// Flatpak and Snap installs put their socket in a subdirectory
+    const SOCKET_DIRS: &[&str] = &["", "app/com.discordapp.Discord", "snap.discord"];
+
+    /// Keep Discord's activity in sync with our presence, reconnecting whenever Discord restarts
+    pub(super) async fn run(
+        mut config: watch::Receiver<DiscordConfig>,
+        mut presence: watch::Receiver<Option<Presence>>,
+    ) {
+        loop {
+            let current = config.borrow_and_update().clone();
+            let client_id = current.client_id.filter(|_| current.enabled);
+            let Some(client_id) = client_id else {
+                if config.changed().await.is_err() {
+                    return;
+                }
+                continue;
+            };
+
+            match Connection::connect(&client_id).await {
+                Ok(connection) => {
+                    info!("Connected to Discord");
+                    if let Err(e) = connection.serve(&mut config, &mut presence).await {
+                        warn!("Lost our Discord connection: {e}");
+                    }
+                }
+                Err(e) => {
+                    debug!("Couldn't connect to Discord: {e}");
+                    tokio::select! {
+                        _ = sleep(RECONNECT_INTERVAL) => {}
+                        changed = config.changed() => if changed.is_err() {
+                            return;
+                        },
+                    }
+                }
+            }
+        }
+    }
+
+    struct Connection {
+        writer: OwnedWriteHalf,
+        /// Finishes when Discord closes our connection
+        reader: JoinHandle<()>,
+    }
+
+    impl Connection {
+        async fn connect(client_id: &str) -> anyhow::Result<Self> {
+            let mut last_error = None;
+            for path in socket_paths() {
+                match Self::connect_to(&path, client_id).await {
+                    Ok(connection) => return Ok(connection),
+                    Err(e) => last_error = Some(e),
+                }
+            }
+
+            Err(last_error.unwrap_or_else(|| anyhow::anyhow!("No Discord socket found")))
+        }
+
+        async fn connect_to(path: &Path, client_id: &str) -> anyhow::Result<Self> {
+            let stream = UnixStream::connect(path).await?;
+            let (reader, writer) = stream.into_split();
+            let mut connection = Self {
+                writer,
+                reader: tauri::async_runtime::spawn(read_frames(reader)),
+            };
+            connection
+                .write(OP_HANDSHAKE, json!({ "v": 1, "client_id": client_id }))
+                .await?;
+
+            Ok(connection)
+        }
+
+        /// Returns once our config changes so we reconnect with it, or with an error if Discord goes away
+        async fn serve(
+            mut self,
+            config: &mut watch::Receiver<DiscordConfig>,
+            presence: &mut watch::Receiver<Option<Presence>>,
+        ) -> anyhow::Result<()> {
+            let current = presence.borrow_and_update().clone();
+            self.set_activity(current.as_ref()).await?;
+
+            loop {
+                tokio::select! {
+                    changed = presence.changed() => {
+                        changed?;
+                        let current = presence.borrow_and_update().clone();
+                        self.set_activity(current.as_ref()).await?;
+                    }
+                    changed = config.changed() => {
+                        changed?;
+                        // don't leave our activity behind under the old config
+                        self.set_activity(None).await.log_warn();
+                        self.reader.abort();
+                        return Ok(());
+                    }
+                    _ = &mut self.reader => {
+                        anyhow::bail!("Discord closed our connection");
+                    }
+                }
+            }
+        }
+
+        async fn set_activity(&mut self, presence: Option<&Presence>) -> anyhow::Result<()> {
+            debug!("Setting our Discord activity to {presence:?}");
+
+            self.write(
+                OP_FRAME,
+                json!({
+                    "cmd": "SET_ACTIVITY",
+                    "args": {
+                        "pid": std::process::id(),
+                        "activity": presence.map(Presence::activity),
+                    },
+                    "nonce": Alphanumeric.sample_string(&mut rand::rng(), 16),
+                }),
+            )
+            .await
+        }
+
+        /// Frames are a little endian opcode and length followed by JSON
+        async fn write(&mut self, opcode: u32, payload: Value) -> anyhow::Result<()> {
+            let payload = serde_json::to_vec(&payload)?;
+            let mut frame = Vec::with_capacity(8 + payload.len());
+            frame.extend_from_slice(&opcode.to_le_bytes());
+            frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+            frame.extend_from_slice(&payload);
+
+            self.writer.write_all(&frame).await?;
+
+            Ok(())
+        }
+    }
+
+    impl Drop for Connection {
+        fn drop(&mut self) {
+            self.reader.abort();
+        }
+    }
+
+    async fn read_frames(mut reader: OwnedReadHalf) {
+        let mut header = [0; 8];
+        loop {
+            if reader.read_exact(&mut header).await.is_err() {
+                return;
+            }
+            let opcode = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+            let length = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+
+            let mut payload = vec![0; length as usize];
+            if reader.read_exact(&mut payload).await.is_err() {
+                return;
+            }
+            debug!(
+                "Discord frame {opcode}: {}",
+                String::from_utf8_lossy(&payload)
+            );
+
+            if opcode == OP_CLOSE {
+                return;
+            }
+        }
+    }
+
+    fn socket_paths() -> Vec<PathBuf> {
+        let base = ["XDG_RUNTIME_DIR", "TMPDIR", "TMP", "TEMP"]
+            .iter()
+            .find_map(std::env::var_os)
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("/tmp"));
+        let base = base.as_path();
+
+        SOCKET_DIRS
+            .iter()
+            .flat_map(|dir| (0..10).map(move |i| base.join(dir).join(format!("discord-ipc-{i}"))))
+            .filter(|path| path.exists())
+            .collect()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use tokio::net::UnixListener;
+
+        /// Reads one frame the way Discord would
+        async fn read_frame(discord: &mut UnixStream) -> (u32, Value) {
+            let mut header = [0; 8];
+            discord.read_exact(&mut header).await.unwrap();
+            let opcode = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+            let length = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+
+            let mut payload = vec![0; length as usize];
+            discord.read_exact(&mut payload).await.unwrap();
+
+            (opcode, serde_json::from_slice(&payload).unwrap())
+        }
+
+        async fn read_activity(discord: &mut UnixStream) -> Value {
+            let (opcode, payload) = read_frame(discord).await;
+            assert_eq!(opcode, OP_FRAME);
+            assert_eq!(payload["cmd"], "SET_ACTIVITY");
+            assert_eq!(payload["args"]["pid"], std::process::id());
+
+            payload["args"]["activity"].clone()
+        }
+
+        /// Connects to a fake Discord listening on its own socket
+        async fn connect() -> (Connection, UnixStream) {
+            let path = std::env::temp_dir().join(format!(
+                "medyia-discord-ipc-{}",
+                Alphanumeric.sample_string(&mut rand::rng(), 8)
+            ));
+            let listener = UnixListener::bind(&path).unwrap();
+
+            let (connection, accepted) =
+                tokio::join!(Connection::connect_to(&path, "1234"), listener.accept());
+            std::fs::remove_file(&path).unwrap();
+
+            (connection.unwrap(), accepted.unwrap().0)
+        }
+
+        fn serve(
+            connection: Connection,
+        ) -> (
+            watch::Sender<DiscordConfig>,
+            watch::Sender<Option<Presence>>,
+            tokio::task::JoinHandle<anyhow::Result<()>>,
+        ) {
+            let (config_sender, mut config) = watch::channel(DiscordConfig::default());
+            let (presence_sender, mut presence) = watch::channel(None);
+            let served =
+                tokio::spawn(async move { connection.serve(&mut config, &mut presence).await });
+
+            (config_sender, presence_sender, served)
+        }
+
+        fn presence() -> Presence {
+            Presence {
+                source: MediaSource::YouTube,
+                title: "Song".to_string(),
+                artist: Some("Artist".to_string()),
+                start: Some(1_000),
+                end: Some(181_000),
+            }
+        }
+
+        #[tokio::test]
+        async fn handshakes_with_our_client_id() {
+            let (_connection, mut discord) = connect().await;
+
+            let (opcode, payload) = read_frame(&mut discord).await;
+            assert_eq!(opcode, OP_HANDSHAKE);
+            assert_eq!(payload, json!({ "v": 1, "client_id": "1234" }));
+        }
+
+        #[tokio::test]
+        async fn follows_our_presence() {
+            let (connection, mut discord) = connect().await;
+            read_frame(&mut discord).await;
+            let (_config, presence_sender, _served) = serve(connection);
+
+            assert_eq!(read_activity(&mut discord).await, Value::Null);
+
+            presence_sender.send_replace(Some(presence()));
+            let activity = read_activity(&mut discord).await;
+            assert_eq!(activity["details"], "Song");
+            assert_eq!(activity["state"], "Artist");
+            assert_eq!(
+                activity["timestamps"],
+                json!({ "start": 1_000, "end": 181_000 })
+            );
+
+            presence_sender.send_replace(None);
+            assert_eq!(read_activity(&mut discord).await, Value::Null);
+        }
+
+        #[tokio::test]
+        async fn clears_activity_when_config_changes() {
+            let (connection, mut discord) = connect().await;
+            read_frame(&mut discord).await;
+            let (config, presence_sender, served) = serve(connection);
+            read_activity(&mut discord).await;
+            presence_sender.send_replace(Some(presence()));
+            assert!(!read_activity(&mut discord).await.is_null());
+
+            config.send_replace(DiscordConfig {
+                enabled: true,
+                ..DiscordConfig::default()
+            });
+
+            assert_eq!(read_activity(&mut discord).await, Value::Null);
+            served.await.unwrap().unwrap();
+        }
+
+        #[tokio::test]
+        async fn notices_discord_going_away() {
+            let (connection, mut discord) = connect().await;
+            read_frame(&mut discord).await;
+            let (_config, _presence, served) = serve(connection);
+            read_activity(&mut discord).await;
+
+            drop(discord);
+            assert!(served.await.unwrap().is_err());
+        }
+
+        #[tokio::test]
+        async fn notices_discord_closing_our_connection() {
+            let (connection, mut discord) = connect().await;
+            read_frame(&mut discord).await;
+            let (_config, _presence, served) = serve(connection);
+            read_activity(&mut discord).await;
+
+            let payload = serde_json::to_vec(&json!({ "code": 1000 })).unwrap();
+            let mut frame = OP_CLOSE.to_le_bytes().to_vec();
+            frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+            frame.extend_from_slice(&payload);
+            discord.write_all(&frame).await.unwrap();
+
+            assert!(served.await.unwrap().is_err());
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod ipc {
+    use super::*;
+
+    pub(super) async fn run(
+        _config: watch::Receiver<DiscordConfig>,
+        _presence: watch::Receiver<Option<Presence>>,
+    ) {
+        warn!("Discord presence is only supported on unix");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn discord() -> DiscordPresence {
+        DiscordPresence {
+            config: watch::channel(DiscordConfig::default()).0,
+            presence: watch::channel(None).0,
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    fn presence(title: &str) -> Presence {
+        Presence {
+            source: MediaSource::SoundCloud,
+            title: title.to_string(),
+            artist: None,
+            start: None,
+            end: None,
+        }
+    }
+
+    #[test]
+    fn newer_updates_win() {
+        let discord = discord();
+
+        let playing = discord.next_generation();
+        discord.set(playing, Some(presence("First")));
+        assert_eq!(*discord.presence.borrow(), Some(presence("First")));
+
+        // a pause clears our presence while the next track's position is still on its way
+        let next = discord.next_generation();
+        let paused = discord.next_generation();
+        discord.set(paused, None);
+        discord.set(next, Some(presence("Second")));
+
+        assert_eq!(*discord.presence.borrow(), None);
+    }
+
+    #[test]
+    fn activity_falls_back_to_source_name() {
+        let activity = presence("Song").activity();
+
+        assert_eq!(activity["type"], 2);
+        assert_eq!(activity["details"], "Song");
+        assert_eq!(activity["state"], "SoundCloud");
+        assert_eq!(activity["assets"]["large_text"], "SoundCloud");
+        assert!(activity.get("timestamps").is_none());
+    }
+
+    #[test]
+    fn truncates_to_discord_limits() {
+        assert_eq!(truncate("A"), "A ");
+        assert_eq!(truncate("Song"), "Song");
+        assert_eq!(truncate(&"é".repeat(200)).chars().count(), MAX_TEXT_LENGTH);
+    }
+}
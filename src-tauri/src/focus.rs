@@ -0,0 +1,152 @@
+use crate::EnhancedResult;
+use crate::media_sources::MediaSource;
+use crate::state::{
+    AppState, BreakAction, EnhancerAppStateManager, EnhancerAppStateManagerEmitter, FocusConfig,
+    FocusPhase,
+};
+use crate::webview_manager;
+use log::info;
+use std::time::{Duration, Instant};
+use tauri::AppHandle;
+use tauri_plugin_store::StoreExt;
+use tokio::time;
+
+const STORE_PATH: &str = "medyia-focus.json";
+const HISTORY_KEY: &str = "history";
+const MAX_HISTORY: usize = 500;
+
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+const FOCUS_SOURCE: MediaSource = MediaSource::BrainFm;
+
+pub fn start_focus_monitor(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut interval = time::interval(TICK_INTERVAL);
+        loop {
+            interval.tick().await;
+            tick(&app).await.log_error();
+        }
+    });
+}
+
+pub fn restore_focus_history(app: &AppHandle) -> anyhow::Result<()> {
+    let store = app.store(STORE_PATH)?;
+
+    if let Some(history) = store.get(HISTORY_KEY) {
+        let history = serde_json::from_value(history)?;
+        app.app_state_mut(|state| {
+            state.set_focus_history(history);
+            Ok(())
+        })?;
+    }
+
+    Ok(())
+}
+
+pub async fn start_focus_session(app: &AppHandle, config: FocusConfig) -> anyhow::Result<()> {
+    config.validate()?;
+    info!("Starting focus session: {config:?}");
+
+    if let Some(replaced) = app.app_state_mut(|state| Ok(state.start_focus_session(config)))? {
+        info!("Replaced focus session: {replaced:?}");
+        save_history(app)?;
+    }
+
+    enter_phase(app, FocusPhase::Work).await
+}
+
+pub fn stop_focus_session(app: &AppHandle) -> anyhow::Result<()> {
+    if let Some(record) = app.app_state_mut(|state| Ok(state.stop_focus_session()))? {
+        info!("Stopped focus session: {record:?}");
+        save_history(app)?;
+    }
+
+    Ok(())
+}
+
+async fn tick(app: &AppHandle) -> anyhow::Result<()> {
+    if !app.app_state(AppState::has_focus_session) {
+        return Ok(());
+    }
+
+    let entered = app.app_state_mut(|state| Ok(state.tick_focus_session(Instant::now())))?;
+    if let Some(phase) = entered {
+        enter_phase(app, phase).await?;
+    }
+
+    Ok(())
+}
+
+async fn enter_phase(app: &AppHandle, phase: FocusPhase) -> anyhow::Result<()> {
+    let Some(config) = app.app_state(AppState::focus_config) else {
+        return Ok(());
+    };
+
+    info!("Entering focus phase: {phase:?}");
+
+    match (phase, config.break_action) {
+        (FocusPhase::Work, _) => {
+            app.app_state_mut(|state| {
+                state.hide_blocked_sources()?;
+                state.pause_all_except(FOCUS_SOURCE)
+            })?;
+            webview_manager::play_source(app, FOCUS_SOURCE, None).await
+        }
+        (FocusPhase::Break, BreakAction::Pause) => app.app_state_mut(|state| state.pause_all()),
+        (FocusPhase::Break, BreakAction::SwitchTo { source }) => {
+            app.app_state_mut(|state| state.pause_all())?;
+            webview_manager::play_source(app, source, None).await
+        }
+    }
+}
+
+fn save_history(app: &AppHandle) -> anyhow::Result<()> {
+    let history = app.app_state(|state| {
+        let history = state.focus_history();
+        serde_json::to_value(&history[history.len().saturating_sub(MAX_HISTORY)..])
+    })?;
+
+    let store = app.store(STORE_PATH)?;
+    store.set(HISTORY_KEY, history);
+    store.save()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    const REMOTE_CAPABILITY: &str = include_str!("../capabilities/remote.json");
+
+    /// Our capability patterns only ever end in a wildcard
+    fn matches_any(patterns: &Value, value: &str) -> bool {
+        patterns.as_array().unwrap().iter().any(|pattern| {
+            let pattern = pattern.as_str().unwrap();
+            match pattern.strip_suffix('*') {
+                Some(prefix) => value.starts_with(prefix),
+                None => value == pattern,
+            }
+        })
+    }
+
+    #[test]
+    fn focus_sources_can_reply_to_play() {
+        let capability: Value = serde_json::from_str(REMOTE_CAPABILITY).unwrap();
+
+        // work plays our focus source and breaks can switch to any other
+        for source in [FOCUS_SOURCE].iter().chain(MediaSource::ALL) {
+            let label = source.next_tab_key();
+            assert!(
+                matches_any(&capability["webviews"], &label),
+                "{label} can't emit its rpc replies"
+            );
+
+            let url = format!("{}/", source.default_url());
+            assert!(
+                matches_any(&capability["remote"]["urls"], &url),
+                "{url} can't emit its rpc replies"
+            );
+        }
+    }
+}
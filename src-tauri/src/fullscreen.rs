@@ -0,0 +1,113 @@
+use crate::controls::ExitFullscreen;
+use crate::state::{EnhancerAppStateManager, EnhancerAppStateManagerEmitter};
+use crate::{EnhancedManager, EnhancedResult, rpc, webview_manager};
+use log::{debug, error, info};
+use serde::Deserialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tauri::{AppHandle, Listener, Runtime, Window};
+
+const FULLSCREEN_EVENT: &str = "tab-fullscreen";
+
+/// Our window was already fullscreen before a tab took it over, so we leave it that way
+static WINDOW_WAS_FULLSCREEN: AtomicBool = AtomicBool::new(false);
+/// Our window has finished going fullscreen for the tab, resizes before that are still us getting there
+static WINDOW_ENTERED_FULLSCREEN: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Deserialize, Clone)]
+struct FullscreenEvent {
+    label: String,
+    fullscreen: bool,
+}
+
+pub fn setup_fullscreen_listener(app: &AppHandle) {
+    let app_handle = app.clone();
+    app.listen(FULLSCREEN_EVENT, move |event| {
+        let payload = event.payload();
+        debug!("Received tab-fullscreen event: {}", payload);
+        match serde_json::from_str::<FullscreenEvent>(payload) {
+            Ok(evt) => handle_fullscreen_event(&app_handle, evt).log_error(),
+            Err(e) => error!(
+                "Failed to parse fullscreen event: {} — payload: {}",
+                e, payload
+            ),
+        }
+    });
+}
+
+/// Whether the user had our window fullscreen before any tab took it over
+pub fn window_was_fullscreen() -> bool {
+    WINDOW_WAS_FULLSCREEN.load(Ordering::Relaxed)
+}
+
+/// Our fullscreen tab has gone, so put `window` back how the user had it
+pub fn restore_window<R: Runtime>(window: &Window<R>) -> tauri::Result<()> {
+    WINDOW_ENTERED_FULLSCREEN.store(false, Ordering::Relaxed);
+    if !window_was_fullscreen() {
+        window.set_fullscreen(false)?;
+    }
+
+    Ok(())
+}
+
+/// Our window left fullscreen by itself, e.g. through its own controls, so take the page out too
+pub fn on_window_resized(app: &AppHandle) {
+    let Some(key) = app.app_state(|state| state.fullscreen_tab_key()) else {
+        return;
+    };
+    if app.main_window().is_fullscreen().unwrap_or(true) {
+        WINDOW_ENTERED_FULLSCREEN.store(true, Ordering::Relaxed);
+        return;
+    }
+    if !WINDOW_ENTERED_FULLSCREEN.swap(false, Ordering::Relaxed) {
+        return;
+    }
+
+    info!("Our window left fullscreen, restoring {key}");
+    app.app_state_mut(|state| {
+        state.set_fullscreen_tab(None);
+        Ok(())
+    })
+    .log_error();
+
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        rpc::call(&app, &key, ExitFullscreen).await.log_warn();
+    });
+}
+
+fn handle_fullscreen_event(app: &AppHandle, event: FullscreenEvent) -> anyhow::Result<()> {
+    // a floating tab's video already fills its own window
+    if app.app_state(|state| state.detached_tab_key()).as_deref() == Some(event.label.as_str()) {
+        debug!("Ignoring fullscreen from {}, it's floating", event.label);
+        return Ok(());
+    }
+
+    let window = app.main_window();
+    let current = app.app_state(|state| state.fullscreen_tab_key());
+
+    if event.fullscreen {
+        info!("{} went fullscreen", event.label);
+        if current.is_none() {
+            let was_fullscreen = window.is_fullscreen()?;
+            WINDOW_WAS_FULLSCREEN.store(was_fullscreen, Ordering::Relaxed);
+            WINDOW_ENTERED_FULLSCREEN.store(was_fullscreen, Ordering::Relaxed);
+        }
+        app.app_state_mut(|state| {
+            state.set_fullscreen_tab(Some(&event.label));
+            Ok(())
+        })?;
+        window.set_fullscreen(true)?;
+    } else if current.as_deref() == Some(event.label.as_str()) {
+        info!("{} left fullscreen", event.label);
+        app.app_state_mut(|state| {
+            state.set_fullscreen_tab(None);
+            Ok(())
+        })?;
+        restore_window(&window)?;
+    } else {
+        return Ok(());
+    }
+
+    // the tab covers our shell while it's fullscreen
+    webview_manager::relayout(app)
+}
@@ -0,0 +1,1036 @@
+use crate::EnhancedResult;
+use crate::media_sources::MediaSource;
+use crate::playback::PlaybackEvent;
+use crate::state::{AppState, EnhancerAppStateManager, TabKey};
+use anyhow::anyhow;
+use chrono::{DateTime, Local, NaiveDate, TimeDelta, Utc};
+use log::{debug, info};
+use rusqlite::{Connection, Row, params};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+use tauri_plugin_store::StoreExt;
+use tokio::time;
+
+const DB_FILE: &str = "history.sqlite3";
+const STORE_PATH: &str = "medyia-history.json";
+const RETENTION_KEY: &str = "retentionDays";
+
+/// How often we flush the listened time of playing tracks
+const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+/// How often we drop entries older than our retention
+const PRUNE_INTERVAL: Duration = Duration::from_hours(1);
+/// The same track at the same url coming back this soon after it stopped (page reloads) extends its entry
+const RELOAD_WINDOW: Duration = Duration::from_secs(5);
+
+/// Each entry upgrades the schema by one `user_version`
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE plays (
+        id INTEGER PRIMARY KEY,
+        source TEXT NOT NULL,
+        url TEXT,
+        title TEXT NOT NULL,
+        artist TEXT,
+        started_at INTEGER NOT NULL,
+        listened_ms INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE INDEX plays_started_at ON plays (started_at);",
+    // listening totals per local day so our stats don't need to scan every play
+    "CREATE TABLE daily_listening (
+        day TEXT NOT NULL,
+        source TEXT NOT NULL,
+        artist TEXT NOT NULL DEFAULT '',
+        title TEXT NOT NULL,
+        listened_ms INTEGER NOT NULL DEFAULT 0,
+        plays INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (day, source, artist, title)
+    );
+    INSERT INTO daily_listening (day, source, artist, title, listened_ms, plays)
+        SELECT date(started_at / 1000, 'unixepoch', 'localtime'), source, coalesce(artist, ''), title,
+               sum(listened_ms), count(*)
+        FROM plays
+        GROUP BY 1, 2, 3, 4;",
+];
+
+pub struct History {
+    inner: Mutex<HistoryInner>,
+}
+
+struct HistoryInner {
+    connection: Connection,
+    tracks: HashMap<TabKey, CurrentTrack>,
+    /// The track each tab just moved on from and when, in case it comes back after a reload
+    stopped: HashMap<TabKey, (CurrentTrack, Instant)>,
+}
+
+/// The track a tab is on and how much of it we've heard so far
+struct CurrentTrack {
+    id: i64,
+    source: MediaSource,
+    title: String,
+    artist: Option<String>,
+    url: Option<String>,
+    /// Playing it again from here is a new play
+    ended: bool,
+    playing_since: Option<Instant>,
+    listened: Duration,
+    /// How much of `listened` is already in the database
+    saved: Duration,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub id: i64,
+    pub source: MediaSource,
+    pub url: Option<String>,
+    pub title: String,
+    pub artist: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub listened_secs: u64,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryQuery {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub source: Option<MediaSource>,
+    /// Matched against the title and artist
+    pub text: Option<String>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+pub fn setup_history(app: &AppHandle) -> anyhow::Result<()> {
+    let dir = app.path().app_data_dir()?;
+    fs::create_dir_all(&dir)?;
+
+    let history = History::open(&dir.join(DB_FILE))?;
+    app.manage(history);
+    prune(app)?;
+
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let mut interval = time::interval(FLUSH_INTERVAL);
+        let mut last_prune = Instant::now();
+        loop {
+            interval.tick().await;
+            let playing = app.app_state(AppState::playing_by_tab);
+            app.state::<History>().flush(&playing).log_error();
+            if last_prune.elapsed() >= PRUNE_INTERVAL {
+                last_prune = Instant::now();
+                prune(&app).log_error();
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Record what `event`'s tab is playing
+pub fn record_playback(app: &AppHandle, event: &PlaybackEvent) -> anyhow::Result<()> {
+    let Some(source) = app.app_state(|state| state.tab(&event.label).map(|t| t.source)) else {
+        return Ok(());
+    };
+
+    // we've already logged why our history isn't available
+    match app.try_state::<History>() {
+        Some(history) => history.record(source, event, Instant::now()),
+        None => Ok(()),
+    }
+}
+
+pub fn query(app: &AppHandle, query: &HistoryQuery) -> anyhow::Result<Vec<HistoryEntry>> {
+    app.try_state::<History>()
+        .ok_or_else(|| anyhow!("Listening history isn't available"))?
+        .query(query)
+}
+
+pub fn export(
+    app: &AppHandle,
+    query: &HistoryQuery,
+    format: ExportFormat,
+    path: &Path,
+) -> anyhow::Result<()> {
+    let entries = self::query(app, query)?;
+    fs::write(path, format.write(&entries)?)?;
+
+    info!("Exported {} history entries to {path:?}", entries.len());
+
+    Ok(())
+}
+
+pub fn retention_days(app: &AppHandle) -> anyhow::Result<Option<u32>> {
+    let store = app.store(STORE_PATH)?;
+
+    Ok(store
+        .get(RETENTION_KEY)
+        .map(serde_json::from_value)
+        .transpose()?
+        .flatten())
+}
+
+pub fn set_retention_days(app: &AppHandle, days: Option<u32>) -> anyhow::Result<()> {
+    let store = app.store(STORE_PATH)?;
+    store.set(RETENTION_KEY, serde_json::to_value(days)?);
+    store.save()?;
+
+    prune(app)
+}
+
+fn prune(app: &AppHandle) -> anyhow::Result<()> {
+    if let Some(days) = retention_days(app)? {
+        let cutoff = Utc::now() - TimeDelta::days(i64::from(days));
+        if let Some(history) = app.try_state::<History>() {
+            let removed = history.remove_before(cutoff)?;
+            if removed > 0 {
+                info!("Removed {removed} history entries from before {cutoff}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+impl History {
+    fn open(path: &Path) -> anyhow::Result<Self> {
+        Self::with_database(Connection::open(path)?)
+    }
+
+    fn with_database(mut connection: Connection) -> anyhow::Result<Self> {
+        migrate(&mut connection)?;
+
+        Ok(Self {
+            inner: Mutex::new(HistoryInner {
+                connection,
+                tracks: HashMap::new(),
+                stopped: HashMap::new(),
+            }),
+        })
+    }
+
+    fn record(
+        &self,
+        source: MediaSource,
+        event: &PlaybackEvent,
+        now: Instant,
+    ) -> anyhow::Result<()> {
+        let Some(title) = event.title.as_deref().filter(|t| !t.is_empty()) else {
+            return Ok(());
+        };
+
+        let mut inner = self.inner.lock().unwrap();
+        let HistoryInner {
+            connection,
+            tracks,
+            stopped,
+        } = &mut *inner;
+
+        let changed = tracks.get(&event.label).is_none_or(|t| {
+            t.title != title || t.artist != event.artist || (t.ended && event.playing)
+        });
+        if changed {
+            if let Some(mut previous) = tracks.remove(&event.label) {
+                previous.pause(now);
+                previous.save(connection)?;
+                stopped.insert(event.label.clone(), (previous, now));
+            }
+
+            // we only start tracking once the track actually plays
+            if !event.playing {
+                return Ok(());
+            }
+
+            let track = match stopped.remove(&event.label) {
+                Some((mut previous, stopped_at))
+                    if previous.reloaded(title, event, stopped_at, now) =>
+                {
+                    debug!("Continuing history entry {}: {title}", previous.id);
+                    previous.playing_since = Some(now);
+                    previous
+                }
+                _ => CurrentTrack::start(connection, source, event, title, now)?,
+            };
+            tracks.insert(event.label.clone(), track);
+        } else if let Some(track) = tracks.get_mut(&event.label) {
+            if event.playing {
+                track.playing_since.get_or_insert(now);
+            } else {
+                track.ended = event.ended;
+                track.pause(now);
+                track.save(connection)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write the listened time of everything that's playing, closed tabs won't tell us they stopped
+    fn flush(&self, playing: &HashMap<TabKey, bool>) -> anyhow::Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        let HistoryInner {
+            connection,
+            tracks,
+            stopped,
+        } = &mut *inner;
+        let now = Instant::now();
+
+        stopped.retain(|_, (_, stopped_at)| now.duration_since(*stopped_at) <= RELOAD_WINDOW);
+
+        let mut result = Ok(());
+        tracks.retain(|label, track| {
+            let still_playing = playing.get(label).copied();
+            if track.playing_since.is_some() {
+                track.pause(now);
+                if still_playing == Some(true) {
+                    track.playing_since = Some(now);
+                }
+                if let Err(e) = track.save(connection) {
+                    result = Err(e);
+                }
+            }
+
+            still_playing.is_some()
+        });
+
+        result
+    }
+
+    fn query(&self, query: &HistoryQuery) -> anyhow::Result<Vec<HistoryEntry>> {
+        let inner = self.inner.lock().unwrap();
+        let mut statement = inner.connection.prepare(
+            "SELECT id, source, url, title, artist, started_at, listened_ms FROM plays
+             WHERE (?1 IS NULL OR started_at >= ?1)
+               AND (?2 IS NULL OR started_at < ?2)
+               AND (?3 IS NULL OR source = ?3)
+               AND (?4 IS NULL OR title LIKE ?4 ESCAPE '\\' OR artist LIKE ?4 ESCAPE '\\')
+             ORDER BY started_at DESC
+             LIMIT ?5 OFFSET ?6",
+        )?;
+
+        let entries = statement
+            .query_map(
+                params![
+                    query.from.map(|d| d.timestamp_millis()),
+                    query.to.map(|d| d.timestamp_millis()),
+                    query.source.map(MediaSource::source_id),
+                    query
+                        .text
+                        .as_deref()
+                        .map(|t| format!("%{}%", escape_like(t))),
+                    query.limit.map_or(-1, i64::from),
+                    query.offset.unwrap_or(0),
+                ],
+                HistoryEntry::from_row,
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(entries)
+    }
+
+    fn remove_before(&self, cutoff: DateTime<Utc>) -> anyhow::Result<usize> {
+        let inner = self.inner.lock().unwrap();
+        inner.connection.execute(
+            "DELETE FROM daily_listening WHERE day < ?1",
+            params![cutoff.with_timezone(&Local).date_naive().to_string()],
+        )?;
+
+        Ok(inner.connection.execute(
+            "DELETE FROM plays WHERE started_at < ?1",
+            params![cutoff.timestamp_millis()],
+        )?)
+    }
+
+    /// Run `f` against our database, for reports that read it directly
+    pub(crate) fn with_connection<T>(&self, f: impl FnOnce(&Connection) -> T) -> T {
+        f(&self.inner.lock().unwrap().connection)
+    }
+}
+
+impl CurrentTrack {
+    fn start(
+        connection: &Connection,
+        source: MediaSource,
+        event: &PlaybackEvent,
+        title: &str,
+        now: Instant,
+    ) -> anyhow::Result<Self> {
+        connection.execute(
+            "INSERT INTO plays (source, url, title, artist, started_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                source.source_id(),
+                event.url,
+                title,
+                event.artist,
+                Utc::now().timestamp_millis()
+            ],
+        )?;
+        let id = connection.last_insert_rowid();
+        add_daily_listening(
+            connection,
+            Local::now().date_naive(),
+            source,
+            title,
+            event.artist.as_deref(),
+            Duration::ZERO,
+            1,
+        )?;
+        debug!("New history entry {id}: {title}");
+
+        Ok(Self {
+            id,
+            source,
+            title: title.to_string(),
+            artist: event.artist.clone(),
+            url: event.url.clone(),
+            ended: false,
+            playing_since: Some(now),
+            listened: Duration::ZERO,
+            saved: Duration::ZERO,
+        })
+    }
+
+    /// Whether `event` is this track again because the page reloaded, rather than a replay
+    fn reloaded(
+        &self,
+        title: &str,
+        event: &PlaybackEvent,
+        stopped_at: Instant,
+        now: Instant,
+    ) -> bool {
+        !self.ended
+            && self.title == title
+            && self.artist == event.artist
+            && self.url == event.url
+            && now.saturating_duration_since(stopped_at) <= RELOAD_WINDOW
+    }
+
+    fn pause(&mut self, now: Instant) {
+        if let Some(since) = self.playing_since.take() {
+            self.listened += now.saturating_duration_since(since);
+        }
+    }
+
+    fn save(&mut self, connection: &Connection) -> anyhow::Result<()> {
+        connection.execute(
+            "UPDATE plays SET listened_ms = ?1 WHERE id = ?2",
+            params![self.listened.as_millis() as u64, self.id],
+        )?;
+
+        let added = self.listened.saturating_sub(self.saved);
+        if !added.is_zero() {
+            add_daily_listening(
+                connection,
+                Local::now().date_naive(),
+                self.source,
+                &self.title,
+                self.artist.as_deref(),
+                added,
+                0,
+            )?;
+        }
+        self.saved = self.listened;
+
+        Ok(())
+    }
+}
+
+impl HistoryEntry {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let started_at: i64 = row.get(5)?;
+        let listened_ms: u64 = row.get(6)?;
+
+        Ok(Self {
+            id: row.get(0)?,
+            source: source_column(row, 1)?,
+            url: row.get(2)?,
+            title: row.get(3)?,
+            artist: row.get(4)?,
+            started_at: DateTime::from_timestamp_millis(started_at).unwrap_or_default(),
+            listened_secs: listened_ms / 1000,
+        })
+    }
+}
+
+/// Sources are stored by their `source_id`
+pub(crate) fn source_column(row: &Row, index: usize) -> rusqlite::Result<MediaSource> {
+    let source: String = row.get(index)?;
+
+    serde_json::from_value(Value::String(source)).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, e.into())
+    })
+}
+
+pub(crate) fn migrate(connection: &mut Connection) -> anyhow::Result<()> {
+    let version: usize = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version > MIGRATIONS.len() {
+        return Err(anyhow!(
+            "History schema version {version} is newer than we support ({})",
+            MIGRATIONS.len()
+        ));
+    }
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", i + 1)?;
+        transaction.commit()?;
+
+        info!("Migrated history schema to version {}", i + 1);
+    }
+
+    Ok(())
+}
+
+/// Listening counts towards the day we heard it, not the day the track started
+fn add_daily_listening(
+    connection: &Connection,
+    day: NaiveDate,
+    source: MediaSource,
+    title: &str,
+    artist: Option<&str>,
+    listened: Duration,
+    plays: u32,
+) -> anyhow::Result<()> {
+    connection.execute(
+        "INSERT INTO daily_listening (day, source, artist, title, listened_ms, plays)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+         ON CONFLICT (day, source, artist, title) DO UPDATE SET
+           listened_ms = listened_ms + excluded.listened_ms,
+           plays = plays + excluded.plays",
+        params![
+            day.to_string(),
+            source.source_id(),
+            artist.unwrap_or_default(),
+            title,
+            listened.as_millis() as u64,
+            plays
+        ],
+    )?;
+
+    Ok(())
+}
+
+/// `LIKE` treats `%` and `_` as wildcards, we're looking for the text itself
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+impl ExportFormat {
+    fn write(self, entries: &[HistoryEntry]) -> anyhow::Result<String> {
+        Ok(match self {
+            ExportFormat::Json => serde_json::to_string_pretty(entries)?,
+            ExportFormat::Csv => to_csv(entries),
+        })
+    }
+}
+
+fn to_csv(entries: &[HistoryEntry]) -> String {
+    fn field(value: &str) -> String {
+        if value.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    }
+
+    let mut csv = String::from("started_at,source,title,artist,url,listened_secs\n");
+    for entry in entries {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{}\n",
+            entry.started_at.to_rfc3339(),
+            field(entry.source.name()),
+            field(&entry.title),
+            field(entry.artist.as_deref().unwrap_or_default()),
+            field(entry.url.as_deref().unwrap_or_default()),
+            entry.listened_secs
+        ));
+    }
+
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    type DailyRow = (String, String, String, String, u64, u32);
+
+    fn open() -> Connection {
+        let mut connection = Connection::open_in_memory().unwrap();
+        migrate(&mut connection).unwrap();
+        connection
+    }
+
+    fn day(day: &str) -> NaiveDate {
+        day.parse().unwrap()
+    }
+
+    fn daily_listening(connection: &Connection) -> Vec<DailyRow> {
+        connection
+            .prepare(
+                "SELECT day, source, artist, title, listened_ms, plays FROM daily_listening
+                 ORDER BY day, source, artist, title",
+            )
+            .unwrap()
+            .query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                ))
+            })
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    fn row(
+        day: &str,
+        source: MediaSource,
+        artist: &str,
+        title: &str,
+        listened_ms: u64,
+        plays: u32,
+    ) -> DailyRow {
+        (
+            day.to_string(),
+            source.source_id(),
+            artist.to_string(),
+            title.to_string(),
+            listened_ms,
+            plays,
+        )
+    }
+
+    #[test]
+    fn daily_listening_adds_up_per_day_source_and_track() {
+        let connection = open();
+        let add = |on, source, title, artist, secs, plays| {
+            add_daily_listening(
+                &connection,
+                day(on),
+                source,
+                title,
+                artist,
+                Duration::from_secs(secs),
+                plays,
+            )
+            .unwrap();
+        };
+
+        add(
+            "2024-03-01",
+            MediaSource::YouTube,
+            "Title",
+            Some("Artist"),
+            0,
+            1,
+        );
+        add(
+            "2024-03-01",
+            MediaSource::YouTube,
+            "Title",
+            Some("Artist"),
+            30,
+            0,
+        );
+        add(
+            "2024-03-01",
+            MediaSource::YouTube,
+            "Title",
+            Some("Artist"),
+            45,
+            0,
+        );
+        add("2024-03-01", MediaSource::YouTube, "Other", None, 10, 1);
+        add(
+            "2024-03-01",
+            MediaSource::SoundCloud,
+            "Title",
+            Some("Artist"),
+            5,
+            1,
+        );
+        add(
+            "2024-03-02",
+            MediaSource::YouTube,
+            "Title",
+            Some("Artist"),
+            20,
+            0,
+        );
+
+        assert_eq!(
+            daily_listening(&connection),
+            [
+                row(
+                    "2024-03-01",
+                    MediaSource::SoundCloud,
+                    "Artist",
+                    "Title",
+                    5000,
+                    1
+                ),
+                row("2024-03-01", MediaSource::YouTube, "", "Other", 10000, 1),
+                row(
+                    "2024-03-01",
+                    MediaSource::YouTube,
+                    "Artist",
+                    "Title",
+                    75000,
+                    1
+                ),
+                row(
+                    "2024-03-02",
+                    MediaSource::YouTube,
+                    "Artist",
+                    "Title",
+                    20000,
+                    0
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn saving_only_adds_what_we_heard_since_last_time() {
+        let connection = open();
+        connection
+            .execute(
+                "INSERT INTO plays (id, source, title, artist, started_at) VALUES (1, ?1, 'Title', 'Artist', 0)",
+                params![MediaSource::YouTube.source_id()],
+            )
+            .unwrap();
+        let mut track = CurrentTrack {
+            id: 1,
+            source: MediaSource::YouTube,
+            title: "Title".to_string(),
+            artist: Some("Artist".to_string()),
+            url: None,
+            ended: false,
+            playing_since: None,
+            listened: Duration::from_secs(90),
+            saved: Duration::from_secs(60),
+        };
+        let today = Local::now().date_naive().to_string();
+        let listened_ms = |connection: &Connection| -> u64 {
+            connection
+                .query_row("SELECT listened_ms FROM plays WHERE id = 1", [], |row| {
+                    row.get(0)
+                })
+                .unwrap()
+        };
+
+        track.save(&connection).unwrap();
+        track.save(&connection).unwrap();
+        assert_eq!(listened_ms(&connection), 90000);
+        assert_eq!(
+            daily_listening(&connection),
+            [row(
+                &today,
+                MediaSource::YouTube,
+                "Artist",
+                "Title",
+                30000,
+                0
+            )]
+        );
+
+        track.listened += Duration::from_secs(15);
+        track.save(&connection).unwrap();
+        assert_eq!(listened_ms(&connection), 105000);
+        assert_eq!(
+            daily_listening(&connection),
+            [row(
+                &today,
+                MediaSource::YouTube,
+                "Artist",
+                "Title",
+                45000,
+                0
+            )]
+        );
+    }
+
+    #[test]
+    fn migrating_fills_daily_listening_from_existing_plays() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch(MIGRATIONS[0]).unwrap();
+        connection.pragma_update(None, "user_version", 1).unwrap();
+
+        let at = |day, hour| {
+            Local
+                .with_ymd_and_hms(2024, 3, day, hour, 0, 0)
+                .unwrap()
+                .timestamp_millis()
+        };
+        let plays = [
+            (
+                MediaSource::YouTube,
+                "Title",
+                Some("Artist"),
+                at(1, 9),
+                60000,
+            ),
+            (
+                MediaSource::YouTube,
+                "Title",
+                Some("Artist"),
+                at(1, 20),
+                30000,
+            ),
+            (MediaSource::YouTube, "Other", None, at(1, 10), 5000),
+            (
+                MediaSource::SoundCloud,
+                "Title",
+                Some("Artist"),
+                at(2, 12),
+                10000,
+            ),
+        ];
+        for (source, title, artist, started_at, listened_ms) in plays {
+            connection
+                .execute(
+                    "INSERT INTO plays (source, title, artist, started_at, listened_ms) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![source.source_id(), title, artist, started_at, listened_ms],
+                )
+                .unwrap();
+        }
+
+        migrate(&mut connection).unwrap();
+
+        assert_eq!(
+            daily_listening(&connection),
+            [
+                row("2024-03-01", MediaSource::YouTube, "", "Other", 5000, 1),
+                row(
+                    "2024-03-01",
+                    MediaSource::YouTube,
+                    "Artist",
+                    "Title",
+                    90000,
+                    2
+                ),
+                row(
+                    "2024-03-02",
+                    MediaSource::SoundCloud,
+                    "Artist",
+                    "Title",
+                    10000,
+                    1
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn refuses_newer_schemas() {
+        let mut connection = open();
+        connection
+            .pragma_update(None, "user_version", MIGRATIONS.len() + 1)
+            .unwrap();
+
+        assert!(migrate(&mut connection).is_err());
+    }
+
+    fn event(title: &str, url: &str, playing: bool, ended: bool) -> PlaybackEvent {
+        PlaybackEvent {
+            label: "YouTube-abcdef".to_string(),
+            playing,
+            ended,
+            audible: playing,
+            muted: false,
+            title: Some(title.to_string()),
+            artist: Some("Artist".to_string()),
+            artwork_url: None,
+            url: Some(url.to_string()),
+            duration: None,
+        }
+    }
+
+    fn plays(history: &History) -> Vec<(String, u64)> {
+        history.with_connection(|connection| {
+            connection
+                .prepare("SELECT title, listened_ms FROM plays ORDER BY id")
+                .unwrap()
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap()
+        })
+    }
+
+    #[test]
+    fn only_reloads_continue_an_entry() {
+        const URL: &str = "https://www.youtube.com/watch?v=1";
+        let cases = [
+            // the page reloaded and briefly showed its own title
+            ("reload", event("YouTube", URL, false, false), 2, URL, 1),
+            ("too late", event("YouTube", URL, false, false), 30, URL, 2),
+            (
+                "other url",
+                event("YouTube", URL, false, false),
+                2,
+                "https://www.youtube.com/watch?v=2",
+                2,
+            ),
+            ("replay", event("Title", URL, false, true), 2, URL, 2),
+        ];
+
+        for (description, stop, gap, url, expected) in cases {
+            let history = History::with_database(Connection::open_in_memory().unwrap()).unwrap();
+            let start = Instant::now();
+            let at = |secs| start + Duration::from_secs(secs);
+            let source = MediaSource::YouTube;
+
+            history
+                .record(source, &event("Title", URL, true, false), at(0))
+                .unwrap();
+            history.record(source, &stop, at(60)).unwrap();
+            history
+                .record(source, &event("Title", url, true, false), at(60 + gap))
+                .unwrap();
+
+            let plays = plays(&history);
+            assert_eq!(plays.len(), expected, "{description}: {plays:?}");
+            assert_eq!(plays[0], ("Title".to_string(), 60000), "{description}");
+        }
+    }
+
+    #[test]
+    fn query_filters() {
+        let history = History::with_database(Connection::open_in_memory().unwrap()).unwrap();
+        let at = |day| Utc.with_ymd_and_hms(2024, 3, day, 12, 0, 0).unwrap();
+        history.with_connection(|connection| {
+            let plays = [
+                (MediaSource::YouTube, "100% Pure", "Artist", 1),
+                (MediaSource::YouTube, "snake_case", "Other", 2),
+                (MediaSource::SoundCloud, "Plain", "100 Percent", 3),
+                (MediaSource::AppleMusic, "Plain Too", "Artist", 4),
+            ];
+            for (source, title, artist, day) in plays {
+                connection
+                    .execute(
+                        "INSERT INTO plays (source, title, artist, started_at) VALUES (?1, ?2, ?3, ?4)",
+                        params![source.source_id(), title, artist, at(day).timestamp_millis()],
+                    )
+                    .unwrap();
+            }
+        });
+
+        let text = |text: &str| HistoryQuery {
+            text: Some(text.to_string()),
+            ..Default::default()
+        };
+        let cases = [
+            (
+                HistoryQuery::default(),
+                vec!["Plain Too", "Plain", "snake_case", "100% Pure"],
+            ),
+            (text("%"), vec!["100% Pure"]),
+            (text("_"), vec!["snake_case"]),
+            (text("100"), vec!["Plain", "100% Pure"]),
+            (text("artist"), vec!["Plain Too", "100% Pure"]),
+            (
+                HistoryQuery {
+                    source: Some(MediaSource::YouTube),
+                    ..Default::default()
+                },
+                vec!["snake_case", "100% Pure"],
+            ),
+            (
+                HistoryQuery {
+                    from: Some(at(2)),
+                    to: Some(at(4)),
+                    ..Default::default()
+                },
+                vec!["Plain", "snake_case"],
+            ),
+            (
+                HistoryQuery {
+                    limit: Some(2),
+                    offset: Some(1),
+                    ..Default::default()
+                },
+                vec!["Plain", "snake_case"],
+            ),
+        ];
+
+        for (query, expected) in cases {
+            let titles: Vec<_> = history
+                .query(&query)
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.title)
+                .collect();
+            assert_eq!(titles, expected, "{query:?}");
+        }
+    }
+
+    #[test]
+    fn export_formats() {
+        let entries = [
+            HistoryEntry {
+                id: 1,
+                source: MediaSource::YouTube,
+                url: Some("https://www.youtube.com/watch?v=1".to_string()),
+                title: "Title, \"Live\"".to_string(),
+                artist: None,
+                started_at: Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap(),
+                listened_secs: 90,
+            },
+            HistoryEntry {
+                id: 2,
+                source: MediaSource::AppleMusic,
+                url: None,
+                title: "Plain".to_string(),
+                artist: Some("Artist".to_string()),
+                started_at: Utc.with_ymd_and_hms(2024, 3, 2, 8, 30, 0).unwrap(),
+                listened_secs: 5,
+            },
+        ];
+
+        assert_eq!(
+            ExportFormat::Csv.write(&entries).unwrap(),
+            "started_at,source,title,artist,url,listened_secs\n\
+             2024-03-01T12:00:00+00:00,YouTube,\"Title, \"\"Live\"\"\",,https://www.youtube.com/watch?v=1,90\n\
+             2024-03-02T08:30:00+00:00,Apple Music,Plain,Artist,,5\n"
+        );
+
+        let json: Value =
+            serde_json::from_str(&ExportFormat::Json.write(&entries).unwrap()).unwrap();
+        assert_eq!(
+            json[0],
+            serde_json::json!({
+                "id": 1,
+                "source": "YouTube",
+                "url": "https://www.youtube.com/watch?v=1",
+                "title": "Title, \"Live\"",
+                "artist": null,
+                "startedAt": "2024-03-01T12:00:00Z",
+                "listenedSecs": 90,
+            })
+        );
+        assert_eq!(json[1]["source"], "AppleMusic");
+    }
+}
@@ -0,0 +1,361 @@
+use crate::media_sources::MediaSource;
+use crate::state::{EnhancerAppStateManager, TabKey, TabKeyRef};
+use anyhow::anyhow;
+use log::{debug, info, warn};
+use once_cell::sync::Lazy;
+use rand::distr::{Alphanumeric, SampleString};
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use std::time::Duration;
+use tauri::AppHandle;
+use tauri_plugin_store::StoreExt;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::Semaphore;
+use tokio::time;
+
+const STORE_PATH: &str = "medyia-hooks.json";
+const HOOKS_KEY: &str = "hooks";
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Hooks past this wait for a running one to finish
+const MAX_CONCURRENT_HOOKS: usize = 4;
+
+static HOOK_PERMITS: Lazy<Semaphore> = Lazy::new(|| Semaphore::new(MAX_CONCURRENT_HOOKS));
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum HookEvent {
+    TrackChanged,
+    Play,
+    Pause,
+    TabOpened,
+    SourceSwitched,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Hook {
+    pub id: String,
+    #[serde(flatten)]
+    pub definition: HookDefinition,
+    pub enabled: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HookDefinition {
+    pub name: String,
+    pub event: HookEvent,
+    /// Run through the shell, with our event in `MEDYIA_*` variables and as JSON on stdin
+    pub command: String,
+    pub timeout_secs: Option<u64>,
+}
+
+/// What we tell hooks about an event
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct HookPayload {
+    event: HookEvent,
+    tab: TabKey,
+    source: MediaSource,
+    url: String,
+    title: Option<String>,
+    artist: Option<String>,
+    playing: bool,
+}
+
+/// Run every hook registered for `event`, describing the tab with `key`
+pub fn fire(app: &AppHandle, event: HookEvent, key: TabKeyRef) {
+    let hooks = match get_hooks(app) {
+        Ok(hooks) => hooks,
+        Err(e) => {
+            warn!("Failed to load our hooks: {e}");
+            return;
+        }
+    };
+    let hooks = hooks
+        .into_iter()
+        .filter(|h| h.enabled && h.definition.event == event)
+        .collect::<Vec<_>>();
+    if hooks.is_empty() {
+        return;
+    }
+
+    let Some(payload) = app.app_state(|state| {
+        state.tab(key).map(|tab| HookPayload {
+            event,
+            tab: tab.key.clone(),
+            source: tab.source,
+            url: tab.url.to_string(),
+            title: tab.title.clone(),
+            artist: tab.artist.clone(),
+            playing: tab.is_playing(),
+        })
+    }) else {
+        return;
+    };
+
+    for hook in hooks {
+        let payload = payload.clone();
+        tauri::async_runtime::spawn(async move {
+            if let Err(e) = run(&hook, &payload).await {
+                warn!("Hook {} ({}) failed: {e}", hook.id, hook.definition.name);
+            }
+        });
+    }
+}
+
+pub fn get_hooks(app: &AppHandle) -> anyhow::Result<Vec<Hook>> {
+    let store = app.store(STORE_PATH)?;
+
+    match store.get(HOOKS_KEY) {
+        Some(hooks) => Ok(serde_json::from_value(hooks)?),
+        None => Ok(Vec::new()),
+    }
+}
+
+pub fn add_hook(app: &AppHandle, definition: HookDefinition) -> anyhow::Result<Hook> {
+    if definition.command.trim().is_empty() {
+        return Err(anyhow!("Hooks need a command"));
+    }
+
+    let hook = Hook {
+        id: Alphanumeric.sample_string(&mut rand::rng(), 8),
+        definition,
+        enabled: true,
+    };
+
+    let mut hooks = get_hooks(app)?;
+    hooks.push(hook.clone());
+    save_hooks(app, &hooks)?;
+
+    info!("Added hook {}: {}", hook.id, hook.definition.name);
+
+    Ok(hook)
+}
+
+pub fn remove_hook(app: &AppHandle, id: &str) -> anyhow::Result<()> {
+    let mut hooks = get_hooks(app)?;
+    hooks.retain(|h| h.id != id);
+    save_hooks(app, &hooks)
+}
+
+pub fn set_hook_enabled(app: &AppHandle, id: &str, enabled: bool) -> anyhow::Result<()> {
+    let mut hooks = get_hooks(app)?;
+    let hook = hooks
+        .iter_mut()
+        .find(|h| h.id == id)
+        .ok_or_else(|| anyhow!("No hook found for {id}"))?;
+    hook.enabled = enabled;
+
+    save_hooks(app, &hooks)
+}
+
+fn save_hooks(app: &AppHandle, hooks: &[Hook]) -> anyhow::Result<()> {
+    let store = app.store(STORE_PATH)?;
+    store.set(HOOKS_KEY, serde_json::to_value(hooks)?);
+    store.save()?;
+
+    Ok(())
+}
+
+async fn run(hook: &Hook, payload: &HookPayload) -> anyhow::Result<()> {
+    let _permit = HOOK_PERMITS.acquire().await?;
+    let timeout = hook
+        .definition
+        .timeout_secs
+        .map_or(DEFAULT_TIMEOUT, Duration::from_secs);
+    debug!("Running hook {} for {:?}", hook.id, payload.event);
+
+    let mut command = shell(&hook.definition.command);
+    command
+        .env(
+            "MEDYIA_EVENT",
+            serde_json::to_value(payload.event)?
+                .as_str()
+                .unwrap_or_default(),
+        )
+        .env("MEDYIA_TAB", &payload.tab)
+        .env("MEDYIA_SOURCE", payload.source.source_id())
+        .env("MEDYIA_URL", &payload.url)
+        .env("MEDYIA_TITLE", payload.title.as_deref().unwrap_or_default())
+        .env(
+            "MEDYIA_ARTIST",
+            payload.artist.as_deref().unwrap_or_default(),
+        )
+        .env("MEDYIA_PLAYING", payload.playing.to_string())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        // a hook that outlives its timeout is killed when we drop it
+        .kill_on_drop(true);
+
+    let mut child = command.spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // hooks that don't read stdin close it early, that's fine
+        stdin.write_all(&serde_json::to_vec(payload)?).await.ok();
+    }
+
+    let output = time::timeout(timeout, child.wait_with_output())
+        .await
+        .map_err(|_| anyhow!("timed out after {timeout:?}"))??;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stdout.trim().is_empty() {
+        debug!("Hook {} output: {}", hook.id, stdout.trim());
+    }
+    if !output.status.success() {
+        return Err(anyhow!("exited with {}: {}", output.status, stderr.trim()));
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn shell(script: &str) -> Command {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(script);
+    command
+}
+
+#[cfg(windows)]
+fn shell(script: &str) -> Command {
+    let mut command = Command::new("cmd");
+    command.arg("/C").arg(script);
+    command
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// A fresh directory per test for our hooks to write to
+    fn hook_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("medyia-hooks-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn hook(command: String, timeout_secs: Option<u64>) -> Hook {
+        Hook {
+            id: "hook".to_string(),
+            definition: HookDefinition {
+                name: "Test".to_string(),
+                event: HookEvent::TrackChanged,
+                command,
+                timeout_secs,
+            },
+            enabled: true,
+        }
+    }
+
+    fn payload() -> HookPayload {
+        HookPayload {
+            event: HookEvent::TrackChanged,
+            tab: "YouTube-abcdef".to_string(),
+            source: MediaSource::YouTube,
+            url: "https://www.youtube.com/watch?v=1".to_string(),
+            title: Some("A Title".to_string()),
+            artist: None,
+            playing: true,
+        }
+    }
+
+    #[tokio::test]
+    async fn passes_the_event_in_env_and_stdin() {
+        let dir = hook_dir("payload");
+        let command = format!(
+            "printf '%s\\n' \"$MEDYIA_EVENT\" \"$MEDYIA_TAB\" \"$MEDYIA_SOURCE\" \"$MEDYIA_URL\" \
+             \"$MEDYIA_TITLE\" \"$MEDYIA_ARTIST\" \"$MEDYIA_PLAYING\" > {0}/env; cat > {0}/stdin",
+            dir.display()
+        );
+
+        run(&hook(command, None), &payload()).await.unwrap();
+
+        assert_eq!(
+            fs::read_to_string(dir.join("env")).unwrap(),
+            "track-changed\nYouTube-abcdef\nYouTube\nhttps://www.youtube.com/watch?v=1\nA Title\n\ntrue\n"
+        );
+        let stdin: Value = serde_json::from_slice(&fs::read(dir.join("stdin")).unwrap()).unwrap();
+        assert_eq!(
+            stdin,
+            json!({
+                "event": "track-changed",
+                "tab": "YouTube-abcdef",
+                "source": "YouTube",
+                "url": "https://www.youtube.com/watch?v=1",
+                "title": "A Title",
+                "artist": null,
+                "playing": true,
+            })
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reports_failures() {
+        let cases = [
+            ("echo oops >&2; exit 3", "exited with exit status: 3: oops"),
+            ("echo fine", ""),
+        ];
+
+        for (command, expected) in cases {
+            let result = run(&hook(command.to_string(), None), &payload()).await;
+            let error = result.err().map(|e| e.to_string()).unwrap_or_default();
+            assert_eq!(error, expected, "{command}");
+        }
+    }
+
+    #[tokio::test]
+    async fn kills_hooks_that_time_out() {
+        let dir = hook_dir("timeout");
+        let marker = dir.join("finished");
+        let command = format!("sleep 2; touch {}", marker.display());
+
+        let error = run(&hook(command, Some(1)), &payload()).await.unwrap_err();
+        assert_eq!(error.to_string(), "timed out after 1s");
+
+        // it would have finished by now if we hadn't killed it
+        time::sleep(Duration::from_secs(2)).await;
+        assert!(!marker.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn limits_how_many_hooks_run_at_once() {
+        let dir = hook_dir("concurrency");
+        let hooks = (0..MAX_CONCURRENT_HOOKS + 2)
+            .map(|i| {
+                let command = format!("touch {0}/{i}; sleep 1", dir.display());
+                tokio::spawn(async move { run(&hook(command, None), &payload()).await })
+            })
+            .collect::<Vec<_>>();
+
+        time::sleep(Duration::from_millis(500)).await;
+        // other tests may be holding permits too, but never more than our limit between us
+        let started = fs::read_dir(&dir).unwrap().count();
+        assert!(
+            (1..=MAX_CONCURRENT_HOOKS).contains(&started),
+            "{started} hooks started"
+        );
+        assert_eq!(HOOK_PERMITS.available_permits(), 0);
+
+        for hook in hooks {
+            hook.await.unwrap().unwrap();
+        }
+        assert_eq!(
+            fs::read_dir(&dir).unwrap().count(),
+            MAX_CONCURRENT_HOOKS + 2
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
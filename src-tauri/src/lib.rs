@@ -1,14 +1,33 @@
+mod artwork;
 mod commands;
+mod controls;
+mod discord;
+mod focus;
+mod fullscreen;
+mod history;
+mod hooks;
 mod media_bridge;
 mod media_sources;
 mod memory;
+mod metadata;
+mod mini_player;
+mod mqtt;
+mod now_playing;
 mod osx_utils;
+mod picture_in_picture;
 mod playback;
+mod rpc;
 mod run;
+mod scheduler;
+mod scrobbler;
 mod session;
+mod settings;
+mod sleep_timer;
 mod state;
+mod stats;
 mod utils;
 mod webview_manager;
+mod window_metrics;
 
 use log::{error, warn};
 use std::fmt::Debug;
@@ -21,6 +40,7 @@ pub const MAIN_WINDOW: &str = "MAIN_WINDOW";
 pub const MAIN_WEBVIEW: &str = "MAIN_WINDOW";
 
 pub const BACKEND_STATE_EVENT: &str = "BACKEND_STATE_EVENT";
+pub const SETTINGS_CHANGED_EVENT: &str = "SETTINGS_CHANGED_EVENT";
 
 trait EnhancedManager<R: Runtime> {
     fn main_window(&self) -> Window<R>;
@@ -1,83 +1,124 @@
-// use std::sync::Mutex;
-// use tauri::AppHandle;
-//
-// use crate::state::EnhancerAppStateManagerEmitter;
-// use crate::EnhancedResult;
-// use souvlaki::{
-//     MediaControlEvent, MediaControls, MediaMetadata as SouvlakiMetadata, MediaPlayback,
-//     PlatformConfig,
-// };
-//
-// static MEDIA_CONTROLS: Mutex<Option<MediaControls>> = Mutex::new(None);
-//
-// pub fn setup_media_keys(app: &AppHandle) -> anyhow::Result<()> {
-//     // let config = PlatformConfig {
-//     //     dbus_name: &app.config().identifier,
-//     //     display_name: app
-//     //         .config()
-//     //         .product_name
-//     //         .as_ref()
-//     //         .map(String::as_str)
-//     //         .unwrap_or_else(|| env!("CARGO_PKG_NAME")),
-//     //     hwnd: None,
-//     // };
-//     //
-//     // let controls = MediaControls::new(config)?;
-//     //
-//     // *MEDIA_CONTROLS.lock().unwrap() = Some(controls);
-//     //
-//     // let app_handle = app.clone();
-//     // let mut guard = MEDIA_CONTROLS.lock().unwrap();
-//     // if let Some(ref mut controls) = *guard {
-//     //     controls.attach(move |event: MediaControlEvent| {
-//     //         handle_media_event(&app_handle, event).log_error();
-//     //     })?;
-//     // }
-//
-//     Ok(())
-// }
-//
-// fn handle_media_event(app: &AppHandle, event: MediaControlEvent) -> anyhow::Result<()> {
-//     app.app_state_mut(|state| {
-//         match event {
-//             MediaControlEvent::Play => state.play_active_tab(app)?,
-//             MediaControlEvent::Pause => {
-//                 state.pause_playing_tab();
-//             }
-//             MediaControlEvent::Toggle => state.toggle_playing(app)?,
-//             MediaControlEvent::Next => {
-//                 if let Some(tab) = state.playing_tab_mut() {
-//                     tab.next();
-//                 }
-//             }
-//             MediaControlEvent::Previous => {
-//                 if let Some(tab) = state.playing_tab_mut() {
-//                     tab.previous();
-//                 }
-//             }
-//             _ => (),
-//         }
-//
-//         Ok(())
-//     })
-// }
-//
-// pub fn update_now_playing(title: Option<&str>, artist: Option<&str>, playing: bool) {
-//     let mut guard = MEDIA_CONTROLS.lock().unwrap();
-//     if let Some(ref mut controls) = *guard {
-//         let _ = controls.set_metadata(SouvlakiMetadata {
-//             title,
-//             artist,
-//             album: None,
-//             cover_url: None,
-//             duration: None,
-//         });
-//
-//         let playback = if playing {
-//             MediaPlayback::Playing { progress: None }
-//         } else {
-//             MediaPlayback::Paused { progress: None }
-//         };
-//         let _ = controls.set_playback(playback);
-//     }
-// }
+use crate::EnhancedResult;
+use crate::artwork;
+use crate::state::{EnhancerAppStateManager, EnhancerAppStateManagerEmitter};
+use anyhow::anyhow;
+use log::debug;
+use souvlaki::{MediaControlEvent, MediaControls, MediaMetadata, MediaPlayback, PlatformConfig};
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager, Runtime};
+use url::Url;
+
+static MEDIA_CONTROLS: Mutex<Option<MediaControls>> = Mutex::new(None);
+/// What we last gave our media controls, we update them whenever we emit our app state
+static LAST_NOW_PLAYING: Mutex<Option<NowPlaying>> = Mutex::new(None);
+
+#[derive(Debug, Clone, PartialEq)]
+struct NowPlaying {
+    title: Option<String>,
+    artist: Option<String>,
+    /// A `file://` URL into our artwork cache, the system can't load our `medyia://` URLs
+    cover_url: Option<String>,
+    playing: bool,
+}
+
+pub fn setup_media_keys(app: &AppHandle) -> anyhow::Result<()> {
+    let config = PlatformConfig {
+        dbus_name: &app.config().identifier,
+        display_name: app
+            .config()
+            .product_name
+            .as_deref()
+            .unwrap_or(env!("CARGO_PKG_NAME")),
+        hwnd: None,
+    };
+
+    let mut controls =
+        MediaControls::new(config).map_err(|e| anyhow!("Couldn't create media controls: {e:?}"))?;
+    let app_handle = app.clone();
+    controls
+        .attach(move |event| handle_media_event(&app_handle, event).log_error())
+        .map_err(|e| anyhow!("Couldn't attach our media controls: {e:?}"))?;
+
+    *MEDIA_CONTROLS.lock().unwrap() = Some(controls);
+    update(app);
+
+    Ok(())
+}
+
+fn handle_media_event(app: &AppHandle, event: MediaControlEvent) -> anyhow::Result<()> {
+    debug!("Media control event: {event:?}");
+
+    app.app_state_mut(|state| {
+        match event {
+            MediaControlEvent::Play => state.play_active_tab(app)?,
+            MediaControlEvent::Pause | MediaControlEvent::Stop => state.pause_all()?,
+            MediaControlEvent::Toggle => state.toggle_playing(app)?,
+            MediaControlEvent::Next => {
+                if let Some(tab) = state.playing_tab() {
+                    tab.next();
+                }
+            }
+            MediaControlEvent::Previous => {
+                if let Some(tab) = state.playing_tab() {
+                    tab.previous();
+                }
+            }
+            _ => (),
+        }
+
+        Ok(())
+    })
+}
+
+/// Show what's playing, or otherwise showing, in our media controls when it's changed
+pub fn update<R: Runtime>(app: &impl Manager<R>) {
+    let mut guard = MEDIA_CONTROLS.lock().unwrap();
+    let Some(controls) = guard.as_mut() else {
+        return;
+    };
+
+    let now_playing = app.app_state(|state| {
+        let playing = state.playing_tab();
+        let tab = playing.or_else(|| state.active_tab_key().and_then(|key| state.tab(&key)))?;
+
+        Some(NowPlaying {
+            title: tab.title.clone(),
+            artist: tab.artist.clone(),
+            cover_url: tab
+                .artwork
+                .as_deref()
+                .and_then(|url| artwork::cached_file(app, url))
+                .and_then(|path| Url::from_file_path(path).ok())
+                .map(String::from),
+            playing: playing.is_some(),
+        })
+    });
+
+    let mut last = LAST_NOW_PLAYING.lock().unwrap();
+    if *last == now_playing {
+        return;
+    }
+
+    let result = match &now_playing {
+        Some(now_playing) => controls
+            .set_metadata(MediaMetadata {
+                title: now_playing.title.as_deref(),
+                artist: now_playing.artist.as_deref(),
+                album: None,
+                cover_url: now_playing.cover_url.as_deref(),
+                duration: None,
+            })
+            .and_then(|_| {
+                controls.set_playback(if now_playing.playing {
+                    MediaPlayback::Playing { progress: None }
+                } else {
+                    MediaPlayback::Paused { progress: None }
+                })
+            }),
+        None => controls.set_playback(MediaPlayback::Stopped),
+    };
+    match result {
+        Ok(()) => *last = now_playing,
+        Err(e) => debug!("Couldn't update our media controls: {e:?}"),
+    }
+}
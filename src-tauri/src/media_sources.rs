@@ -1,3 +1,5 @@
+use crate::artwork;
+use crate::settings::Settings;
 use rand::distr::{Alphanumeric, SampleString};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -6,10 +8,23 @@ use serde_json::Value;
 #[serde(rename_all = "camelCase")]
 pub struct MediaDefinition {
     id: MediaSource,
-    name: &'static str,
-    icon_url: &'static str,
+    name: String,
+    icon_url: String,
     default_url: &'static str,
     multi_instance: bool,
+    hidden: bool,
+}
+
+impl MediaDefinition {
+    /// Apply the user's name for this source, whether it opens several tabs and whether it's hidden
+    pub fn with_settings(self, settings: &Settings) -> Self {
+        Self {
+            name: settings.source_name(self.id).to_string(),
+            multi_instance: settings.is_multi_instance(self.id),
+            hidden: settings.hidden_sources.contains(&self.id),
+            ..self
+        }
+    }
 }
 
 macro_rules! define_sources {
@@ -17,7 +32,7 @@ macro_rules! define_sources {
         $(
             $variant:ident {
                 name: $name:expr,
-                icon_url: $icon_url:expr,
+                icon: $icon:expr,
                 default_url: $default_url:expr,
                 multi_instance: $multi:expr,
                 init_script: $init_script:expr,
@@ -49,9 +64,10 @@ macro_rules! define_sources {
                 }
             }
 
-            pub fn icon_url(&self) -> &'static str {
+            /// Bundled so we can render our sources offline
+            pub fn icon(self) -> &'static [u8] {
                 match self {
-                    $(MediaSource::$variant => $icon_url,)*
+                    $(MediaSource::$variant => $icon,)*
                 }
             }
 
@@ -61,13 +77,14 @@ macro_rules! define_sources {
                 }
             }
 
+            /// Whether we open several tabs by default, users can change this in our settings
             pub fn multi_instance(self) -> bool {
                 match self {
                     $(MediaSource::$variant => $multi,)*
                 }
             }
 
-            pub fn init_script(self, tab_label: &str) -> String {
+            pub fn init_script(self, tab_label: &str, muted: bool) -> String {
                 const BASE_SCRIPT: &str = include_str!("../scripts/base_monitor.js");
                 let metadata = match self {
                     $(MediaSource::$variant => $init_script,)*
@@ -77,6 +94,7 @@ macro_rules! define_sources {
                     r#"(() => {{
                         const TAB_LABEL = '{tab_label}';
                         const SOURCE_ID = '{}';
+                        const START_MUTED = {muted};
                         {BASE_SCRIPT}
                         {metadata}
                     }})();"#,
@@ -99,10 +117,11 @@ macro_rules! define_sources {
             pub fn definition(&self) -> MediaDefinition {
                 MediaDefinition {
                     id: *self,
-                    name: self.name(),
-                    icon_url: self.icon_url(),
+                    name: self.name().to_string(),
+                    icon_url: artwork::icon_url(*self),
                     default_url: self.default_url(),
                     multi_instance: self.multi_instance(),
+                    hidden: false,
                 }
             }
 
@@ -116,7 +135,7 @@ macro_rules! define_sources {
 define_sources! {
     AppleMusic {
         name: "Apple Music",
-        icon_url: "https://music.apple.com/assets/favicon/favicon-180.png",
+        icon: include_bytes!("../icons/sources/apple-music.svg"),
         default_url: "https://music.apple.com",
         multi_instance: false,
         init_script: include_str!("../scripts/apple-music_metadata.js"),
@@ -125,7 +144,7 @@ define_sources! {
     },
     YouTube {
         name: "YouTube",
-        icon_url: "https://www.youtube.com/img/favicon_144.png",
+        icon: include_bytes!("../icons/sources/youtube.svg"),
         default_url: "https://www.youtube.com",
         multi_instance: true,
         init_script: include_str!("../scripts/youtube_metadata.js"),
@@ -134,7 +153,7 @@ define_sources! {
     },
     SoundCloud {
         name: "SoundCloud",
-        icon_url: "https://a-v2.sndcdn.com/assets/images/sc-icons/favicon-2cadd14bdb.ico",
+        icon: include_bytes!("../icons/sources/soundcloud.svg"),
         default_url: "https://soundcloud.com",
         multi_instance: false,
         init_script: include_str!("../scripts/soundcloud_metadata.js"),
@@ -143,7 +162,7 @@ define_sources! {
     },
     BrainFm {
         name: "Brain.fm",
-        icon_url: "https://my.brain.fm/favicon.png",
+        icon: include_bytes!("../icons/sources/brain-fm.svg"),
         default_url: "https://my.brain.fm",
         multi_instance: false,
         init_script: include_str!("../scripts/brain-fm_metadata.js"),
@@ -1,26 +1,41 @@
 use crate::EnhancedResult;
+use crate::settings;
 use crate::state::EnhancerAppStateManagerEmitter;
-use std::time::Duration;
 use tauri::AppHandle;
 use tokio::time;
 
-const CHECK_INTERVAL: Duration = Duration::from_secs(30);
-const UNLOAD_TIMEOUT: Duration = Duration::from_mins(15);
-
 pub fn start_memory_monitor(app: AppHandle) {
     tauri::async_runtime::spawn(async move {
-        let mut interval = time::interval(CHECK_INTERVAL);
+        let mut settings = settings::subscribe(&app);
         loop {
-            interval.tick().await;
-            check_idle_tabs(&app);
+            let check_interval = settings.borrow_and_update().memory_check_interval();
+            let mut interval = time::interval(check_interval);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => check_idle_tabs(&app),
+                    // restart our interval with whatever changed
+                    changed = settings.changed() => {
+                        if changed.is_err() {
+                            return;
+                        }
+                        break;
+                    }
+                }
+            }
         }
     });
 }
 
 fn check_idle_tabs(app: &AppHandle) {
+    let Some(unload_timeout) = settings::get(app).unload_timeout() else {
+        return;
+    };
+
     app.app_state_mut(|state| {
-        for tab in state.tabs_mut() {
-            tab.try_unload_inactive(UNLOAD_TIMEOUT)?;
+        // our mini-player's tab stays around even while it's paused
+        let pinned = state.mini_player_tab_key();
+        for tab in state.tabs_mut().filter(|t| pinned.as_ref() != Some(&t.key)) {
+            tab.try_unload_inactive(unload_timeout)?;
         }
 
         Ok(())
@@ -0,0 +1,525 @@
+use crate::media_sources::MediaSource;
+use crate::playback::PlaybackEvent;
+use crate::state::EnhancerAppStateManager;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Runtime};
+use tauri_plugin_store::StoreExt;
+
+const STORE_PATH: &str = "medyia-metadata.json";
+const RULES_KEY: &str = "rules";
+
+/// How uploaders separate the artist from the title, "Artist - Title"
+const SEPARATORS: &[&str] = &[" - ", " – ", " — ", " -- "];
+/// YouTube's auto-generated channels for an artist's official audio
+const TOPIC_SUFFIX: &str = " - Topic";
+/// Bracketed suffixes that describe the upload rather than the track
+///
+/// "audio" and "video" only count alongside another word, a track can be called "Video"
+const NOISE: &[&str] = &[
+    "official",
+    "official video",
+    "official music video",
+    "official audio",
+    "official lyric video",
+    "official visualizer",
+    "official hd video",
+    "music video",
+    "lyric video",
+    "lyrics",
+    "audio only",
+    "video only",
+    "hd video",
+    "hq audio",
+    "visualizer",
+    "visualiser",
+    "hd",
+    "hq",
+    "4k",
+    "mv",
+];
+
+/// Fixes the metadata of tracks we get wrong, the first matching rule wins
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MetadataRule {
+    /// Every source when missing
+    pub source: Option<MediaSource>,
+    /// Matched case-insensitively against the artist the page reports, e.g. the YouTube channel
+    pub artist: Option<String>,
+    /// Matched case-insensitively against the title the page reports
+    pub title_contains: Option<String>,
+    pub set_artist: Option<String>,
+    pub set_title: Option<String>,
+    /// Leave the title as the page reports it rather than splitting and cleaning it
+    #[serde(default)]
+    pub keep_title: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+}
+
+/// Clean up `event`'s title and artist before anything else sees them
+pub fn normalize_event<R: Runtime>(app: &AppHandle<R>, mut event: PlaybackEvent) -> PlaybackEvent {
+    let Some(source) = app.app_state(|state| state.tab(&event.label).map(|t| t.source)) else {
+        return event;
+    };
+    let rules = get_rules(app).unwrap_or_default();
+
+    let metadata = normalize(
+        source,
+        Metadata {
+            title: event.title.take(),
+            artist: event.artist.take(),
+        },
+        &rules,
+    );
+    event.title = metadata.title;
+    event.artist = metadata.artist;
+
+    event
+}
+
+pub fn get_rules<R: Runtime>(app: &AppHandle<R>) -> anyhow::Result<Vec<MetadataRule>> {
+    let store = app.store(STORE_PATH)?;
+
+    match store.get(RULES_KEY) {
+        Some(rules) => Ok(serde_json::from_value(rules)?),
+        None => Ok(Vec::new()),
+    }
+}
+
+pub fn set_rules(app: &AppHandle, rules: Vec<MetadataRule>) -> anyhow::Result<()> {
+    let store = app.store(STORE_PATH)?;
+    store.set(RULES_KEY, serde_json::to_value(rules)?);
+    store.save()?;
+
+    Ok(())
+}
+
+pub fn normalize(source: MediaSource, metadata: Metadata, rules: &[MetadataRule]) -> Metadata {
+    let rule = rules.iter().find(|r| r.matches(source, &metadata));
+
+    let mut normalized = if rule.is_some_and(|r| r.keep_title) {
+        metadata
+    } else {
+        match source {
+            MediaSource::YouTube | MediaSource::SoundCloud => clean_uploaded(metadata),
+            MediaSource::AppleMusic | MediaSource::BrainFm => metadata,
+        }
+    };
+
+    if let Some(rule) = rule {
+        if let Some(artist) = &rule.set_artist {
+            normalized.artist = Some(artist.clone());
+        }
+        if let Some(title) = &rule.set_title {
+            normalized.title = Some(title.clone());
+        }
+    }
+
+    normalized
+}
+
+/// Uploads name the artist in the title, and the uploader is often a label or fan channel
+fn clean_uploaded(metadata: Metadata) -> Metadata {
+    let Metadata { title, artist } = metadata;
+    let Some(title) = title else {
+        return Metadata { title, artist };
+    };
+
+    let mut artist = artist.map(|a| a.trim().to_string());
+    let mut title = strip_noise(&title);
+
+    // topic channels are named after the artist and use the plain track title
+    let topic_artist = artist
+        .as_deref()
+        .and_then(|a| a.strip_suffix(TOPIC_SUFFIX))
+        .map(str::to_string);
+    if let Some(topic_artist) = topic_artist {
+        artist = Some(topic_artist);
+    } else if let Some((split_artist, split_title)) = split_artist_title(&title) {
+        artist = Some(split_artist);
+        title = split_title;
+    }
+
+    Metadata {
+        title: Some(title).filter(|t| !t.is_empty()),
+        artist: artist.filter(|a| !a.is_empty()),
+    }
+}
+
+fn split_artist_title(title: &str) -> Option<(String, String)> {
+    let (artist, title) = SEPARATORS
+        .iter()
+        .filter_map(|separator| title.split_once(separator))
+        .min_by_key(|(artist, _)| artist.len())?;
+    let artist = artist.trim();
+    let title = strip_quotes(&strip_noise(title)).to_string();
+
+    (!artist.is_empty() && !title.is_empty()).then(|| (artist.to_string(), title))
+}
+
+/// Drop trailing bracketed groups like "(Official Video)" or "[4K]"
+fn strip_noise(title: &str) -> String {
+    let mut title = title.trim();
+    while let Some(start) = trailing_group(title) {
+        let inner = title[start + 1..title.len() - 1].to_lowercase();
+        if !is_noise(&inner) {
+            break;
+        }
+        title = title[..start].trim_end();
+    }
+
+    title.to_string()
+}
+
+/// Where the bracketed group ending `title` opens, skipping over any brackets nested inside it
+fn trailing_group(title: &str) -> Option<usize> {
+    let (open, close) = match title.chars().last()? {
+        ')' => ('(', ')'),
+        ']' => ('[', ']'),
+        _ => return None,
+    };
+
+    let mut depth = 0;
+    for (i, c) in title.char_indices().rev() {
+        if c == close {
+            depth += 1;
+        } else if c == open {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i);
+            }
+        }
+    }
+
+    None
+}
+
+/// Every part of a group like "Official Video / 4K (HD)" describes the upload
+fn is_noise(inner: &str) -> bool {
+    let mut parts = inner
+        .split(['/', '|', '&', '+', '(', ')', '[', ']'])
+        .map(|part| part.split_whitespace().collect::<Vec<_>>())
+        .filter(|words| !words.is_empty())
+        .peekable();
+
+    parts.peek().is_some()
+        && parts.all(|words| {
+            NOISE.contains(&words.join(" ").as_str()) || words.iter().all(|w| NOISE.contains(w))
+        })
+}
+
+fn strip_quotes(title: &str) -> &str {
+    ['"', '\'', '“']
+        .iter()
+        .find_map(|&quote| {
+            let close = if quote == '“' { '”' } else { quote };
+            title.strip_prefix(quote)?.strip_suffix(close)
+        })
+        .unwrap_or(title)
+        .trim()
+}
+
+impl MetadataRule {
+    fn matches(&self, source: MediaSource, metadata: &Metadata) -> bool {
+        let contains = |value: &Option<String>, needle: &str, exact: bool| {
+            value.as_deref().is_some_and(|value| {
+                let (value, needle) = (value.to_lowercase(), needle.to_lowercase());
+                if exact {
+                    value.trim() == needle.trim()
+                } else {
+                    value.contains(&needle)
+                }
+            })
+        };
+
+        self.source.is_none_or(|s| s == source)
+            && self
+                .artist
+                .as_deref()
+                .is_none_or(|artist| contains(&metadata.artist, artist, true))
+            && self
+                .title_contains
+                .as_deref()
+                .is_none_or(|title| contains(&metadata.title, title, false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{AppState, EnhancerAppStateManagerEmitter};
+    use tauri::test::{MockRuntime, mock_builder, mock_context, noop_assets};
+
+    fn metadata(title: &str, artist: Option<&str>) -> Metadata {
+        Metadata {
+            title: Some(title.to_string()),
+            artist: artist.map(str::to_string),
+        }
+    }
+
+    fn rule(artist: Option<&str>, title_contains: Option<&str>) -> MetadataRule {
+        MetadataRule {
+            source: None,
+            artist: artist.map(str::to_string),
+            title_contains: title_contains.map(str::to_string),
+            set_artist: None,
+            set_title: None,
+            keep_title: false,
+        }
+    }
+
+    fn event(label: &str, title: Option<&str>, artist: Option<&str>) -> PlaybackEvent {
+        PlaybackEvent {
+            label: label.to_string(),
+            playing: true,
+            ended: false,
+            audible: true,
+            muted: false,
+            title: title.map(str::to_string),
+            artist: artist.map(str::to_string),
+            artwork_url: None,
+            url: None,
+            duration: None,
+        }
+    }
+
+    #[test]
+    fn strip_noise_cases() {
+        let cases = [
+            ("Song", "Song"),
+            ("  Song  ", "Song"),
+            ("Song (Official Video)", "Song"),
+            ("Song [4K]", "Song"),
+            ("Song (Official Music Video) [HD]", "Song"),
+            ("Song (Official Video) (Official Video)", "Song"),
+            ("Song (Official Video / Lyrics)", "Song"),
+            ("Song (Official Video (HD))", "Song"),
+            ("Song (HD | 4K)", "Song"),
+            ("Song (Audio Only)", "Song"),
+            // the noise we strip has to describe the upload
+            ("Song (Live)", "Song (Live)"),
+            (
+                "Song (feat. Someone) (Official Video)",
+                "Song (feat. Someone)",
+            ),
+            ("Song (Remix (Official))", "Song (Remix (Official))"),
+            ("Song (Live (HD))", "Song (Live (HD))"),
+            // tracks can really be called these
+            ("Video", "Video"),
+            ("(Audio)", "(Audio)"),
+            ("Song (Audio)", "Song (Audio)"),
+            ("Song (Video)", "Song (Video)"),
+            // unbalanced brackets are left alone
+            ("Song Official Video)", "Song Official Video)"),
+            ("Song ()", "Song ()"),
+            ("Canción (Video Oficial)", "Canción (Video Oficial)"),
+            ("Lemon (MV)", "Lemon"),
+            ("夜に駆ける [Official Video]", "夜に駆ける"),
+        ];
+
+        for (title, expected) in cases {
+            assert_eq!(strip_noise(title), expected, "stripping {title:?}");
+        }
+    }
+
+    #[test]
+    fn split_artist_title_cases() {
+        let cases = [
+            ("Artist - Song", Some(("Artist", "Song"))),
+            ("Artist – Song", Some(("Artist", "Song"))),
+            ("Artist — Song", Some(("Artist", "Song"))),
+            ("Artist -- Song", Some(("Artist", "Song"))),
+            // the shortest artist wins, the rest belongs to the title
+            ("Artist - Song - Remix", Some(("Artist", "Song - Remix"))),
+            ("Artist – Song - Remix", Some(("Artist", "Song - Remix"))),
+            ("Artist - \"Song\"", Some(("Artist", "Song"))),
+            ("Artist - 'Song'", Some(("Artist", "Song"))),
+            ("Artist - “Song”", Some(("Artist", "Song"))),
+            ("Artist - Song (Official Video)", Some(("Artist", "Song"))),
+            ("AC/DC - Back In Black", Some(("AC/DC", "Back In Black"))),
+            ("Beyoncé - Halo", Some(("Beyoncé", "Halo"))),
+            ("米津玄師 - Lemon", Some(("米津玄師", "Lemon"))),
+            ("Song", None),
+            ("Song-With-Hyphens", None),
+            (" - Song", None),
+            ("Artist - ", None),
+            ("Artist - (Official Video)", None),
+        ];
+
+        for (title, expected) in cases {
+            let expected = expected.map(|(a, t)| (a.to_string(), t.to_string()));
+            assert_eq!(split_artist_title(title), expected, "splitting {title:?}");
+        }
+    }
+
+    #[test]
+    fn normalize_cases() {
+        let cases = [
+            (
+                MediaSource::YouTube,
+                metadata("Artist - Song (Official Video)", Some("Some Label")),
+                metadata("Song", Some("Artist")),
+            ),
+            (
+                MediaSource::YouTube,
+                metadata("Song (Official Audio)", Some("Artist - Topic")),
+                metadata("Song", Some("Artist")),
+            ),
+            // topic channels already use the plain title
+            (
+                MediaSource::YouTube,
+                metadata("Song - Remastered", Some("Artist - Topic")),
+                metadata("Song - Remastered", Some("Artist")),
+            ),
+            (
+                MediaSource::YouTube,
+                metadata("Song", Some("  Artist  ")),
+                metadata("Song", Some("Artist")),
+            ),
+            (
+                MediaSource::YouTube,
+                metadata("(Official Video)", None),
+                Metadata {
+                    title: None,
+                    artist: None,
+                },
+            ),
+            (
+                MediaSource::SoundCloud,
+                metadata("Artist – Song [HQ]", Some("uploader")),
+                metadata("Song", Some("Artist")),
+            ),
+            (
+                MediaSource::YouTube,
+                metadata("Rosalía - DESPECHÁ (Official Video)", Some("Rosalía")),
+                metadata("DESPECHÁ", Some("Rosalía")),
+            ),
+            // Apple Music already reports clean metadata
+            (
+                MediaSource::AppleMusic,
+                metadata("Artist - Song (Official Video)", Some("Someone")),
+                metadata("Artist - Song (Official Video)", Some("Someone")),
+            ),
+        ];
+
+        for (source, input, expected) in cases {
+            let description = format!("{source:?} {input:?}");
+            assert_eq!(normalize(source, input, &[]), expected, "{description}");
+        }
+    }
+
+    #[test]
+    fn normalize_rules() {
+        let relabel = MetadataRule {
+            set_artist: Some("Real Artist".to_string()),
+            ..rule(Some("fan channel"), None)
+        };
+        let keep = MetadataRule {
+            keep_title: true,
+            ..rule(None, Some("- live -"))
+        };
+        let retitle = MetadataRule {
+            source: Some(MediaSource::SoundCloud),
+            set_title: Some("Fixed".to_string()),
+            ..rule(None, Some("broken"))
+        };
+        let rules = [relabel, keep, retitle];
+
+        let cases = [
+            // rules match what the page reports exactly but case-insensitively
+            (
+                MediaSource::YouTube,
+                metadata("Song (Official Video)", Some("Fan Channel")),
+                metadata("Song", Some("Real Artist")),
+            ),
+            (
+                MediaSource::YouTube,
+                metadata("Song", Some("Fan Channel 2")),
+                metadata("Song", Some("Fan Channel 2")),
+            ),
+            (
+                MediaSource::YouTube,
+                metadata("Artist - Live - 2019 (HD)", Some("Channel")),
+                metadata("Artist - Live - 2019 (HD)", Some("Channel")),
+            ),
+            (
+                MediaSource::SoundCloud,
+                metadata("Broken Title", Some("Artist")),
+                metadata("Fixed", Some("Artist")),
+            ),
+            // rules only apply to their source
+            (
+                MediaSource::YouTube,
+                metadata("Broken Title", Some("Artist")),
+                metadata("Broken Title", Some("Artist")),
+            ),
+            // the first matching rule wins
+            (
+                MediaSource::YouTube,
+                metadata("A - Live - B", Some("FAN CHANNEL")),
+                metadata("Live - B", Some("Real Artist")),
+            ),
+        ];
+
+        for (source, input, expected) in cases {
+            let description = format!("{source:?} {input:?}");
+            assert_eq!(normalize(source, input, &rules), expected, "{description}");
+        }
+    }
+
+    #[test]
+    fn normalize_event_cases() {
+        let app = mock_builder()
+            .plugin(tauri_plugin_store::Builder::new().build())
+            .manage(AppState::<MockRuntime>::new())
+            .build(mock_context(noop_assets()))
+            .unwrap();
+        let handle = app.handle();
+        let youtube = handle
+            .app_state_mut(|state| state.create_tab(MediaSource::YouTube, None))
+            .unwrap();
+        let apple_music = handle
+            .app_state_mut(|state| state.create_tab(MediaSource::AppleMusic, None))
+            .unwrap();
+
+        let cases = [
+            (
+                event(
+                    &youtube,
+                    Some("Artist - Song (Official Video)"),
+                    Some("Label"),
+                ),
+                (Some("Song"), Some("Artist")),
+            ),
+            (
+                event(&youtube, Some("Song"), Some("Artist - Topic")),
+                (Some("Song"), Some("Artist")),
+            ),
+            (
+                event(&youtube, None, Some("Artist")),
+                (None, Some("Artist")),
+            ),
+            (
+                event(&apple_music, Some("Artist - Song"), None),
+                (Some("Artist - Song"), None),
+            ),
+            // we leave events from tabs we don't know about alone
+            (
+                event("youtube-unknown", Some("Artist - Song"), None),
+                (Some("Artist - Song"), None),
+            ),
+        ];
+
+        for (input, (title, artist)) in cases {
+            let description = format!("{input:?}");
+            let normalized = normalize_event(handle, input);
+            assert_eq!(normalized.title.as_deref(), title, "{description}");
+            assert_eq!(normalized.artist.as_deref(), artist, "{description}");
+        }
+    }
+}
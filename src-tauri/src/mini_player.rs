@@ -0,0 +1,167 @@
+use crate::state::EnhancerAppStateManagerEmitter;
+use crate::{EnhancedManager, EnhancedResult};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use tauri::{
+    AppHandle, LogicalPosition, LogicalSize, Manager, Monitor, WebviewUrl, WebviewWindow,
+    WebviewWindowBuilder, WindowEvent,
+};
+use tauri_plugin_store::StoreExt;
+
+pub const MINI_PLAYER_WINDOW: &str = "MINI_PLAYER";
+
+const STORE_PATH: &str = "medyia-mini-player.json";
+const GEOMETRY_KEY: &str = "geometry";
+
+const DEFAULT_SIZE: LogicalSize<f64> = LogicalSize::new(360., 120.);
+const MIN_SIZE: LogicalSize<f64> = LogicalSize::new(280., 96.);
+/// How far from the corner of the screen we first place our mini-player
+const SCREEN_MARGIN: f64 = 24.;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+struct MiniPlayerGeometry {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+}
+
+/// Swap our main window for our mini-player, our tabs keep running in the hidden main window
+pub fn set_mini_player(app: &AppHandle, enabled: bool) -> anyhow::Result<()> {
+    if enabled { open(app) } else { close(app) }
+}
+
+pub fn toggle_mini_player(app: &AppHandle) -> anyhow::Result<()> {
+    set_mini_player(app, app.get_webview_window(MINI_PLAYER_WINDOW).is_none())
+}
+
+fn open(app: &AppHandle) -> anyhow::Result<()> {
+    if let Some(window) = app.get_webview_window(MINI_PLAYER_WINDOW) {
+        window.set_focus()?;
+        return Ok(());
+    }
+
+    let (position, size) = placement(app);
+    let window = WebviewWindowBuilder::new(
+        app,
+        MINI_PLAYER_WINDOW,
+        WebviewUrl::App("mini-player.html".into()),
+    )
+    .title("Medyia")
+    .inner_size(size.width, size.height)
+    .min_inner_size(MIN_SIZE.width, MIN_SIZE.height)
+    .position(position.x, position.y)
+    .always_on_top(true)
+    .decorations(false)
+    .build()?;
+
+    let app_handle = app.clone();
+    let mini_player = window.clone();
+    window.on_window_event(move |event| match event {
+        WindowEvent::Moved(_) | WindowEvent::Resized(_) => {
+            remember_geometry(&app_handle, &mini_player).log_error();
+        }
+        WindowEvent::CloseRequested { api, .. } => {
+            // closing our mini-player brings back our main window
+            api.prevent_close();
+            close(&app_handle).log_error();
+        }
+        _ => {}
+    });
+
+    app.app_state_mut(|state| {
+        state.set_mini_player(true);
+        Ok(())
+    })?;
+    app.main_window().hide()?;
+    info!("Switched to our mini-player");
+
+    Ok(())
+}
+
+fn close(app: &AppHandle) -> anyhow::Result<()> {
+    let Some(window) = app.get_webview_window(MINI_PLAYER_WINDOW) else {
+        return Ok(());
+    };
+
+    remember_geometry(app, &window)?;
+    app.store(STORE_PATH)?.save()?;
+
+    // show our main window first so we're never left without one
+    let main_window = app.main_window();
+    main_window.show()?;
+    main_window.set_focus()?;
+    window.destroy()?;
+
+    app.app_state_mut(|state| {
+        state.set_mini_player(false);
+        Ok(())
+    })?;
+    info!("Switched back to our main window");
+
+    Ok(())
+}
+
+/// The store writes itself out shortly after, so dragging our mini-player doesn't save every step
+fn remember_geometry(app: &AppHandle, window: &WebviewWindow) -> anyhow::Result<()> {
+    let scale = window.scale_factor()?;
+    let position = window.outer_position()?.to_logical::<f64>(scale);
+    let size = window.inner_size()?.to_logical::<f64>(scale);
+    let geometry = MiniPlayerGeometry {
+        x: position.x,
+        y: position.y,
+        width: size.width,
+        height: size.height,
+    };
+
+    debug!("Remembering mini-player geometry {geometry:?}");
+    app.store(STORE_PATH)?
+        .set(GEOMETRY_KEY, serde_json::to_value(geometry)?);
+
+    Ok(())
+}
+
+/// Where we left our mini-player, or the corner of our primary monitor if we can't show it there anymore
+fn placement(app: &AppHandle) -> (LogicalPosition<f64>, LogicalSize<f64>) {
+    let saved = app
+        .store(STORE_PATH)
+        .ok()
+        .and_then(|store| store.get(GEOMETRY_KEY))
+        .and_then(|geometry| serde_json::from_value::<MiniPlayerGeometry>(geometry).ok());
+    let monitors = app.available_monitors().unwrap_or_default();
+
+    let size = saved.map_or(DEFAULT_SIZE, |g| {
+        LogicalSize::new(g.width.max(MIN_SIZE.width), g.height.max(MIN_SIZE.height))
+    });
+    if let Some(saved) = saved.filter(|g| monitors.iter().any(|m| g.is_on(m))) {
+        return (LogicalPosition::new(saved.x, saved.y), size);
+    }
+
+    let primary = app.primary_monitor().ok().flatten();
+    let Some(monitor) = primary.as_ref().or(monitors.first()) else {
+        return (LogicalPosition::new(SCREEN_MARGIN, SCREEN_MARGIN), size);
+    };
+    let scale = monitor.scale_factor();
+    let origin = monitor.position().to_logical::<f64>(scale);
+    let bounds = monitor.size().to_logical::<f64>(scale);
+
+    let position = LogicalPosition::new(
+        origin.x + (bounds.width - size.width - SCREEN_MARGIN).max(0.),
+        origin.y + (bounds.height - size.height - SCREEN_MARGIN).max(0.),
+    );
+    (position, size)
+}
+
+impl MiniPlayerGeometry {
+    /// Whether the middle of our mini-player is on `monitor`
+    fn is_on(&self, monitor: &Monitor) -> bool {
+        let scale = monitor.scale_factor();
+        let origin = monitor.position().to_logical::<f64>(scale);
+        let bounds = monitor.size().to_logical::<f64>(scale);
+        let (x, y) = (self.x + self.width / 2., self.y + self.height / 2.);
+
+        (origin.x..origin.x + bounds.width).contains(&x)
+            && (origin.y..origin.y + bounds.height).contains(&y)
+    }
+}
@@ -0,0 +1,949 @@
+use crate::controls::{Click, Play, SetVolume};
+use crate::media_sources::MediaSource;
+use crate::rpc;
+use crate::state::{EnhancerAppStateManager, EnhancerAppStateManagerEmitter, TabKey};
+use crate::webview_manager;
+use anyhow::{anyhow, bail};
+use log::{debug, info, warn};
+use rand::distr::{Alphanumeric, SampleString};
+use rumqttc::{
+    AsyncClient, Event, EventLoop, LastWill, MqttOptions, Outgoing, Packet, Publish, QoS,
+    TlsConfiguration, Transport,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+use tauri::{AppHandle, Manager, Runtime};
+use tauri_plugin_store::StoreExt;
+use tokio::sync::watch;
+use tokio::time::{self, sleep};
+
+const STORE_PATH: &str = "medyia-mqtt.json";
+const CONFIG_KEY: &str = "config";
+
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// How long we give our last messages to go out when we disconnect
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
+/// How many requests we queue for the event loop before publishing fails
+const CLIENT_CAPACITY: usize = 32;
+
+const ONLINE: &str = "online";
+const OFFLINE: &str = "offline";
+const COMMANDS: &[&str] = &["play", "pause", "next", "previous"];
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MqttConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    #[serde(default)]
+    pub tls: bool,
+    /// A PEM certificate authority for brokers with self-signed certificates, otherwise we use the system roots
+    pub ca_file: Option<PathBuf>,
+    /// We publish under `<prefix>/state` and listen on `<prefix>/command/<command>`
+    #[serde(default = "default_topic_prefix")]
+    pub topic_prefix: String,
+    /// Publish Home Assistant discovery payloads under this prefix
+    #[serde(default = "default_discovery_prefix")]
+    pub discovery_prefix: Option<String>,
+}
+
+/// What our broker asks us to do through `<prefix>/command/<command>`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Command {
+    Play,
+    Pause,
+    Next,
+    Previous,
+    /// Between 0 and 1
+    Volume(f64),
+    Source(MediaSource),
+}
+
+/// What we publish, retained, to our state topic
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct PlaybackState {
+    state: &'static str,
+    tab: Option<TabKey>,
+    source: Option<MediaSource>,
+    title: Option<String>,
+    artist: Option<String>,
+    artwork: Option<String>,
+}
+
+pub struct Mqtt {
+    config: watch::Sender<MqttConfig>,
+    state: watch::Sender<PlaybackState>,
+}
+
+pub fn setup_mqtt(app: &AppHandle) -> anyhow::Result<()> {
+    let config = get_config(app)?;
+    let (config, config_receiver) = watch::channel(config);
+    let (state, state_receiver) = watch::channel(playback_state(app));
+    app.manage(Mqtt { config, state });
+
+    tauri::async_runtime::spawn(run(app.clone(), config_receiver, state_receiver));
+
+    Ok(())
+}
+
+/// Publish what's playing when it's changed, this runs whenever we emit our app state
+pub fn update<R: Runtime>(app: &impl Manager<R>) {
+    if let Some(mqtt) = app.try_state::<Mqtt>() {
+        mqtt.state.send_if_modified(|state| {
+            let updated = playback_state(app);
+            let changed = *state != updated;
+            *state = updated;
+
+            changed
+        });
+    }
+}
+
+pub fn get_config(app: &AppHandle) -> anyhow::Result<MqttConfig> {
+    let store = app.store(STORE_PATH)?;
+
+    match store.get(CONFIG_KEY) {
+        Some(config) => Ok(serde_json::from_value(config)?),
+        None => Ok(MqttConfig::default()),
+    }
+}
+
+pub fn set_config(app: &AppHandle, config: MqttConfig) -> anyhow::Result<()> {
+    config.validate()?;
+
+    let store = app.store(STORE_PATH)?;
+    store.set(CONFIG_KEY, serde_json::to_value(&config)?);
+    store.save()?;
+
+    if let Some(mqtt) = app.try_state::<Mqtt>() {
+        mqtt.config.send_replace(config);
+    }
+
+    Ok(())
+}
+
+fn playback_state<R: Runtime>(app: &impl Manager<R>) -> PlaybackState {
+    app.app_state(|state| {
+        let playing = state.playing_tab();
+        let tab = playing.or_else(|| state.active_tab_key().and_then(|key| state.tab(&key)));
+
+        PlaybackState {
+            state: match (playing, tab) {
+                (Some(_), _) => "playing",
+                (None, Some(_)) => "paused",
+                (None, None) => "idle",
+            },
+            tab: tab.map(|t| t.key.clone()),
+            source: tab.map(|t| t.source),
+            title: tab.and_then(|t| t.title.clone()),
+            artist: tab.and_then(|t| t.artist.clone()),
+            artwork: tab.and_then(|t| t.artwork.clone()),
+        }
+    })
+}
+
+/// Keep our broker up to date with our state, reconnecting with backoff whenever we lose it
+async fn run(
+    app: AppHandle,
+    mut config: watch::Receiver<MqttConfig>,
+    mut state: watch::Receiver<PlaybackState>,
+) {
+    loop {
+        let current = config.borrow_and_update().clone();
+        if !current.enabled {
+            if config.changed().await.is_err() {
+                return;
+            }
+            continue;
+        }
+
+        match Connection::new(&current) {
+            Ok(connection) => {
+                let on_command = |command: Command| {
+                    let app = app.clone();
+                    tauri::async_runtime::spawn(async move {
+                        if let Err(e) = run_command(&app, command).await {
+                            warn!("Failed to run MQTT command {command:?}: {e}");
+                        }
+                    });
+                };
+                connection.serve(&mut config, &mut state, on_command).await;
+            }
+            Err(e) => {
+                warn!("Invalid MQTT config: {e}");
+                if config.changed().await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+struct Connection {
+    client: AsyncClient,
+    event_loop: EventLoop,
+    config: MqttConfig,
+}
+
+impl Connection {
+    fn new(config: &MqttConfig) -> anyhow::Result<Self> {
+        let mut options = MqttOptions::new(client_id(), &config.host, config.port);
+        options.set_keep_alive(KEEP_ALIVE);
+        options.set_last_will(LastWill::new(
+            config.topic("availability"),
+            OFFLINE,
+            QoS::AtLeastOnce,
+            true,
+        ));
+        if let Some(username) = &config.username {
+            options.set_credentials(username, config.password.as_deref().unwrap_or_default());
+        }
+        if config.tls {
+            options.set_transport(match &config.ca_file {
+                Some(ca_file) => Transport::Tls(TlsConfiguration::Simple {
+                    ca: fs::read(ca_file)?,
+                    alpn: None,
+                    client_auth: None,
+                }),
+                None => Transport::tls_with_default_config(),
+            });
+        }
+
+        let (client, event_loop) = AsyncClient::new(options, CLIENT_CAPACITY);
+
+        Ok(Self {
+            client,
+            event_loop,
+            config: config.clone(),
+        })
+    }
+
+    /// Returns once our config changes so we reconnect with it
+    async fn serve(
+        mut self,
+        config: &mut watch::Receiver<MqttConfig>,
+        state: &mut watch::Receiver<PlaybackState>,
+        on_command: impl Fn(Command),
+    ) {
+        let mut backoff = MIN_BACKOFF;
+
+        loop {
+            tokio::select! {
+                event = self.event_loop.poll() => match event {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        info!("Connected to MQTT broker {}", self.config.host);
+                        backoff = MIN_BACKOFF;
+                        state.mark_changed();
+                        if let Err(e) = self.announce() {
+                            warn!("Failed to announce ourselves over MQTT: {e}");
+                        }
+                    }
+                    Ok(Event::Incoming(Packet::Publish(publish))) => {
+                        match Command::parse(&self.config.topic("command/"), &publish) {
+                            Ok(command) => on_command(command),
+                            Err(e) => warn!("Invalid MQTT command {}: {e}", publish.topic),
+                        }
+                    }
+                    Ok(event) => debug!("MQTT event: {event:?}"),
+                    Err(e) => {
+                        // the event loop reconnects on our next poll
+                        warn!("Lost our MQTT connection, retrying in {backoff:?}: {e}");
+                        tokio::select! {
+                            _ = sleep(backoff) => {}
+                            changed = config.changed() => {
+                                if changed.is_ok() {
+                                    return;
+                                }
+                            }
+                        }
+                        backoff = (backoff * 2).min(MAX_BACKOFF);
+                    }
+                },
+                changed = state.changed() => {
+                    if changed.is_err() {
+                        return;
+                    }
+                    let current = state.borrow_and_update().clone();
+                    if let Err(e) = self.publish_state(&current) {
+                        debug!("Couldn't publish our MQTT state: {e}");
+                    }
+                }
+                changed = config.changed() => {
+                    if changed.is_ok() {
+                        // don't leave a stale availability behind under the old config
+                        self.client
+                            .try_publish(self.config.topic("availability"), QoS::AtLeastOnce, true, OFFLINE)
+                            .ok();
+                        self.client.try_disconnect().ok();
+                        self.flush().await;
+                    }
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Requests only go out as we poll, so poll until we've sent our disconnect
+    async fn flush(&mut self) {
+        let flushed = async {
+            while let Ok(event) = self.event_loop.poll().await {
+                if let Event::Outgoing(Outgoing::Disconnect) = event {
+                    return;
+                }
+            }
+        };
+        time::timeout(FLUSH_TIMEOUT, flushed).await.ok();
+    }
+
+    /// Subscribe to our commands and publish our availability and discovery payloads
+    fn announce(&self) -> anyhow::Result<()> {
+        self.client
+            .try_subscribe(self.config.topic("command/#"), QoS::AtLeastOnce)?;
+        self.client.try_publish(
+            self.config.topic("availability"),
+            QoS::AtLeastOnce,
+            true,
+            ONLINE,
+        )?;
+
+        if let Some(discovery_prefix) = &self.config.discovery_prefix {
+            for (component, object_id, payload) in self.config.discovery() {
+                self.client.try_publish(
+                    format!("{discovery_prefix}/{component}/medyia/{object_id}/config"),
+                    QoS::AtLeastOnce,
+                    true,
+                    serde_json::to_vec(&payload)?,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn publish_state(&self, state: &PlaybackState) -> anyhow::Result<()> {
+        debug!("Publishing our MQTT state: {state:?}");
+
+        self.client.try_publish(
+            self.config.topic("state"),
+            QoS::AtLeastOnce,
+            true,
+            serde_json::to_vec(state)?,
+        )?;
+
+        Ok(())
+    }
+}
+
+async fn run_command(app: &AppHandle, command: Command) -> anyhow::Result<()> {
+    match command {
+        Command::Play => rpc::call(app, &target_tab(app)?, Play).await?,
+        Command::Pause => app.app_state_mut(|state| state.pause_all())?,
+        Command::Next | Command::Previous => {
+            let key = target_tab(app)?;
+            let source = app
+                .app_state(|state| state.tab(&key).map(|t| t.source))
+                .ok_or_else(|| anyhow!("No tab for {key}"))?;
+            let selector = match command {
+                Command::Next => source.next_selector(),
+                _ => source.previous_selector(),
+            };
+            rpc::call(app, &key, Click { selector }).await?
+        }
+        Command::Volume(volume) => rpc::call(app, &target_tab(app)?, SetVolume { volume }).await?,
+        Command::Source(source) => webview_manager::switch_to_source(app, source)?,
+    }
+
+    Ok(())
+}
+
+/// The tab our commands apply to, whatever is playing or otherwise whatever is showing
+fn target_tab(app: &AppHandle) -> anyhow::Result<TabKey> {
+    app.app_state(|state| {
+        state
+            .playing_tab()
+            .map(|t| t.key.clone())
+            .or_else(|| state.active_tab_key())
+    })
+    .ok_or_else(|| anyhow!("No tab to control"))
+}
+
+impl Command {
+    /// `prefix` is our topic up to the command, volume and source take their value as the payload
+    fn parse(prefix: &str, publish: &Publish) -> anyhow::Result<Self> {
+        let command = publish
+            .topic
+            .strip_prefix(prefix)
+            .ok_or_else(|| anyhow!("Unexpected topic"))?;
+        let payload = std::str::from_utf8(&publish.payload)?.trim();
+        info!("MQTT command: {command} {payload}");
+
+        Ok(match command {
+            "play" => Self::Play,
+            "pause" => Self::Pause,
+            "next" => Self::Next,
+            "previous" => Self::Previous,
+            "volume" => {
+                // our discovery payload advertises a percentage
+                let percent = payload.parse::<f64>()?;
+                if !percent.is_finite() {
+                    bail!("Invalid volume {payload}");
+                }
+                Self::Volume(percent.clamp(0., 100.) / 100.)
+            }
+            "source" => {
+                let source = MediaSource::ALL
+                    .iter()
+                    .find(|s| s.source_id() == payload || s.name() == payload)
+                    .ok_or_else(|| anyhow!("Unknown source {payload}"))?;
+                Self::Source(*source)
+            }
+            _ => bail!("Unknown command"),
+        })
+    }
+}
+
+impl MqttConfig {
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.enabled && self.host.trim().is_empty() {
+            bail!("Choose a broker to connect to");
+        }
+        let prefix = self.topic_prefix.trim_matches('/');
+        if prefix.is_empty() || prefix.contains(['+', '#']) {
+            bail!("Invalid topic prefix {:?}", self.topic_prefix);
+        }
+        if let Some(ca_file) = self.ca_file.as_ref().filter(|_| self.tls)
+            && !ca_file.is_file()
+        {
+            bail!("Couldn't find {}", ca_file.display());
+        }
+
+        Ok(())
+    }
+
+    fn topic(&self, topic: &str) -> String {
+        format!("{}/{topic}", self.topic_prefix.trim_matches('/'))
+    }
+
+    /// Our Home Assistant entities, as their component, object id and config
+    fn discovery(&self) -> Vec<(&'static str, String, Value)> {
+        let device = json!({
+            "identifiers": ["medyia"],
+            "name": "Medyia",
+            "manufacturer": "Medyia",
+        });
+        let common = |name: &str, object_id: &str| {
+            json!({
+                "name": name,
+                "unique_id": format!("medyia_{object_id}"),
+                "availability_topic": self.topic("availability"),
+                "device": device,
+            })
+        };
+        let with = |mut entity: Value, fields: Value| {
+            if let (Some(entity), Value::Object(fields)) = (entity.as_object_mut(), fields) {
+                entity.extend(fields);
+            }
+            entity
+        };
+
+        let mut entities = vec![
+            (
+                "sensor",
+                "state".to_string(),
+                with(
+                    common("Playback", "state"),
+                    json!({
+                        "state_topic": self.topic("state"),
+                        "value_template": "{{ value_json.state }}",
+                        "json_attributes_topic": self.topic("state"),
+                    }),
+                ),
+            ),
+            (
+                "sensor",
+                "title".to_string(),
+                with(
+                    common("Title", "title"),
+                    json!({
+                        "state_topic": self.topic("state"),
+                        "value_template": "{{ value_json.title }}",
+                    }),
+                ),
+            ),
+            (
+                "sensor",
+                "artist".to_string(),
+                with(
+                    common("Artist", "artist"),
+                    json!({
+                        "state_topic": self.topic("state"),
+                        "value_template": "{{ value_json.artist }}",
+                    }),
+                ),
+            ),
+            (
+                "number",
+                "volume".to_string(),
+                with(
+                    common("Volume", "volume"),
+                    json!({
+                        "command_topic": self.topic("command/volume"),
+                        "min": 0,
+                        "max": 100,
+                        "unit_of_measurement": "%",
+                        "optimistic": true,
+                    }),
+                ),
+            ),
+            (
+                "select",
+                "source".to_string(),
+                with(
+                    common("Source", "source"),
+                    json!({
+                        "command_topic": self.topic("command/source"),
+                        "state_topic": self.topic("state"),
+                        "value_template": "{{ value_json.source }}",
+                        "options": MediaSource::ALL.iter().map(|s| s.source_id()).collect::<Vec<_>>(),
+                    }),
+                ),
+            ),
+        ];
+
+        for command in COMMANDS {
+            let name = format!("{}{}", command[..1].to_uppercase(), &command[1..]);
+            entities.push((
+                "button",
+                command.to_string(),
+                with(
+                    common(&name, command),
+                    json!({ "command_topic": self.topic(&format!("command/{command}")) }),
+                ),
+            ));
+        }
+
+        entities
+    }
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: String::new(),
+            port: default_port(),
+            username: None,
+            password: None,
+            tls: false,
+            ca_file: None,
+            topic_prefix: default_topic_prefix(),
+            discovery_prefix: default_discovery_prefix(),
+        }
+    }
+}
+
+fn default_port() -> u16 {
+    1883
+}
+
+fn default_topic_prefix() -> String {
+    "medyia".to_string()
+}
+
+fn default_discovery_prefix() -> Option<String> {
+    Some("homeassistant".to_string())
+}
+
+/// Brokers drop an existing connection when another connects with the same id
+fn client_id() -> String {
+    format!("medyia-{}", Alphanumeric.sample_string(&mut rand::rng(), 8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::BytesMut;
+    use rumqttc::mqttbytes::{self, v4};
+    use rumqttc::{ConnAck, ConnectReturnCode, PubAck, SubAck, SubscribeReasonCode};
+    use std::collections::HashMap;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::mpsc;
+    use tokio::time::timeout;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Just enough of a broker to see what we publish and send us commands
+    struct Broker {
+        listener: TcpListener,
+        retained: HashMap<String, Vec<u8>>,
+        subscriptions: Vec<String>,
+    }
+
+    struct Client {
+        stream: TcpStream,
+        buffer: BytesMut,
+    }
+
+    impl Broker {
+        async fn start() -> Self {
+            Self {
+                listener: TcpListener::bind("127.0.0.1:0").await.unwrap(),
+                retained: HashMap::new(),
+                subscriptions: Vec::new(),
+            }
+        }
+
+        fn config(&self) -> MqttConfig {
+            MqttConfig {
+                enabled: true,
+                host: "127.0.0.1".to_string(),
+                port: self.listener.local_addr().unwrap().port(),
+                ..MqttConfig::default()
+            }
+        }
+
+        async fn accept(&self) -> Client {
+            let (stream, _) = timeout(TIMEOUT, self.listener.accept())
+                .await
+                .unwrap()
+                .unwrap();
+            let mut client = Client {
+                stream,
+                buffer: BytesMut::new(),
+            };
+
+            assert!(matches!(client.read().await, Packet::Connect(..)));
+            let mut connack = BytesMut::new();
+            ConnAck::new(ConnectReturnCode::Success, false)
+                .write(&mut connack)
+                .unwrap();
+            client.stream.write_all(&connack).await.unwrap();
+
+            client
+        }
+
+        /// Acknowledges whatever `client` sends until it publishes to `topic`
+        async fn receive(&mut self, client: &mut Client, topic: &str) -> Publish {
+            loop {
+                let mut reply = BytesMut::new();
+                match client.read().await {
+                    Packet::Subscribe(subscribe) => {
+                        self.subscriptions
+                            .extend(subscribe.filters.iter().map(|f| f.path.clone()));
+                        let codes = subscribe
+                            .filters
+                            .iter()
+                            .map(|f| SubscribeReasonCode::Success(f.qos))
+                            .collect();
+                        SubAck::new(subscribe.pkid, codes)
+                            .write(&mut reply)
+                            .unwrap();
+                    }
+                    Packet::Publish(publish) => {
+                        if publish.qos != QoS::AtMostOnce {
+                            PubAck::new(publish.pkid).write(&mut reply).unwrap();
+                            client.stream.write_all(&reply).await.unwrap();
+                        }
+                        if publish.retain {
+                            self.retained
+                                .insert(publish.topic.clone(), publish.payload.to_vec());
+                        }
+                        if publish.topic == topic {
+                            return publish;
+                        }
+                        continue;
+                    }
+                    Packet::PingReq => {
+                        rumqttc::PingResp.write(&mut reply).unwrap();
+                    }
+                    packet => panic!("Unexpected packet {packet:?} waiting for {topic}"),
+                }
+                client.stream.write_all(&reply).await.unwrap();
+            }
+        }
+
+        fn retained_json(&self, topic: &str) -> Value {
+            serde_json::from_slice(&self.retained[topic]).unwrap()
+        }
+    }
+
+    impl Client {
+        async fn read(&mut self) -> Packet {
+            loop {
+                match v4::read(&mut self.buffer, 1024 * 1024) {
+                    Ok(packet) => return packet,
+                    Err(mqttbytes::Error::InsufficientBytes(_)) => {}
+                    Err(e) => panic!("Invalid packet: {e:?}"),
+                }
+                let read = timeout(TIMEOUT, self.stream.read_buf(&mut self.buffer))
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_ne!(read, 0, "We disconnected");
+            }
+        }
+
+        async fn send_command(&mut self, command: &str, payload: &str) {
+            let mut packet = BytesMut::new();
+            Publish::new(
+                format!("medyia/command/{command}"),
+                QoS::AtMostOnce,
+                payload,
+            )
+            .write(&mut packet)
+            .unwrap();
+            self.stream.write_all(&packet).await.unwrap();
+        }
+    }
+
+    struct Served {
+        config: watch::Sender<MqttConfig>,
+        state: watch::Sender<PlaybackState>,
+        commands: mpsc::UnboundedReceiver<Command>,
+        task: tokio::task::JoinHandle<()>,
+    }
+
+    fn serve(config: MqttConfig) -> Served {
+        let connection = Connection::new(&config).unwrap();
+        let (config, mut config_receiver) = watch::channel(config);
+        let (state, mut state_receiver) = watch::channel(idle());
+        let (command_sender, commands) = mpsc::unbounded_channel();
+
+        let task = tokio::spawn(async move {
+            let on_command = move |command| {
+                command_sender.send(command).unwrap();
+            };
+            connection
+                .serve(&mut config_receiver, &mut state_receiver, on_command)
+                .await;
+        });
+
+        Served {
+            config,
+            state,
+            commands,
+            task,
+        }
+    }
+
+    fn idle() -> PlaybackState {
+        PlaybackState {
+            state: "idle",
+            tab: None,
+            source: None,
+            title: None,
+            artist: None,
+            artwork: None,
+        }
+    }
+
+    fn playing() -> PlaybackState {
+        PlaybackState {
+            state: "playing",
+            tab: Some("youtube-abcdef".to_string()),
+            source: Some(MediaSource::YouTube),
+            title: Some("Song".to_string()),
+            artist: Some("Artist".to_string()),
+            artwork: None,
+        }
+    }
+
+    fn publish(topic: &str, payload: &str) -> Publish {
+        Publish::new(topic, QoS::AtLeastOnce, payload)
+    }
+
+    #[test]
+    fn parses_commands() {
+        let cases = [
+            ("play", "", Some(Command::Play)),
+            ("pause", "", Some(Command::Pause)),
+            ("next", "", Some(Command::Next)),
+            ("previous", "", Some(Command::Previous)),
+            // Home Assistant sends percentages
+            ("volume", "1", Some(Command::Volume(0.01))),
+            ("volume", "50", Some(Command::Volume(0.5))),
+            ("volume", " 100 ", Some(Command::Volume(1.))),
+            ("volume", "0", Some(Command::Volume(0.))),
+            ("volume", "150", Some(Command::Volume(1.))),
+            ("volume", "-5", Some(Command::Volume(0.))),
+            ("volume", "NaN", None),
+            ("volume", "loud", None),
+            (
+                "source",
+                "youtube",
+                Some(Command::Source(MediaSource::YouTube)),
+            ),
+            (
+                "source",
+                "Apple Music",
+                Some(Command::Source(MediaSource::AppleMusic)),
+            ),
+            ("source", "spotify", None),
+            ("shuffle", "", None),
+        ];
+
+        for (command, payload, expected) in cases {
+            let publish = publish(&format!("medyia/command/{command}"), payload);
+            let parsed = Command::parse("medyia/command/", &publish).ok();
+            assert_eq!(parsed, expected, "{command} {payload:?}");
+        }
+
+        let publish = publish("other/command/play", "");
+        assert!(Command::parse("medyia/command/", &publish).is_err());
+    }
+
+    #[test]
+    fn topics_ignore_slashes() {
+        let config = MqttConfig {
+            topic_prefix: "/home/medyia/".to_string(),
+            ..MqttConfig::default()
+        };
+        assert_eq!(config.topic("state"), "home/medyia/state");
+    }
+
+    #[test]
+    fn rejects_invalid_configs() {
+        let valid = MqttConfig {
+            enabled: true,
+            host: "broker.local".to_string(),
+            ..MqttConfig::default()
+        };
+        assert!(valid.validate().is_ok());
+
+        let invalid = [
+            MqttConfig {
+                host: " ".to_string(),
+                ..valid.clone()
+            },
+            MqttConfig {
+                topic_prefix: "/".to_string(),
+                ..valid.clone()
+            },
+            MqttConfig {
+                topic_prefix: "medyia/#".to_string(),
+                ..valid.clone()
+            },
+            MqttConfig {
+                tls: true,
+                ca_file: Some(PathBuf::from("/does/not/exist.pem")),
+                ..valid.clone()
+            },
+        ];
+        for config in invalid {
+            assert!(config.validate().is_err(), "{config:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn announces_itself_and_publishes_retained_state() {
+        let mut broker = Broker::start().await;
+        let _served = serve(broker.config());
+        let mut client = broker.accept().await;
+
+        let state = broker.receive(&mut client, "medyia/state").await;
+        assert!(state.retain);
+
+        assert_eq!(broker.subscriptions, ["medyia/command/#"]);
+        assert_eq!(broker.retained["medyia/availability"], b"online");
+        assert_eq!(broker.retained_json("medyia/state")["state"], "idle");
+
+        let volume = broker.retained_json("homeassistant/number/medyia/volume/config");
+        assert_eq!(volume["command_topic"], "medyia/command/volume");
+        assert_eq!(volume["max"], 100);
+        for command in COMMANDS {
+            let topic = format!("homeassistant/button/medyia/{command}/config");
+            assert!(broker.retained.contains_key(&topic), "missing {topic}");
+        }
+    }
+
+    #[tokio::test]
+    async fn publishes_state_changes() {
+        let mut broker = Broker::start().await;
+        let served = serve(broker.config());
+        let mut client = broker.accept().await;
+        broker.receive(&mut client, "medyia/state").await;
+
+        served.state.send_replace(playing());
+        broker.receive(&mut client, "medyia/state").await;
+
+        let state = broker.retained_json("medyia/state");
+        assert_eq!(state["state"], "playing");
+        assert_eq!(state["tab"], "youtube-abcdef");
+        assert_eq!(state["source"], "YouTube");
+        assert_eq!(state["title"], "Song");
+        assert_eq!(state["artist"], "Artist");
+    }
+
+    #[tokio::test]
+    async fn runs_commands_from_the_broker() {
+        let mut broker = Broker::start().await;
+        let mut served = serve(broker.config());
+        let mut client = broker.accept().await;
+        broker.receive(&mut client, "medyia/state").await;
+
+        client.send_command("volume", "1").await;
+        client.send_command("shuffle", "").await;
+        client.send_command("pause", "").await;
+
+        let mut received = Vec::new();
+        for _ in 0..2 {
+            let command = timeout(TIMEOUT, served.commands.recv()).await.unwrap();
+            received.push(command.unwrap());
+        }
+        assert_eq!(received, [Command::Volume(0.01), Command::Pause]);
+    }
+
+    #[tokio::test]
+    async fn reconnects_and_announces_again() {
+        let mut broker = Broker::start().await;
+        let served = serve(broker.config());
+        let mut client = broker.accept().await;
+        broker.receive(&mut client, "medyia/state").await;
+
+        drop(client);
+        broker.retained.clear();
+        broker.subscriptions.clear();
+        served.state.send_replace(playing());
+
+        let mut client = broker.accept().await;
+        broker.receive(&mut client, "medyia/availability").await;
+        assert_eq!(broker.subscriptions, ["medyia/command/#"]);
+        assert_eq!(broker.retained["medyia/availability"], b"online");
+
+        // whatever we couldn't send while we were away goes out once we're back
+        broker.receive(&mut client, "medyia/state").await;
+        assert_eq!(broker.retained_json("medyia/state")["state"], "playing");
+    }
+
+    #[tokio::test]
+    async fn goes_offline_when_config_changes() {
+        let mut broker = Broker::start().await;
+        let served = serve(broker.config());
+        let mut client = broker.accept().await;
+        broker.receive(&mut client, "medyia/state").await;
+
+        // we don't acknowledge anything here, we're gone by the time it would arrive
+        served.config.send_replace(MqttConfig::default());
+        let Packet::Publish(offline) = client.read().await else {
+            panic!("We didn't go offline");
+        };
+        assert_eq!(offline.topic, "medyia/availability");
+        assert_eq!(&offline.payload[..], b"offline");
+        assert!(offline.retain);
+        assert!(matches!(client.read().await, Packet::Disconnect));
+
+        timeout(TIMEOUT, served.task).await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,370 @@
+use crate::artwork;
+use crate::media_sources::MediaSource;
+use crate::state::EnhancerAppStateManager;
+use anyhow::{anyhow, bail};
+use log::debug;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager, Runtime};
+use tauri_plugin_store::StoreExt;
+
+const STORE_PATH: &str = "medyia-now-playing.json";
+const CONFIG_KEY: &str = "config";
+
+const JSON_FILE: &str = "now-playing.json";
+/// Streaming software sniffs image formats, so this name works whatever the artwork is
+const ARTWORK_FILE: &str = "artwork.png";
+const FIELDS: &[&str] = &["title", "artist", "source"];
+
+/// What we last exported, so we only write when it changes
+static LAST_EXPORT: Lazy<Mutex<Option<Option<NowPlaying>>>> = Lazy::new(|| Mutex::new(None));
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    pub directory: Option<PathBuf>,
+    #[serde(default = "default_templates")]
+    pub templates: Vec<TextTemplate>,
+    #[serde(default = "default_true")]
+    pub json: bool,
+    #[serde(default)]
+    pub artwork: bool,
+}
+
+/// A text file rendered from `template`, e.g. `{artist} - {title}`
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TextTemplate {
+    pub file_name: String,
+    pub template: String,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct NowPlaying {
+    source: MediaSource,
+    title: Option<String>,
+    artist: Option<String>,
+    #[serde(skip)]
+    artwork: Option<String>,
+}
+
+enum Segment<'a> {
+    Text(&'a str),
+    Field(&'a str),
+}
+
+/// Write out what's playing when it's changed, this runs whenever we emit our app state
+pub fn export<R: Runtime>(app: &impl Manager<R>) -> anyhow::Result<()> {
+    let config = get_config(app)?;
+    let Some(directory) = config.directory.as_deref().filter(|_| config.enabled) else {
+        return Ok(());
+    };
+
+    let now_playing = app.app_state(|state| {
+        state.playing_tab().map(|tab| NowPlaying {
+            source: tab.source,
+            title: tab.title.clone(),
+            artist: tab.artist.clone(),
+            artwork: tab.artwork.clone(),
+        })
+    });
+
+    let mut last = LAST_EXPORT.lock().unwrap();
+    if last.as_ref() == Some(&now_playing) {
+        return Ok(());
+    }
+    debug!("Exporting now playing: {now_playing:?}");
+
+    write_files(app, &config, directory, now_playing.as_ref())?;
+    *last = Some(now_playing);
+
+    Ok(())
+}
+
+pub fn get_config<R: Runtime>(app: &impl Manager<R>) -> anyhow::Result<ExportConfig> {
+    let store = app.store(STORE_PATH)?;
+
+    match store.get(CONFIG_KEY) {
+        Some(config) => Ok(serde_json::from_value(config)?),
+        None => Ok(ExportConfig::default()),
+    }
+}
+
+pub fn set_config(app: &AppHandle, config: ExportConfig) -> anyhow::Result<()> {
+    config.validate()?;
+
+    // clear out what we wrote under our old config
+    let previous = get_config(app)?;
+    if let Some(directory) = previous.directory.as_deref().filter(|_| previous.enabled) {
+        write_files(app, &previous, directory, None)?;
+    }
+
+    let store = app.store(STORE_PATH)?;
+    store.set(CONFIG_KEY, serde_json::to_value(config)?);
+    store.save()?;
+
+    *LAST_EXPORT.lock().unwrap() = None;
+    export(app)
+}
+
+/// Write every file we export, or clear them all when nothing is playing
+fn write_files<R: Runtime>(
+    app: &impl Manager<R>,
+    config: &ExportConfig,
+    directory: &Path,
+    now_playing: Option<&NowPlaying>,
+) -> anyhow::Result<()> {
+    fs::create_dir_all(directory)?;
+
+    for template in &config.templates {
+        let text = now_playing
+            .map(|n| render(&template.template, n))
+            .transpose()?
+            .unwrap_or_default();
+        write_atomic(&directory.join(&template.file_name), text.as_bytes())?;
+    }
+
+    if config.json {
+        // an empty object rather than an empty file, which JSON readers fail to parse
+        let json = match now_playing {
+            Some(now_playing) => serde_json::to_vec_pretty(now_playing)?,
+            None => b"{}".to_vec(),
+        };
+        write_atomic(&directory.join(JSON_FILE), &json)?;
+    }
+
+    if config.artwork {
+        let path = directory.join(ARTWORK_FILE);
+        let cached = now_playing
+            .and_then(|n| n.artwork.as_deref())
+            .and_then(|url| artwork::cached_file(app, url));
+        match cached {
+            Some(cached) => write_atomic(&path, &fs::read(cached)?)?,
+            None if path.exists() => fs::remove_file(&path)?,
+            None => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Write beside `path` first so readers never see a partial file
+fn write_atomic(path: &Path, contents: &[u8]) -> anyhow::Result<()> {
+    let partial = path.with_extension("partial");
+    fs::write(&partial, contents)?;
+    fs::rename(&partial, path)?;
+
+    Ok(())
+}
+
+fn render(template: &str, now_playing: &NowPlaying) -> anyhow::Result<String> {
+    Ok(parse(template)?
+        .into_iter()
+        .map(|segment| match segment {
+            Segment::Text(text) => text,
+            Segment::Field("title") => now_playing.title.as_deref().unwrap_or_default(),
+            Segment::Field("artist") => now_playing.artist.as_deref().unwrap_or_default(),
+            Segment::Field(_) => now_playing.source.name(),
+        })
+        .collect())
+}
+
+/// `{field}` is replaced, `{{` and `}}` are literal braces
+fn parse(template: &str) -> anyhow::Result<Vec<Segment<'_>>> {
+    let mut segments = Vec::new();
+    let mut rest = template;
+    while let Some(index) = rest.find(['{', '}']) {
+        segments.push(Segment::Text(&rest[..index]));
+        let brace = &rest[index..index + 1];
+        rest = &rest[index + 1..];
+
+        if let Some(escaped) = rest.strip_prefix(brace) {
+            segments.push(Segment::Text(brace));
+            rest = escaped;
+        } else if brace == "{" {
+            let end = rest
+                .find('}')
+                .ok_or_else(|| anyhow!("Unclosed {{ in {template:?}"))?;
+            let field = &rest[..end];
+            if !FIELDS.contains(&field) {
+                bail!("Unknown field {{{field}}} in {template:?}, expected one of {FIELDS:?}");
+            }
+            segments.push(Segment::Field(field));
+            rest = &rest[end + 1..];
+        } else {
+            bail!("Unmatched }} in {template:?}");
+        }
+    }
+    segments.push(Segment::Text(rest));
+
+    Ok(segments)
+}
+
+impl ExportConfig {
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.enabled && self.directory.is_none() {
+            bail!("Choose a directory to export to");
+        }
+        // some file systems ignore case, so our names can't differ only by it
+        let mut names = BTreeSet::from([JSON_FILE.to_lowercase(), ARTWORK_FILE.to_lowercase()]);
+        for template in &self.templates {
+            let name = Path::new(&template.file_name);
+            // we only write directly into our directory
+            if name.file_name() != Some(name.as_os_str()) {
+                bail!("Invalid file name {:?}", template.file_name);
+            }
+            if !names.insert(template.file_name.to_lowercase()) {
+                bail!("{:?} is already one of our files", template.file_name);
+            }
+            parse(&template.template)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            directory: None,
+            templates: default_templates(),
+            json: true,
+            artwork: false,
+        }
+    }
+}
+
+fn default_templates() -> Vec<TextTemplate> {
+    vec![TextTemplate {
+        file_name: "now-playing.txt".to_string(),
+        template: "{artist} - {title}".to_string(),
+    }]
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tauri::test::{mock_builder, mock_context, noop_assets};
+
+    fn config(file_names: &[&str]) -> ExportConfig {
+        ExportConfig {
+            enabled: true,
+            directory: Some(PathBuf::from("overlay")),
+            templates: file_names
+                .iter()
+                .map(|file_name| TextTemplate {
+                    file_name: file_name.to_string(),
+                    template: "{artist} - {title}".to_string(),
+                })
+                .collect(),
+            json: true,
+            artwork: true,
+        }
+    }
+
+    fn now_playing() -> NowPlaying {
+        NowPlaying {
+            source: MediaSource::YouTube,
+            title: Some("Title".to_string()),
+            artist: Some("Artist".to_string()),
+            artwork: None,
+        }
+    }
+
+    #[test]
+    fn validate_cases() {
+        let cases: [(&[&str], bool); 9] = [
+            (&["now-playing.txt"], true),
+            (&["title.txt", "artist.txt"], true),
+            (&[], true),
+            (&["title.txt", "title.txt"], false),
+            (&["Title.txt", "title.txt"], false),
+            (&["now-playing.json"], false),
+            (&["artwork.png"], false),
+            (&["Artwork.PNG"], false),
+            (&["../title.txt"], false),
+        ];
+
+        for (file_names, valid) in cases {
+            assert_eq!(
+                config(file_names).validate().is_ok(),
+                valid,
+                "{file_names:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn enabled_needs_a_directory() {
+        let mut config = config(&[]);
+        config.directory = None;
+        assert!(config.validate().is_err());
+
+        config.enabled = false;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn render_cases() {
+        let cases = [
+            ("{artist} - {title}", Some("Artist - Title")),
+            ("{title} on {source}", Some("Title on YouTube")),
+            ("{{{title}}}", Some("{Title}")),
+            ("no fields", Some("no fields")),
+            ("{album}", None),
+            ("{title", None),
+            ("title}", None),
+        ];
+
+        for (template, expected) in cases {
+            assert_eq!(
+                render(template, &now_playing()).ok().as_deref(),
+                expected,
+                "{template}"
+            );
+        }
+    }
+
+    #[test]
+    fn clearing_writes_files_readers_can_parse() {
+        let app = mock_builder().build(mock_context(noop_assets())).unwrap();
+        let directory =
+            std::env::temp_dir().join(format!("medyia-now-playing-{}", std::process::id()));
+        let config = ExportConfig {
+            artwork: false,
+            ..config(&["now-playing.txt"])
+        };
+
+        write_files(&app, &config, &directory, Some(&now_playing())).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_slice(&fs::read(directory.join(JSON_FILE)).unwrap()).unwrap();
+        assert_eq!(json["title"], "Title");
+        assert_eq!(
+            fs::read_to_string(directory.join("now-playing.txt")).unwrap(),
+            "Artist - Title"
+        );
+
+        write_files(&app, &config, &directory, None).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_slice(&fs::read(directory.join(JSON_FILE)).unwrap()).unwrap();
+        assert_eq!(json, serde_json::json!({}));
+        assert_eq!(
+            fs::read_to_string(directory.join("now-playing.txt")).unwrap(),
+            ""
+        );
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,91 @@
+use crate::controls::{EnterPictureInPicture, ExitPictureInPicture};
+use crate::state::{EnhancerAppStateManager, EnhancerAppStateManagerEmitter, TabKeyRef};
+use crate::{EnhancedManager, EnhancedResult, rpc, webview_manager};
+use anyhow::anyhow;
+use log::info;
+use tauri::{AppHandle, LogicalSize, WindowBuilder, WindowEvent};
+
+pub const PICTURE_IN_PICTURE_WINDOW: &str = "PICTURE_IN_PICTURE";
+
+const DEFAULT_SIZE: LogicalSize<f64> = LogicalSize::new(480., 270.);
+const MIN_SIZE: LogicalSize<f64> = LogicalSize::new(240., 135.);
+
+/// Float `key` in an always-on-top window showing only its video, any tab already floating returns first
+///
+/// We move the tab's webview into our own window rather than use the page's Picture-in-Picture API,
+/// which not every platform's webview has
+pub async fn enter(app: &AppHandle, key: TabKeyRef<'_>) -> anyhow::Result<()> {
+    exit(app)?;
+
+    // the page tells us if it doesn't have a video to float
+    rpc::call(app, key, EnterPictureInPicture).await?;
+
+    if let Err(e) = float(app, key) {
+        // the tab stays in our main window, which shouldn't show only its video
+        rpc::call(app, key, ExitPictureInPicture).await.log_warn();
+        return Err(e);
+    }
+    info!("Floating {key} in picture-in-picture");
+
+    Ok(())
+}
+
+/// Build our picture-in-picture window and move `key` into it
+fn float(app: &AppHandle, key: TabKeyRef<'_>) -> anyhow::Result<()> {
+    let title = app
+        .app_state(|state| state.tab(key).map(|t| t.display_name.clone()))
+        .ok_or_else(|| anyhow!("No tab found for {key}"))?;
+    let window = WindowBuilder::new(app, PICTURE_IN_PICTURE_WINDOW)
+        .title(title)
+        .inner_size(DEFAULT_SIZE.width, DEFAULT_SIZE.height)
+        .min_inner_size(MIN_SIZE.width, MIN_SIZE.height)
+        .always_on_top(true)
+        .build()?;
+
+    let app_handle = app.clone();
+    window.on_window_event(move |event| match event {
+        WindowEvent::Resized(_) | WindowEvent::ScaleFactorChanged { .. } => {
+            webview_manager::relayout(&app_handle).log_error();
+        }
+        WindowEvent::CloseRequested { api, .. } => {
+            // closing our window returns the tab, which closes our window once it's empty
+            api.prevent_close();
+            exit(&app_handle).log_error();
+        }
+        _ => {}
+    });
+
+    if let Err(e) = app.app_state_mut(|state| state.detach_tab(key, &window, app)) {
+        window.destroy().log_error();
+        return Err(e);
+    }
+
+    Ok(())
+}
+
+/// Return our floating tab to our main window
+pub fn exit(app: &AppHandle) -> anyhow::Result<()> {
+    if let Some(key) = app.app_state_mut(|state| state.attach_tab(app))? {
+        info!("Returned {key} from picture-in-picture");
+        app.main_window().set_focus()?;
+    }
+
+    Ok(())
+}
+
+/// Float the playing tab, or our active one, unless a tab is already floating in which case we return it
+pub async fn toggle(app: &AppHandle) -> anyhow::Result<()> {
+    if app.app_state(|state| state.detached_tab_key()).is_some() {
+        return exit(app);
+    }
+
+    let key = app
+        .app_state(|state| {
+            state
+                .playing_tab()
+                .map(|t| t.key.clone())
+                .or_else(|| state.active_tab_key())
+        })
+        .ok_or_else(|| anyhow!("No tab to float"))?;
+    enter(app, &key).await
+}
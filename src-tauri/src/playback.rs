@@ -1,16 +1,70 @@
+use crate::EnhancedResult;
+use crate::artwork;
+use crate::discord;
+use crate::history;
+use crate::hooks::{self, HookEvent};
+use crate::metadata;
+use crate::scrobbler;
+use crate::state::{EnhancerAppStateManager, EnhancerAppStateManagerEmitter, TabKey, TabKeyRef};
 use log::{debug, error, info, warn};
-use serde::Deserialize;
-use tauri::{AppHandle, Emitter, Listener};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Listener, Runtime};
+use tokio::time::sleep;
+
+const RESUME_DELAY: Duration = Duration::from_secs(5);
+
+static NEXT_RESUME: AtomicU64 = AtomicU64::new(0);
+/// The latest resume each paused tab scheduled, anything older has been superseded
+static PENDING_RESUMES: Lazy<Mutex<HashMap<TabKey, u64>>> = Lazy::new(Mutex::default);
 
 #[derive(Debug, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct PlaybackEvent {
     pub label: String,
     pub playing: bool,
+    /// We stopped because we reached the end rather than being paused
+    #[serde(default)]
+    pub ended: bool,
+    /// Playing, not muted and with a non-zero volume
+    pub audible: bool,
+    /// Whether the page has applied our tab mute
+    pub muted: bool,
     pub title: Option<String>,
     pub artist: Option<String>,
-    #[allow(dead_code)]
     pub artwork_url: Option<String>,
+    /// Where the page is now, which may have moved on from where the tab started
+    #[serde(default)]
+    pub url: Option<String>,
+    /// In seconds, once the page knows it
+    #[serde(default)]
+    pub duration: Option<f64>,
+}
+
+/// How we treat a tab that starts playing while another one is already playing
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(tag = "mode", rename_all = "camelCase")]
+pub enum PlaybackPolicy {
+    /// Pause everything else
+    #[default]
+    Exclusive,
+    /// Leave everything else playing
+    AllowConcurrent,
+    /// Lower the volume of everything else by `percent` until the new tab stops
+    DuckOthers { percent: u8 },
+}
+
+impl PlaybackPolicy {
+    pub fn duck_factor(self) -> Option<f64> {
+        match self {
+            PlaybackPolicy::DuckOthers { percent } => Some(1. - f64::from(percent.min(100)) / 100.),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -45,65 +99,113 @@ pub fn setup_playback_listener(app: &AppHandle) {
     });
 }
 
-fn handle_playback_event(_app: &AppHandle, _event: PlaybackEvent) {
-    todo!()
-    // info!(
-    //     "Playback event: label={}, playing={}, title={:?}",
-    //     event.label, event.playing, event.title
-    // );
-    // let state_mutex = app.state::<Mutex<TabsState>>();
-    //
-    // if event.playing {
-    //     // Pause all other tabs
-    //     let labels_to_pause: Vec<String> = {
-    //         let state = state_mutex.lock().unwrap();
-    //         state
-    //             .tabs
-    //             .keys()
-    //             .filter(|l| **l != event.label)
-    //             .cloned()
-    //             .collect()
-    //     };
-    //
-    //     for label in &labels_to_pause {
-    //         debug!("Pausing tab: {}", label);
-    //         if let Some(wv) = app.get_webview(label) {
-    //             let _ = wv.eval(
-    //                 "document.querySelectorAll('video, audio').forEach(el => { if (!el.paused) el.pause(); });",
-    //             );
-    //         }
-    //     }
-    //
-    //     // Update state
-    //     {
-    //         let mut state = state_mutex.lock().unwrap();
-    //         for (label, tab) in state.tabs.iter_mut() {
-    //             tab.is_playing = *label == event.label;
-    //         }
-    //         state.playing_tab_key = Some(event.label.clone());
-    //         debug!("State updated: playing_tab={}", event.label);
-    //     }
-    //
-    //     // Update system now-playing
-    //     media_bridge::update_now_playing(event.title.as_deref(), event.artist.as_deref(), true);
-    // } else {
-    //     // Paused
-    //     {
-    //         let mut state = state_mutex.lock().unwrap();
-    //         if let Some(tab) = state.tabs.get_mut(&event.label) {
-    //             tab.is_playing = false;
-    //         }
-    //         if state.playing_tab_key.as_deref() == Some(&event.label) {
-    //             state.playing_tab_key = None;
-    //         }
-    //         debug!("State updated: {} paused", event.label);
-    //     }
-    //
-    //     media_bridge::update_now_playing(event.title.as_deref(), event.artist.as_deref(), false);
-    // }
-    //
-    // // Notify shell webview to refresh tab UI
-    // notify_shell(app);
+fn handle_playback_event(app: &AppHandle, event: PlaybackEvent) {
+    let event = metadata::normalize_event(app, event);
+    info!(
+        "Playback event: label={}, playing={}, audible={}, title={:?}",
+        event.label, event.playing, event.audible, event.title
+    );
+
+    let previous = app.app_state(|state| {
+        state
+            .tab(&event.label)
+            .map(|t| (t.is_playing(), t.title.clone(), t.artist.clone()))
+    });
+    app.app_state_mut(|state| state.update_playback(&event))
+        .log_error();
+    if let Some((was_playing, title, artist)) = previous {
+        if event.title.is_some() && (event.title != title || event.artist != artist) {
+            hooks::fire(app, HookEvent::TrackChanged, &event.label);
+        }
+        if event.playing != was_playing {
+            let hook_event = if event.playing {
+                HookEvent::Play
+            } else {
+                HookEvent::Pause
+            };
+            hooks::fire(app, hook_event, &event.label);
+        }
+    }
+    history::record_playback(app, &event).log_error();
+    scrobbler::record_playback(app, &event).log_error();
+    discord::update_presence(app);
+    if let Some(url) = &event.artwork_url {
+        cache_artwork(app, event.label.clone(), url.clone());
+    }
+
+    if event.playing {
+        cancel_resume(&event.label);
+    } else if app.app_state(|state| state.has_interrupted(&event.label)) {
+        schedule_resume(app, event.label);
+    }
+}
+
+/// Swap the page's artwork for our cached copy once we have it
+fn cache_artwork(app: &AppHandle, label: String, url: String) {
+    let cached = artwork::artwork_url(&url);
+    let current = app.app_state(|state| {
+        state
+            .tab(&label)
+            .is_some_and(|t| t.artwork.as_deref() == Some(cached.as_str()))
+    });
+    if current {
+        return;
+    }
+
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        match artwork::cache(&app, &url).await {
+            Ok(Some(cached)) => app
+                .app_state_mut(|state| {
+                    state.set_cached_artwork(&label, &url, cached);
+                    Ok(())
+                })
+                .log_error(),
+            Ok(None) => {}
+            Err(e) => debug!("Couldn't cache artwork {url}: {e}"),
+        }
+    });
+}
+
+/// Give a paused tab a moment to start again before resuming whatever it interrupted
+fn schedule_resume(app: &AppHandle, label: TabKey) {
+    debug!("Resuming tabs interrupted by {label} in {RESUME_DELAY:?}");
+
+    let resume = start_resume(&label);
+    tauri::async_runtime::spawn(resume_after_delay(app.clone(), label, resume));
+}
+
+/// Supersede any resume `label` already has pending
+fn start_resume(label: TabKeyRef) -> u64 {
+    let resume = NEXT_RESUME.fetch_add(1, Ordering::Relaxed);
+    PENDING_RESUMES
+        .lock()
+        .unwrap()
+        .insert(label.to_string(), resume);
+    resume
+}
+
+/// The tab is playing again, so it's no longer done with whatever it interrupted
+fn cancel_resume(label: TabKeyRef) {
+    PENDING_RESUMES.lock().unwrap().remove(label);
+}
+
+async fn resume_after_delay<R: Runtime>(app: AppHandle<R>, label: TabKey, resume: u64) {
+    sleep(RESUME_DELAY).await;
+
+    {
+        let mut pending = PENDING_RESUMES.lock().unwrap();
+        if pending.get(&label) != Some(&resume) {
+            return;
+        }
+        pending.remove(&label);
+    }
+
+    app.app_state_mut(|state| {
+        state.resume_interrupted(&label);
+        Ok(())
+    })
+    .log_error();
 }
 
 fn handle_title_changed(_app: &AppHandle, _event: TitleChangedEvent) {
@@ -142,3 +244,65 @@ fn notify_shell(app: &AppHandle) {
         Err(e) => warn!("Failed to emit playback-changed: {}", e),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::media_sources::MediaSource;
+    use crate::state::AppState;
+    use tauri::test::{MockRuntime, mock_builder, mock_context, noop_assets};
+
+    fn event(label: &str, playing: bool) -> PlaybackEvent {
+        PlaybackEvent {
+            label: label.to_string(),
+            playing,
+            ended: false,
+            audible: playing,
+            muted: false,
+            title: None,
+            artist: None,
+            artwork_url: None,
+            url: None,
+            duration: None,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn only_the_latest_pause_resumes() {
+        let app = mock_builder()
+            .plugin(tauri_plugin_store::Builder::new().build())
+            .manage(AppState::<MockRuntime>::new())
+            .build(mock_context(noop_assets()))
+            .unwrap();
+        let handle = app.handle();
+        let (first, second) = handle
+            .app_state_mut(|state| {
+                let first = state.create_tab(MediaSource::YouTube, None)?;
+                let second = state.create_tab(MediaSource::SoundCloud, None)?;
+                state.update_playback(&event(&first, true))?;
+                state.update_playback(&event(&second, true))?;
+                state.update_playback(&event(&second, false))?;
+                Ok((first, second))
+            })
+            .unwrap();
+        let interrupted = || handle.app_state(|state| state.has_interrupted(&second));
+        let pause = || {
+            let resume = start_resume(&second);
+            tokio::spawn(resume_after_delay(handle.clone(), second.clone(), resume))
+        };
+
+        // pause, play for a moment, then pause again
+        let stale = pause();
+        sleep(Duration::from_secs(3)).await;
+        cancel_resume(&second);
+        let latest = pause();
+
+        // the first pause's resume is due, but the second pause hasn't waited long enough
+        sleep(Duration::from_secs(3)).await;
+        stale.await.unwrap();
+        assert!(interrupted(), "{second} resumed {first} too early");
+
+        latest.await.unwrap();
+        assert!(!interrupted(), "{second} never resumed {first}");
+    }
+}
@@ -0,0 +1,320 @@
+use crate::state::{EnhancerAppStateManager, TabKey, TabKeyRef};
+use log::{debug, error, warn};
+use once_cell::sync::Lazy;
+use rand::distr::{Alphanumeric, SampleString};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter};
+use std::marker::PhantomData;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use std::{error, fmt};
+use tauri::{AppHandle, Listener, Runtime, Webview};
+use tokio::sync::oneshot;
+use tokio::time;
+
+const RPC_REPLY_EVENT: &str = "rpc-reply";
+pub const DEFAULT_RPC_TIMEOUT: Duration = Duration::from_secs(2);
+
+static NEXT_RPC_ID: AtomicU64 = AtomicU64::new(0);
+static PENDING_RPCS: Lazy<Mutex<HashMap<u64, PendingEntry>>> = Lazy::new(Mutex::default);
+
+/// A request handled by one of the `window.__medyia_handlers` in the injected page scripts
+pub trait RpcRequest: Serialize + Debug {
+    const METHOD: &'static str;
+    const TIMEOUT: Duration = DEFAULT_RPC_TIMEOUT;
+
+    type Response: DeserializeOwned;
+}
+
+#[derive(Debug)]
+pub enum RpcError {
+    /// The tab doesn't have a webview to talk to
+    NotLoaded(TabKey),
+    Send(tauri::Error),
+    Encode(serde_json::Error),
+    Timeout(Duration),
+    /// The tab was unloaded before it replied
+    Closed,
+    /// The page script threw or rejected
+    Remote(String),
+    Decode(serde_json::Error),
+}
+
+#[derive(Deserialize, Debug)]
+struct RpcReply {
+    id: u64,
+    /// The webview that answered, it has to be the one we asked
+    label: String,
+    /// Only the page we sent the request to knows this, so other pages can't answer for it
+    token: String,
+    ok: bool,
+    #[serde(default)]
+    value: Value,
+    error: Option<String>,
+}
+
+struct PendingEntry {
+    label: String,
+    token: String,
+    sender: oneshot::Sender<RpcReply>,
+}
+
+/// The reply to a request we've sent, dropping this forgets the request
+pub struct PendingRpc<T> {
+    id: u64,
+    timeout: Duration,
+    receiver: oneshot::Receiver<RpcReply>,
+    _response: PhantomData<T>,
+}
+
+pub fn setup_rpc_listener(app: &AppHandle) {
+    app.listen(RPC_REPLY_EVENT, |event| {
+        let payload = event.payload();
+        match serde_json::from_str::<RpcReply>(payload) {
+            Ok(reply) => resolve(reply),
+            Err(e) => error!("Failed to parse rpc reply: {e} — payload: {payload}"),
+        }
+    });
+}
+
+/// Hand `reply` to the request it answers, as long as it came from the page we asked
+fn resolve(reply: RpcReply) {
+    let mut pending = PENDING_RPCS.lock().unwrap();
+    let Some(entry) = pending.get(&reply.id) else {
+        return;
+    };
+    if entry.label != reply.label || entry.token != reply.token {
+        warn!(
+            "Dropping rpc reply {} from {}, it was sent to {}",
+            reply.id, reply.label, entry.label
+        );
+        return;
+    }
+
+    if let Some(entry) = pending.remove(&reply.id) {
+        // the caller may have already given up on us
+        let _ = entry.sender.send(reply);
+    }
+}
+
+/// Start waiting on a reply from the webview with `label`, returning the token it has to send back
+fn register<T>(label: &str, timeout: Duration) -> (PendingRpc<T>, String) {
+    let id = NEXT_RPC_ID.fetch_add(1, Ordering::Relaxed);
+    let token = Alphanumeric.sample_string(&mut rand::rng(), 32);
+    let (sender, receiver) = oneshot::channel();
+    PENDING_RPCS.lock().unwrap().insert(
+        id,
+        PendingEntry {
+            label: label.to_string(),
+            token: token.clone(),
+            sender,
+        },
+    );
+
+    let pending = PendingRpc {
+        id,
+        timeout,
+        receiver,
+        _response: PhantomData,
+    };
+    (pending, token)
+}
+
+/// Send `request` to the tab with `key` and wait for its typed reply
+pub async fn call<Req: RpcRequest>(
+    app: &AppHandle,
+    key: TabKeyRef<'_>,
+    request: Req,
+) -> anyhow::Result<Req::Response> {
+    let pending = app.app_state(|state| match state.tab(key) {
+        Some(tab) => tab.request(&request),
+        None => Err(RpcError::NotLoaded(key.to_string())),
+    })?;
+
+    debug!("{key} Tab -> {request:?}");
+
+    Ok(pending.reply().await?)
+}
+
+pub fn send<Req: RpcRequest, R: Runtime>(
+    webview: &Webview<R>,
+    request: &Req,
+) -> Result<PendingRpc<Req::Response>, RpcError> {
+    let params = serde_json::to_string(request).map_err(RpcError::Encode)?;
+    let (pending, token) = register(webview.label(), Req::TIMEOUT);
+
+    webview
+        .eval(format!(
+            "window.__medyia_rpc?.({}, '{token}', '{}', {params});",
+            pending.id,
+            Req::METHOD
+        ))
+        .map_err(RpcError::Send)?;
+
+    Ok(pending)
+}
+
+/// Fail every request still waiting on the webview with `label`
+pub fn cancel_pending(label: &str) {
+    PENDING_RPCS
+        .lock()
+        .unwrap()
+        .retain(|_, entry| entry.label != label);
+}
+
+impl<T: DeserializeOwned> PendingRpc<T> {
+    pub async fn reply(mut self) -> Result<T, RpcError> {
+        let reply = match time::timeout(self.timeout, &mut self.receiver).await {
+            Ok(Ok(reply)) => reply,
+            Ok(Err(_)) => return Err(RpcError::Closed),
+            Err(_) => return Err(RpcError::Timeout(self.timeout)),
+        };
+
+        if reply.ok {
+            serde_json::from_value(reply.value).map_err(RpcError::Decode)
+        } else {
+            Err(RpcError::Remote(
+                reply
+                    .error
+                    .unwrap_or_else(|| format!("Request {} failed", reply.id)),
+            ))
+        }
+    }
+}
+
+impl<T> Drop for PendingRpc<T> {
+    fn drop(&mut self) {
+        PENDING_RPCS.lock().unwrap().remove(&self.id);
+    }
+}
+
+impl Display for RpcError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RpcError::NotLoaded(key) => write!(f, "Tab {key} isn't loaded"),
+            RpcError::Send(e) => write!(f, "Couldn't send request: {e}"),
+            RpcError::Encode(e) => write!(f, "Couldn't encode request: {e}"),
+            RpcError::Timeout(timeout) => write!(f, "Request timed out after {timeout:?}"),
+            RpcError::Closed => write!(f, "Tab was unloaded before replying"),
+            RpcError::Remote(e) => write!(f, "Page script failed: {e}"),
+            RpcError::Decode(e) => write!(f, "Couldn't decode reply: {e}"),
+        }
+    }
+}
+
+impl error::Error for RpcError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            RpcError::Send(e) => Some(e),
+            RpcError::Encode(e) | RpcError::Decode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn reply(id: u64, label: &str, token: &str, ok: bool, value: Value) -> RpcReply {
+        RpcReply {
+            id,
+            label: label.to_string(),
+            token: token.to_string(),
+            ok,
+            value,
+            error: (!ok).then(|| "No media element found".to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn matched_reply_resolves() {
+        let (pending, token) = register::<f64>("youtube-matched", DEFAULT_RPC_TIMEOUT);
+        resolve(reply(
+            pending.id,
+            "youtube-matched",
+            &token,
+            true,
+            json!(12.5),
+        ));
+
+        assert_eq!(pending.reply().await.unwrap(), 12.5);
+    }
+
+    #[tokio::test]
+    async fn page_error_is_propagated() {
+        let (pending, token) = register::<()>("youtube-error", DEFAULT_RPC_TIMEOUT);
+        resolve(reply(
+            pending.id,
+            "youtube-error",
+            &token,
+            false,
+            Value::Null,
+        ));
+
+        match pending.reply().await {
+            Err(RpcError::Remote(e)) => assert_eq!(e, "No media element found"),
+            other => panic!("expected a remote error, got {other:?}"),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn unanswered_request_times_out() {
+        let (pending, _) = register::<()>("youtube-timeout", DEFAULT_RPC_TIMEOUT);
+        let id = pending.id;
+
+        match pending.reply().await {
+            Err(RpcError::Timeout(timeout)) => assert_eq!(timeout, DEFAULT_RPC_TIMEOUT),
+            other => panic!("expected a timeout, got {other:?}"),
+        }
+        assert!(!PENDING_RPCS.lock().unwrap().contains_key(&id));
+    }
+
+    #[tokio::test]
+    async fn unloading_cancels_pending_requests() {
+        let (pending, _) = register::<()>("youtube-unloaded", DEFAULT_RPC_TIMEOUT);
+        let (other, other_token) = register::<()>("youtube-still-loaded", DEFAULT_RPC_TIMEOUT);
+        cancel_pending("youtube-unloaded");
+
+        assert!(matches!(pending.reply().await, Err(RpcError::Closed)));
+
+        resolve(reply(
+            other.id,
+            "youtube-still-loaded",
+            &other_token,
+            true,
+            Value::Null,
+        ));
+        assert!(other.reply().await.is_ok());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn replies_from_other_pages_are_dropped() {
+        let cases = [
+            // another page answering with the real token
+            ("soundcloud-forger", true),
+            // the right label but a guessed token
+            ("youtube-forged", false),
+        ];
+
+        for (label, real_token) in cases {
+            let (pending, token) = register::<String>("youtube-forged", DEFAULT_RPC_TIMEOUT);
+            let token = if real_token {
+                token
+            } else {
+                "guess".to_string()
+            };
+            resolve(reply(pending.id, label, &token, true, json!("forged")));
+
+            assert!(
+                matches!(pending.reply().await, Err(RpcError::Timeout(_))),
+                "{label} shouldn't be able to answer"
+            );
+        }
+    }
+}
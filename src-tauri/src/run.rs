@@ -1,8 +1,9 @@
 use crate::state::{AppState, EnhancerAppStateManagerEmitter};
 use crate::utils::EnhancedWindow;
 use crate::{
-    EnhancedManager, EnhancedResult, MAIN_WEBVIEW, MAIN_WINDOW, commands, memory, playback,
-    session, webview_manager,
+    EnhancedManager, EnhancedResult, MAIN_WEBVIEW, MAIN_WINDOW, artwork, commands, discord, focus,
+    fullscreen, history, media_bridge, memory, mini_player, mqtt, picture_in_picture, playback,
+    rpc, scheduler, scrobbler, session, settings, sleep_timer, webview_manager,
 };
 use std::time::Duration;
 use tauri::menu::{MenuBuilder, MenuItem, SubmenuBuilder};
@@ -13,14 +14,18 @@ use tokio::time::sleep;
 
 const CLOSE_TAB_KEY: &str = "CLOSE_TAB";
 const TOGGLE_DEVTOOLS_KEY: &str = "TOGGLE_DEVTOOLS";
+const MINI_PLAYER_KEY: &str = "MINI_PLAYER";
+const PICTURE_IN_PICTURE_KEY: &str = "PICTURE_IN_PICTURE";
 
 #[cfg_attr(mobile, mobile_entry_point)]
 pub fn run() {
     Builder::default()
+        .register_asynchronous_uri_scheme_protocol(artwork::SCHEME, artwork::handle_request)
         .plugin(tauri_plugin_store::Builder::default().build())
         .plugin(
             tauri_plugin_log::Builder::new()
-                .level(log::LevelFilter::Debug)
+                // we filter with our log level setting instead
+                .level(log::LevelFilter::Trace)
                 .build(),
         )
         .manage(AppState::<Wry>::new())
@@ -29,8 +34,61 @@ pub fn run() {
             commands::switch_source,
             commands::switch_tab,
             commands::close_tab,
+            commands::play_tab,
+            commands::pause_tab,
+            commands::next_track,
+            commands::previous_track,
+            commands::get_position,
             commands::get_sources,
+            commands::get_all_sources,
+            commands::show_home,
             commands::emit_backend_state,
+            commands::seek,
+            commands::seek_relative,
+            commands::set_volume,
+            commands::set_muted,
+            commands::set_rate,
+            commands::get_tab_metadata,
+            commands::toggle_tab_mute,
+            commands::set_restore_muted,
+            commands::set_playback_policy,
+            commands::set_auto_resume,
+            commands::start_sleep_timer,
+            commands::extend_sleep_timer,
+            commands::cancel_sleep_timer,
+            commands::get_schedules,
+            commands::add_schedule,
+            commands::remove_schedule,
+            commands::set_schedule_enabled,
+            commands::get_hooks,
+            commands::add_hook,
+            commands::remove_hook,
+            commands::set_hook_enabled,
+            commands::start_focus_session,
+            commands::stop_focus_session,
+            commands::query_history,
+            commands::export_history,
+            commands::get_history_retention,
+            commands::set_history_retention,
+            commands::get_stats,
+            commands::get_scrobbler_config,
+            commands::set_scrobble_sources,
+            commands::connect_last_fm,
+            commands::connect_listen_brainz,
+            commands::disconnect_scrobbler,
+            commands::get_metadata_rules,
+            commands::set_metadata_rules,
+            commands::get_discord_config,
+            commands::set_discord_config,
+            commands::get_mqtt_config,
+            commands::set_mqtt_config,
+            commands::get_settings,
+            commands::update_settings,
+            commands::set_mini_player,
+            commands::enter_picture_in_picture,
+            commands::exit_picture_in_picture,
+            commands::get_now_playing_export,
+            commands::set_now_playing_export,
         ])
         .setup(|app| {
             let app_menu = SubmenuBuilder::new(app, "App")
@@ -72,6 +130,21 @@ pub fn run() {
                 .minimize()
                 .maximize()
                 .fullscreen()
+                .separator()
+                .item(&MenuItem::with_id(
+                    app,
+                    MINI_PLAYER_KEY,
+                    "Mini Player",
+                    true,
+                    Some("cmd+shift+m"),
+                )?)
+                .item(&MenuItem::with_id(
+                    app,
+                    PICTURE_IN_PICTURE_KEY,
+                    "Picture in Picture",
+                    true,
+                    Some("cmd+shift+p"),
+                )?)
                 .build()?;
 
             let menu = MenuBuilder::new(app)
@@ -83,11 +156,22 @@ pub fn run() {
             app.set_menu(menu)?;
 
             // Create window programmatically (no config-created WebviewWindow)
-            let window = WindowBuilder::new(app, MAIN_WINDOW)
+            let placement = session::window_placement(app.handle());
+            let size = placement.map_or(session::DEFAULT_WINDOW_SIZE, |p| p.size);
+            let mut window = WindowBuilder::new(app, MAIN_WINDOW)
                 .title("Medyia")
-                .inner_size(1400.0, 1000.0)
-                .min_inner_size(800.0, 600.0)
-                .build()?;
+                .inner_size(size.width, size.height)
+                .min_inner_size(
+                    session::MIN_WINDOW_SIZE.width,
+                    session::MIN_WINDOW_SIZE.height,
+                );
+            if let Some(placement) = placement {
+                window = window.position(placement.position.x, placement.position.y);
+            }
+            let window = window.build()?;
+            if let Some(placement) = placement {
+                placement.apply(&window).log_error();
+            }
 
             let _main_view = window.add_child(
                 WebviewBuilder::new(MAIN_WEBVIEW, WebviewUrl::App("index.html".into()))
@@ -97,10 +181,24 @@ pub fn run() {
             )?;
 
             let handle = app.handle();
+            settings::setup_settings(handle);
             playback::setup_playback_listener(handle);
-            // media_bridge::setup_media_keys(&handle).log_error();
+            fullscreen::setup_fullscreen_listener(handle);
+            rpc::setup_rpc_listener(handle);
+            media_bridge::setup_media_keys(handle).log_error();
             memory::start_memory_monitor(handle.clone());
+            sleep_timer::start_sleep_timer_monitor(handle.clone());
+            scheduler::start_scheduler(handle.clone());
+            artwork::setup_artwork_cache(handle).log_error();
+            history::setup_history(handle).log_error();
+            scrobbler::setup_scrobbler(handle);
+            discord::setup_discord(handle).log_error();
+            mqtt::setup_mqtt(handle).log_error();
+            focus::restore_focus_history(handle).log_error();
+            focus::start_focus_monitor(handle.clone());
             session::restore_session(handle).log_error();
+            // line our tabs up with wherever we restored our window to
+            webview_manager::relayout(handle).log_error();
             Ok(())
         })
         .on_menu_event(|app, event| match event.id().as_ref() {
@@ -108,6 +206,15 @@ pub fn run() {
                 app.app_state_mut(|state| state.close_active_tab(app))
                     .log_error();
             }
+            MINI_PLAYER_KEY => {
+                mini_player::toggle_mini_player(app).log_error();
+            }
+            PICTURE_IN_PICTURE_KEY => {
+                let app = app.clone();
+                tauri::async_runtime::spawn(async move {
+                    picture_in_picture::toggle(&app).await.log_error();
+                });
+            }
             #[cfg(debug_assertions)]
             TOGGLE_DEVTOOLS_KEY => {
                 let main_webview = app.main_webview();
@@ -129,7 +236,13 @@ pub fn run() {
             _ => (),
         })
         .on_window_event(|window, event| match event {
-            WindowEvent::Resized(_) | WindowEvent::ScaleFactorChanged { .. } => {
+            // our other windows handle their own events
+            _ if window.label() != MAIN_WINDOW => {}
+            WindowEvent::Resized(_) => {
+                fullscreen::on_window_resized(window.app_handle());
+                webview_manager::relayout(window.app_handle()).log_error();
+            }
+            WindowEvent::ScaleFactorChanged { .. } => {
                 webview_manager::relayout(window.app_handle()).log_error();
             }
             WindowEvent::CloseRequested { .. } => {
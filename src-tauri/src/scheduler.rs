@@ -0,0 +1,408 @@
+use crate::EnhancedResult;
+use crate::media_sources::MediaSource;
+use crate::state::EnhancerAppStateManagerEmitter;
+use crate::webview_manager;
+use anyhow::anyhow;
+use chrono::{DateTime, Local, TimeDelta, TimeZone, Utc};
+use chrono_tz::Tz;
+use log::{info, warn};
+use rand::distr::{Alphanumeric, SampleString};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::time::Duration;
+use tauri::AppHandle;
+use tauri_plugin_store::StoreExt;
+use tokio::time;
+
+const STORE_PATH: &str = "medyia-schedules.json";
+const SCHEDULES_KEY: &str = "schedules";
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// Runs we missed by more than this (we were asleep) are skipped rather than run late
+const MISSED_RUN_GRACE: TimeDelta = TimeDelta::minutes(15);
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Schedule {
+    pub id: String,
+    #[serde(flatten)]
+    pub definition: ScheduleDefinition,
+    pub enabled: bool,
+    pub created: DateTime<Utc>,
+    pub last_run: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduleDefinition {
+    pub name: String,
+    /// `sec min hour day-of-month month day-of-week`, e.g. `0 0 9 * * Mon-Fri`
+    pub cron: String,
+    /// IANA time zone name, we use the system time zone when this is missing
+    pub time_zone: Option<String>,
+    pub action: ScheduledAction,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ScheduledAction {
+    /// Open `source`, or a new tab at `url`, and start playing
+    Play {
+        source: MediaSource,
+        url: Option<String>,
+    },
+    PauseAll,
+}
+
+pub fn start_scheduler(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut interval = time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            run_due_schedules(&app).await.log_error();
+        }
+    });
+}
+
+pub fn get_schedules(app: &AppHandle) -> anyhow::Result<Vec<Schedule>> {
+    let store = app.store(STORE_PATH)?;
+
+    match store.get(SCHEDULES_KEY) {
+        Some(schedules) => Ok(serde_json::from_value(schedules)?),
+        None => Ok(Vec::new()),
+    }
+}
+
+pub fn add_schedule(app: &AppHandle, definition: ScheduleDefinition) -> anyhow::Result<Schedule> {
+    definition.validate()?;
+
+    let schedule = Schedule {
+        id: Alphanumeric.sample_string(&mut rand::rng(), 8),
+        definition,
+        enabled: true,
+        created: Utc::now(),
+        last_run: None,
+    };
+
+    let mut schedules = get_schedules(app)?;
+    schedules.push(schedule.clone());
+    save_schedules(app, &schedules)?;
+
+    info!(
+        "Added schedule {}: {}",
+        schedule.id, schedule.definition.name
+    );
+
+    Ok(schedule)
+}
+
+pub fn remove_schedule(app: &AppHandle, id: &str) -> anyhow::Result<()> {
+    let mut schedules = get_schedules(app)?;
+    schedules.retain(|s| s.id != id);
+    save_schedules(app, &schedules)
+}
+
+pub fn set_schedule_enabled(app: &AppHandle, id: &str, enabled: bool) -> anyhow::Result<()> {
+    let mut schedules = get_schedules(app)?;
+    let schedule = schedules
+        .iter_mut()
+        .find(|s| s.id == id)
+        .ok_or_else(|| anyhow!("No schedule found for {id}"))?;
+    schedule.enabled = enabled;
+    if enabled {
+        // don't fire for everything we skipped while disabled
+        schedule.last_run = Some(Utc::now());
+    }
+
+    save_schedules(app, &schedules)
+}
+
+fn save_schedules(app: &AppHandle, schedules: &[Schedule]) -> anyhow::Result<()> {
+    let store = app.store(STORE_PATH)?;
+    store.set(SCHEDULES_KEY, serde_json::to_value(schedules)?);
+    store.save()?;
+
+    Ok(())
+}
+
+async fn run_due_schedules(app: &AppHandle) -> anyhow::Result<()> {
+    let now = Utc::now();
+    let mut schedules = get_schedules(app)?;
+    let last_runs = schedules.iter().map(|s| s.last_run).collect::<Vec<_>>();
+
+    let due = take_due(&mut schedules, now);
+    // skipped runs are handled too, otherwise we'd warn about them every check
+    if schedules.iter().map(|s| s.last_run).ne(last_runs) {
+        save_schedules(app, &schedules)?;
+    }
+
+    for schedule in due {
+        info!(
+            "Running schedule {} ({}): {:?}",
+            schedule.id, schedule.definition.name, schedule.definition.action
+        );
+        run_action(app, &schedule.definition.action)
+            .await
+            .log_error();
+    }
+
+    Ok(())
+}
+
+/// Mark every occurrence since each schedule's last run as handled, returning the schedules to run now
+fn take_due(schedules: &mut [Schedule], now: DateTime<Utc>) -> Vec<Schedule> {
+    let mut due = Vec::new();
+
+    for schedule in schedules.iter_mut().filter(|s| s.enabled) {
+        // one broken schedule shouldn't stop the others from running
+        let occurrence = match schedule.last_occurrence(now) {
+            Ok(occurrence) => occurrence,
+            Err(e) => {
+                warn!(
+                    "Skipping schedule {} ({}): {e}",
+                    schedule.id, schedule.definition.name
+                );
+                continue;
+            }
+        };
+
+        if let Some(occurrence) = occurrence {
+            // whether we run it or skip it, this occurrence is handled
+            schedule.last_run = Some(now);
+
+            if now - occurrence <= MISSED_RUN_GRACE {
+                due.push(schedule.clone());
+            } else {
+                warn!(
+                    "Skipping schedule {} ({}), we missed it at {occurrence}",
+                    schedule.id, schedule.definition.name
+                );
+            }
+        }
+    }
+
+    due
+}
+
+async fn run_action(app: &AppHandle, action: &ScheduledAction) -> anyhow::Result<()> {
+    match action {
+        ScheduledAction::PauseAll => app.app_state_mut(|state| state.pause_all()),
+        ScheduledAction::Play { source, url } => {
+            webview_manager::play_source(app, *source, url.clone()).await
+        }
+    }
+}
+
+impl ScheduleDefinition {
+    fn validate(&self) -> anyhow::Result<()> {
+        self.cron_schedule()?;
+        self.time_zone()?;
+        if let ScheduledAction::Play { url: Some(url), .. } = &self.action {
+            url::Url::parse(url)?;
+        }
+
+        Ok(())
+    }
+
+    fn cron_schedule(&self) -> anyhow::Result<cron::Schedule> {
+        cron::Schedule::from_str(&self.cron)
+            .map_err(|e| anyhow!("Invalid schedule {:?}: {e}", self.cron))
+    }
+
+    fn time_zone(&self) -> anyhow::Result<Option<Tz>> {
+        self.time_zone
+            .as_deref()
+            .map(|tz| Tz::from_str(tz).map_err(|e| anyhow!("Invalid time zone {tz:?}: {e}")))
+            .transpose()
+    }
+}
+
+impl Schedule {
+    /// The latest time we should have run since our last run, if there is one
+    fn last_occurrence(&self, now: DateTime<Utc>) -> anyhow::Result<Option<DateTime<Utc>>> {
+        let since = self.last_run.unwrap_or(self.created);
+        let cron = self.definition.cron_schedule()?;
+
+        // occurrences are computed in the schedule's time zone so 09:00 stays 09:00 across DST
+        Ok(match self.definition.time_zone()? {
+            Some(tz) => last_occurrence_in(&cron, since, now, &tz),
+            None => last_occurrence_in(&cron, since, now, &Local),
+        })
+    }
+}
+
+fn last_occurrence_in<Z: TimeZone>(
+    cron: &cron::Schedule,
+    since: DateTime<Utc>,
+    now: DateTime<Utc>,
+    tz: &Z,
+) -> Option<DateTime<Utc>> {
+    cron.after(&since.with_timezone(tz))
+        .map(|occurrence| occurrence.with_timezone(&Utc))
+        .take_while(|occurrence| *occurrence <= now)
+        .last()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time).unwrap().to_utc()
+    }
+
+    fn schedule(id: &str, cron: &str, time_zone: &str, created: &str) -> Schedule {
+        Schedule {
+            id: id.to_string(),
+            definition: ScheduleDefinition {
+                name: id.to_string(),
+                cron: cron.to_string(),
+                time_zone: Some(time_zone.to_string()),
+                action: ScheduledAction::PauseAll,
+            },
+            enabled: true,
+            created: at(created),
+            last_run: None,
+        }
+    }
+
+    fn ids(schedules: &[Schedule]) -> Vec<&str> {
+        schedules.iter().map(|s| s.id.as_str()).collect()
+    }
+
+    #[test]
+    fn runs_each_occurrence_once() {
+        let mut schedules = [schedule("a", "0 0 9 * * *", "UTC", "2026-03-01T00:00:00Z")];
+
+        let before = at("2026-03-01T08:59:30Z");
+        assert!(take_due(&mut schedules, before).is_empty());
+        assert_eq!(schedules[0].last_run, None);
+
+        let now = at("2026-03-01T09:00:10Z");
+        assert_eq!(ids(&take_due(&mut schedules, now)), ["a"]);
+        assert_eq!(schedules[0].last_run, Some(now));
+
+        let later = at("2026-03-01T09:00:40Z");
+        assert!(take_due(&mut schedules, later).is_empty());
+        assert_eq!(schedules[0].last_run, Some(now));
+
+        let tomorrow = at("2026-03-02T09:00:20Z");
+        assert_eq!(ids(&take_due(&mut schedules, tomorrow)), ["a"]);
+    }
+
+    #[test]
+    fn runs_late_within_grace() {
+        let mut schedules = [schedule("a", "0 0 9 * * *", "UTC", "2026-03-01T00:00:00Z")];
+
+        let now = at("2026-03-01T09:14:00Z");
+        assert_eq!(ids(&take_due(&mut schedules, now)), ["a"]);
+    }
+
+    #[test]
+    fn skips_missed_runs_but_marks_them_handled() {
+        let mut schedules = [schedule("a", "0 0 9 * * *", "UTC", "2026-03-01T00:00:00Z")];
+
+        let now = at("2026-03-01T09:16:00Z");
+        assert!(take_due(&mut schedules, now).is_empty());
+        assert_eq!(schedules[0].last_run, Some(now));
+
+        // we don't skip it again on the next check
+        let later = at("2026-03-01T09:16:30Z");
+        assert!(take_due(&mut schedules, later).is_empty());
+        assert_eq!(schedules[0].last_run, Some(now));
+    }
+
+    #[test]
+    fn runs_once_after_missing_several() {
+        let mut schedules = [schedule(
+            "a",
+            "0 */5 * * * *",
+            "UTC",
+            "2026-03-01T09:00:00Z",
+        )];
+
+        let now = at("2026-03-01T10:00:05Z");
+        assert_eq!(ids(&take_due(&mut schedules, now)), ["a"]);
+        assert!(take_due(&mut schedules, now).is_empty());
+    }
+
+    #[test]
+    fn ignores_disabled_schedules() {
+        let mut schedules = [
+            schedule("a", "0 0 9 * * *", "UTC", "2026-03-01T00:00:00Z"),
+            Schedule {
+                enabled: false,
+                ..schedule("b", "0 0 9 * * *", "UTC", "2026-03-01T00:00:00Z")
+            },
+        ];
+
+        let now = at("2026-03-01T09:00:10Z");
+        assert_eq!(ids(&take_due(&mut schedules, now)), ["a"]);
+        assert_eq!(schedules[1].last_run, None);
+    }
+
+    #[test]
+    fn broken_schedules_dont_stop_the_others() {
+        let mut schedules = [
+            schedule("bad cron", "not a cron", "UTC", "2026-03-01T00:00:00Z"),
+            schedule(
+                "bad zone",
+                "0 0 9 * * *",
+                "Nowhere/Special",
+                "2026-03-01T00:00:00Z",
+            ),
+            schedule("a", "0 0 9 * * *", "UTC", "2026-03-01T00:00:00Z"),
+        ];
+
+        let now = at("2026-03-01T09:00:10Z");
+        assert_eq!(ids(&take_due(&mut schedules, now)), ["a"]);
+        assert_eq!(schedules[0].last_run, None);
+        assert_eq!(schedules[1].last_run, None);
+    }
+
+    #[test]
+    fn keeps_local_time_across_dst() {
+        let mut schedules = [schedule(
+            "a",
+            "0 0 9 * * *",
+            "Europe/London",
+            "2026-03-27T12:00:00Z",
+        )];
+
+        // GMT, 09:00 is 09:00 UTC
+        let winter = at("2026-03-28T08:00:10Z");
+        assert!(take_due(&mut schedules, winter).is_empty());
+        let winter = at("2026-03-28T09:00:10Z");
+        assert_eq!(ids(&take_due(&mut schedules, winter)), ["a"]);
+
+        // BST, 09:00 is 08:00 UTC
+        let summer = at("2026-03-30T08:00:10Z");
+        assert_eq!(ids(&take_due(&mut schedules, summer)), ["a"]);
+    }
+
+    #[test]
+    fn invalid_definitions_are_rejected() {
+        let definition = |cron: &str, time_zone: &str, url: Option<&str>| ScheduleDefinition {
+            name: "test".to_string(),
+            cron: cron.to_string(),
+            time_zone: Some(time_zone.to_string()),
+            action: ScheduledAction::Play {
+                source: MediaSource::YouTube,
+                url: url.map(str::to_string),
+            },
+        };
+
+        assert!(definition("0 0 9 * * *", "UTC", None).validate().is_ok());
+        assert!(definition("every morning", "UTC", None).validate().is_err());
+        assert!(
+            definition("0 0 9 * * *", "Mars/Olympus", None)
+                .validate()
+                .is_err()
+        );
+        assert!(
+            definition("0 0 9 * * *", "UTC", Some("not a url"))
+                .validate()
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use crate::scrobbler::{QueuedScrobble, Track};
+use anyhow::anyhow;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+const DEFAULT_API_URL: &str = "https://ws.audioscrobbler.com/2.0/";
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LastFmConfig {
+    pub api_key: String,
+    pub api_secret: String,
+    pub username: String,
+    pub session_key: String,
+    #[serde(default = "default_api_url")]
+    pub api_url: String,
+}
+
+#[derive(Deserialize)]
+struct SessionResponse {
+    session: Session,
+}
+
+#[derive(Deserialize)]
+struct Session {
+    name: String,
+    key: String,
+}
+
+impl LastFmConfig {
+    /// Trade the user's password for a session key, we never store the password
+    pub(super) async fn connect(
+        client: &reqwest::Client,
+        api_key: String,
+        api_secret: String,
+        username: String,
+        password: &str,
+    ) -> anyhow::Result<Self> {
+        let mut config = Self {
+            api_key,
+            api_secret,
+            username,
+            session_key: String::new(),
+            api_url: default_api_url(),
+        };
+
+        let response = config
+            .call(
+                client,
+                "auth.getMobileSession",
+                vec![
+                    ("username".into(), config.username.clone()),
+                    ("password".into(), password.to_string()),
+                ],
+            )
+            .await?;
+        let SessionResponse { session } = serde_json::from_value(response)?;
+        config.username = session.name;
+        config.session_key = session.key;
+
+        Ok(config)
+    }
+
+    pub(super) async fn now_playing(
+        &self,
+        client: &reqwest::Client,
+        track: &Track,
+    ) -> anyhow::Result<()> {
+        let mut params = vec![
+            ("artist".into(), track.artist.clone()),
+            ("track".into(), track.title.clone()),
+            ("sk".into(), self.session_key.clone()),
+        ];
+        if let Some(duration) = track.duration_secs {
+            params.push(("duration".into(), duration.to_string()));
+        }
+
+        self.call(client, "track.updateNowPlaying", params).await?;
+
+        Ok(())
+    }
+
+    pub(super) async fn scrobble(
+        &self,
+        client: &reqwest::Client,
+        scrobbles: &[&QueuedScrobble],
+    ) -> anyhow::Result<()> {
+        let mut params = vec![("sk".into(), self.session_key.clone())];
+        for (i, scrobble) in scrobbles.iter().enumerate() {
+            params.push((format!("artist[{i}]"), scrobble.track.artist.clone()));
+            params.push((format!("track[{i}]"), scrobble.track.title.clone()));
+            params.push((
+                format!("timestamp[{i}]"),
+                scrobble.started.timestamp().to_string(),
+            ));
+            if let Some(duration) = scrobble.track.duration_secs {
+                params.push((format!("duration[{i}]"), duration.to_string()));
+            }
+        }
+
+        let response = self.call(client, "track.scrobble", params).await?;
+        if let Some(ignored) = response.pointer("/scrobbles/@attr/ignored") {
+            debug!("Last.fm ignored {ignored} scrobbles");
+        }
+
+        Ok(())
+    }
+
+    async fn call(
+        &self,
+        client: &reqwest::Client,
+        method: &str,
+        mut params: Vec<(String, String)>,
+    ) -> anyhow::Result<Value> {
+        params.push(("method".into(), method.into()));
+        params.push(("api_key".into(), self.api_key.clone()));
+        params.push(("api_sig".into(), self.sign(&params)));
+        params.push(("format".into(), "json".into()));
+
+        let response: Value = client
+            .post(&self.api_url)
+            .form(&params)
+            .send()
+            .await?
+            .json()
+            .await?;
+
+        match response.get("error") {
+            Some(code) => Err(anyhow!(
+                "Last.fm {method} failed ({code}): {}",
+                response["message"].as_str().unwrap_or_default()
+            )),
+            None => Ok(response),
+        }
+    }
+
+    /// The md5 of every parameter name and value in name order, followed by our secret
+    fn sign(&self, params: &[(String, String)]) -> String {
+        let mut sorted = params.iter().collect::<Vec<_>>();
+        sorted.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut signature = sorted
+            .into_iter()
+            .map(|(name, value)| format!("{name}{value}"))
+            .collect::<String>();
+        signature.push_str(&self.api_secret);
+
+        format!("{:x}", md5::compute(signature))
+    }
+}
+
+fn default_api_url() -> String {
+    DEFAULT_API_URL.to_string()
+}
@@ -0,0 +1,129 @@
+use crate::scrobbler::{QueuedScrobble, Track};
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+const DEFAULT_API_URL: &str = "https://api.listenbrainz.org";
+const CLIENT_NAME: &str = "Medyia";
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ListenBrainzConfig {
+    pub token: String,
+    pub username: String,
+    #[serde(default = "default_api_url")]
+    pub api_url: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+struct ValidateTokenResponse {
+    valid: bool,
+    user_name: Option<String>,
+}
+
+impl ListenBrainzConfig {
+    /// Check `token` is valid before we start using it, against listenbrainz.org unless we're given `api_url`
+    pub(super) async fn connect(
+        client: &reqwest::Client,
+        token: String,
+        api_url: Option<String>,
+    ) -> anyhow::Result<Self> {
+        let api_url = api_url
+            .map(|url| url.trim().trim_end_matches('/').to_string())
+            .filter(|url| !url.is_empty())
+            .unwrap_or_else(default_api_url);
+        let response: ValidateTokenResponse = client
+            .get(format!("{api_url}/1/validate-token"))
+            .header("Authorization", format!("Token {token}"))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        match response {
+            ValidateTokenResponse {
+                valid: true,
+                user_name: Some(username),
+            } => Ok(Self {
+                token,
+                username,
+                api_url,
+            }),
+            _ => Err(anyhow!("Invalid ListenBrainz token")),
+        }
+    }
+
+    pub(super) async fn now_playing(
+        &self,
+        client: &reqwest::Client,
+        track: &Track,
+    ) -> anyhow::Result<()> {
+        self.submit(
+            client,
+            "playing_now",
+            vec![json!({ "track_metadata": track_metadata(track) })],
+        )
+        .await
+    }
+
+    pub(super) async fn scrobble(
+        &self,
+        client: &reqwest::Client,
+        scrobbles: &[&QueuedScrobble],
+    ) -> anyhow::Result<()> {
+        let listens = scrobbles
+            .iter()
+            .map(|scrobble| {
+                json!({
+                    "listened_at": scrobble.started.timestamp(),
+                    "track_metadata": track_metadata(&scrobble.track),
+                })
+            })
+            .collect::<Vec<_>>();
+        let listen_type = if listens.len() == 1 {
+            "single"
+        } else {
+            "import"
+        };
+
+        self.submit(client, listen_type, listens).await
+    }
+
+    async fn submit(
+        &self,
+        client: &reqwest::Client,
+        listen_type: &str,
+        payload: Vec<Value>,
+    ) -> anyhow::Result<()> {
+        client
+            .post(format!("{}/1/submit-listens", self.api_url))
+            .header("Authorization", format!("Token {}", self.token))
+            .json(&json!({
+                "listen_type": listen_type,
+                "payload": payload,
+            }))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+fn track_metadata(track: &Track) -> Value {
+    json!({
+        "artist_name": track.artist,
+        "track_name": track.title,
+        "additional_info": {
+            "duration_ms": track.duration_secs.map(|d| u64::from(d) * 1000),
+            "media_player": CLIENT_NAME,
+            "submission_client": CLIENT_NAME,
+        },
+    })
+}
+
+fn default_api_url() -> String {
+    DEFAULT_API_URL.to_string()
+}
@@ -0,0 +1,971 @@
+mod last_fm;
+mod listen_brainz;
+
+use crate::EnhancedResult;
+use crate::media_sources::MediaSource;
+use crate::playback::PlaybackEvent;
+use crate::state::{AppState, EnhancerAppStateManager, TabKey};
+use chrono::{DateTime, Utc};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+use tauri_plugin_store::StoreExt;
+use tokio::time;
+
+pub use last_fm::LastFmConfig;
+pub use listen_brainz::ListenBrainzConfig;
+
+const STORE_PATH: &str = "medyia-scrobbler.json";
+const CONFIG_KEY: &str = "config";
+const QUEUE_KEY: &str = "queue";
+
+const TICK_INTERVAL: Duration = Duration::from_secs(10);
+/// Tracks shorter than this, or listened to for less, are never scrobbled
+const MIN_LISTENED: Duration = Duration::from_secs(30);
+/// Listening this long scrobbles a track even if we haven't heard half of it
+const MAX_REQUIRED: Duration = Duration::from_mins(4);
+/// The most scrobbles a backend accepts in one request
+const BATCH_SIZE: usize = 50;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(30);
+const MAX_BACKOFF: Duration = Duration::from_mins(30);
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ScrobblerConfig {
+    /// The sources we scrobble from
+    #[serde(default = "default_sources")]
+    pub sources: BTreeSet<MediaSource>,
+    pub last_fm: Option<LastFmConfig>,
+    pub listen_brainz: Option<ListenBrainzConfig>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Ord, PartialOrd, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum BackendKind {
+    LastFm,
+    ListenBrainz,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Track {
+    pub artist: String,
+    pub title: String,
+    pub duration_secs: Option<u32>,
+}
+
+/// A scrobble waiting to be accepted by every backend in `pending`
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct QueuedScrobble {
+    pub track: Track,
+    pub started: DateTime<Utc>,
+    pub pending: BTreeSet<BackendKind>,
+}
+
+pub struct Scrobbler {
+    client: reqwest::Client,
+    tracks: Mutex<HashMap<TabKey, TabTrack>>,
+    /// Held while we read, send and write back the queue
+    queue_lock: tokio::sync::Mutex<()>,
+    retry: Mutex<Retry>,
+}
+
+/// The track a tab is on and how much of it we've heard
+struct TabTrack {
+    track: Track,
+    started: DateTime<Utc>,
+    playing_since: Option<Instant>,
+    listened: Duration,
+    scrobbled: bool,
+}
+
+struct Retry {
+    next_attempt: Option<Instant>,
+    backoff: Duration,
+}
+
+enum Backend<'a> {
+    LastFm(&'a LastFmConfig),
+    ListenBrainz(&'a ListenBrainzConfig),
+}
+
+pub fn setup_scrobbler(app: &AppHandle) {
+    app.manage(Scrobbler {
+        client: reqwest::Client::new(),
+        tracks: Mutex::new(HashMap::new()),
+        queue_lock: tokio::sync::Mutex::new(()),
+        retry: Mutex::new(Retry {
+            next_attempt: None,
+            backoff: INITIAL_BACKOFF,
+        }),
+    });
+
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let mut interval = time::interval(TICK_INTERVAL);
+        loop {
+            interval.tick().await;
+            tick(&app).await.log_error();
+        }
+    });
+}
+
+/// Follow what `event`'s tab is playing, sending now playing updates and scrobbling once we've heard enough
+pub fn record_playback(app: &AppHandle, event: &PlaybackEvent) -> anyhow::Result<()> {
+    let Some(source) = app.app_state(|state| state.tab(&event.label).map(|t| t.source)) else {
+        return Ok(());
+    };
+    let config = get_config(app)?;
+    if !config.sources.contains(&source) || !config.has_backends() {
+        return Ok(());
+    }
+    // we can't scrobble without an artist
+    let Some(track) = Track::from_event(event) else {
+        return Ok(());
+    };
+
+    let scrobbler = app.state::<Scrobbler>();
+    let now = Instant::now();
+    let (started_playing, due) = {
+        let mut tracks = scrobbler.tracks.lock().unwrap();
+        let tab_track = tracks
+            .entry(event.label.clone())
+            .and_modify(|t| {
+                if !t.track.same_track(&track) {
+                    *t = TabTrack::new(track.clone());
+                } else if t.track.duration_secs.is_none() {
+                    // the duration usually loads after the title
+                    t.track.duration_secs = track.duration_secs;
+                }
+            })
+            .or_insert_with(|| TabTrack::new(track.clone()));
+
+        let started_playing = event.playing && tab_track.playing_since.is_none();
+        if event.playing {
+            tab_track.playing_since.get_or_insert(now);
+        } else {
+            tab_track.pause(now);
+        }
+
+        (started_playing, tab_track.take_due(now))
+    };
+
+    if started_playing {
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move {
+            now_playing(&app, &track).await;
+        });
+    }
+    if let Some(scrobble) = due {
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move {
+            enqueue(&app, vec![scrobble]).await.log_error();
+        });
+    }
+
+    Ok(())
+}
+
+pub fn get_config(app: &AppHandle) -> anyhow::Result<ScrobblerConfig> {
+    let store = app.store(STORE_PATH)?;
+
+    match store.get(CONFIG_KEY) {
+        Some(config) => Ok(serde_json::from_value(config)?),
+        None => Ok(ScrobblerConfig::default()),
+    }
+}
+
+pub fn set_sources(app: &AppHandle, sources: BTreeSet<MediaSource>) -> anyhow::Result<()> {
+    update_config(app, |config| config.sources = sources)
+}
+
+pub async fn connect_last_fm(
+    app: &AppHandle,
+    api_key: String,
+    api_secret: String,
+    username: String,
+    password: String,
+) -> anyhow::Result<()> {
+    let client = &app.state::<Scrobbler>().client;
+    let last_fm = LastFmConfig::connect(client, api_key, api_secret, username, &password).await?;
+    info!("Connected to Last.fm as {}", last_fm.username);
+
+    update_config(app, |config| config.last_fm = Some(last_fm))
+}
+
+/// `api_url` points us at a self-hosted ListenBrainz rather than listenbrainz.org
+pub async fn connect_listen_brainz(
+    app: &AppHandle,
+    token: String,
+    api_url: Option<String>,
+) -> anyhow::Result<()> {
+    let client = &app.state::<Scrobbler>().client;
+    let listen_brainz = ListenBrainzConfig::connect(client, token, api_url).await?;
+    info!("Connected to ListenBrainz as {}", listen_brainz.username);
+
+    update_config(app, |config| config.listen_brainz = Some(listen_brainz))
+}
+
+pub fn disconnect(app: &AppHandle, backend: BackendKind) -> anyhow::Result<()> {
+    update_config(app, |config| match backend {
+        BackendKind::LastFm => config.last_fm = None,
+        BackendKind::ListenBrainz => config.listen_brainz = None,
+    })
+}
+
+fn update_config(app: &AppHandle, f: impl FnOnce(&mut ScrobblerConfig)) -> anyhow::Result<()> {
+    let mut config = get_config(app)?;
+    f(&mut config);
+
+    let store = app.store(STORE_PATH)?;
+    store.set(CONFIG_KEY, serde_json::to_value(config)?);
+    store.save()?;
+
+    Ok(())
+}
+
+fn load_queue(app: &AppHandle) -> anyhow::Result<Vec<QueuedScrobble>> {
+    let store = app.store(STORE_PATH)?;
+
+    match store.get(QUEUE_KEY) {
+        Some(queue) => Ok(serde_json::from_value(queue)?),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn save_queue(app: &AppHandle, queue: &[QueuedScrobble]) -> anyhow::Result<()> {
+    let store = app.store(STORE_PATH)?;
+    store.set(QUEUE_KEY, serde_json::to_value(queue)?);
+    store.save()?;
+
+    Ok(())
+}
+
+async fn tick(app: &AppHandle) -> anyhow::Result<()> {
+    let playing = app.app_state(AppState::playing_by_tab);
+    let now = Instant::now();
+
+    let due = {
+        let mut tracks = app.state::<Scrobbler>().tracks.lock().unwrap();
+        // closed tabs won't tell us they've stopped
+        tracks.retain(|label, _| playing.contains_key(label));
+
+        tracks
+            .iter_mut()
+            .filter(|(label, _)| playing.get(*label) == Some(&true))
+            .filter_map(|(_, track)| track.take_due(now))
+            .collect::<Vec<_>>()
+    };
+
+    if due.is_empty() {
+        flush_queue(app).await
+    } else {
+        enqueue(app, due).await
+    }
+}
+
+async fn now_playing(app: &AppHandle, track: &Track) {
+    let Ok(config) = get_config(app) else {
+        return;
+    };
+    let client = &app.state::<Scrobbler>().client;
+
+    for backend in config.backends() {
+        if let Err(e) = backend.now_playing(client, track).await {
+            // now playing is best effort, there's no point retrying it later
+            debug!("Failed to send now playing to {:?}: {e}", backend.kind());
+        }
+    }
+}
+
+async fn enqueue(app: &AppHandle, scrobbles: Vec<(Track, DateTime<Utc>)>) -> anyhow::Result<()> {
+    let config = get_config(app)?;
+    let pending = config.backend_kinds();
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    {
+        let _guard = app.state::<Scrobbler>().queue_lock.lock().await;
+        let mut queue = load_queue(app)?;
+        for (track, started) in scrobbles {
+            info!("Scrobbling {} - {}", track.artist, track.title);
+            queue.push(QueuedScrobble {
+                track,
+                started,
+                pending: pending.clone(),
+            });
+        }
+        save_queue(app, &queue)?;
+    }
+
+    flush_queue(app).await
+}
+
+/// Send whatever's queued, backing off while our backends are failing
+async fn flush_queue(app: &AppHandle) -> anyhow::Result<()> {
+    let scrobbler = app.state::<Scrobbler>();
+    if !scrobbler.retry.lock().unwrap().is_due(Instant::now()) {
+        return Ok(());
+    }
+
+    let _guard = scrobbler.queue_lock.lock().await;
+    let mut queue = load_queue(app)?;
+    if queue.is_empty() {
+        return Ok(());
+    }
+
+    let config = get_config(app)?;
+    let failed = send_queue(&scrobbler.client, &config, &mut queue).await;
+    save_queue(app, &queue)?;
+
+    scrobbler
+        .retry
+        .lock()
+        .unwrap()
+        .update(failed, Instant::now());
+
+    Ok(())
+}
+
+/// Send a batch of `queue` to each backend, dropping the scrobbles every backend has, returns whether any failed
+async fn send_queue(
+    client: &reqwest::Client,
+    config: &ScrobblerConfig,
+    queue: &mut Vec<QueuedScrobble>,
+) -> bool {
+    let configured = config.backend_kinds();
+    let mut failed = false;
+
+    for backend in config.backends() {
+        let kind = backend.kind();
+        let indices = queue
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.pending.contains(&kind))
+            .map(|(i, _)| i)
+            .take(BATCH_SIZE)
+            .collect::<Vec<_>>();
+        if indices.is_empty() {
+            continue;
+        }
+
+        let batch = indices.iter().map(|&i| &queue[i]).collect::<Vec<_>>();
+        match backend.scrobble(client, &batch).await {
+            Ok(()) => {
+                debug!("Sent {} scrobbles to {kind:?}", indices.len());
+                for i in indices {
+                    queue[i].pending.remove(&kind);
+                }
+            }
+            Err(e) => {
+                warn!("Failed to send scrobbles to {kind:?}: {e}");
+                failed = true;
+            }
+        }
+    }
+
+    // scrobbles for backends we've disconnected from have nowhere to go
+    for scrobble in queue.iter_mut() {
+        scrobble.pending.retain(|k| configured.contains(k));
+    }
+    queue.retain(|s| !s.pending.is_empty());
+
+    failed
+}
+
+impl ScrobblerConfig {
+    fn has_backends(&self) -> bool {
+        self.last_fm.is_some() || self.listen_brainz.is_some()
+    }
+
+    fn backends(&self) -> Vec<Backend<'_>> {
+        self.last_fm
+            .iter()
+            .map(Backend::LastFm)
+            .chain(self.listen_brainz.iter().map(Backend::ListenBrainz))
+            .collect()
+    }
+
+    fn backend_kinds(&self) -> BTreeSet<BackendKind> {
+        self.backends().iter().map(Backend::kind).collect()
+    }
+}
+
+impl Default for ScrobblerConfig {
+    fn default() -> Self {
+        Self {
+            sources: default_sources(),
+            last_fm: None,
+            listen_brainz: None,
+        }
+    }
+}
+
+impl Backend<'_> {
+    fn kind(&self) -> BackendKind {
+        match self {
+            Backend::LastFm(_) => BackendKind::LastFm,
+            Backend::ListenBrainz(_) => BackendKind::ListenBrainz,
+        }
+    }
+
+    async fn now_playing(&self, client: &reqwest::Client, track: &Track) -> anyhow::Result<()> {
+        match self {
+            Backend::LastFm(last_fm) => last_fm.now_playing(client, track).await,
+            Backend::ListenBrainz(listen_brainz) => listen_brainz.now_playing(client, track).await,
+        }
+    }
+
+    async fn scrobble(
+        &self,
+        client: &reqwest::Client,
+        scrobbles: &[&QueuedScrobble],
+    ) -> anyhow::Result<()> {
+        match self {
+            Backend::LastFm(last_fm) => last_fm.scrobble(client, scrobbles).await,
+            Backend::ListenBrainz(listen_brainz) => listen_brainz.scrobble(client, scrobbles).await,
+        }
+    }
+}
+
+impl Track {
+    fn from_event(event: &PlaybackEvent) -> Option<Self> {
+        let artist = event.artist.as_deref().filter(|a| !a.is_empty())?;
+        let title = event.title.as_deref().filter(|t| !t.is_empty())?;
+
+        Some(Self {
+            artist: artist.to_string(),
+            title: title.to_string(),
+            duration_secs: event
+                .duration
+                .filter(|d| d.is_finite() && *d > 0.)
+                .map(|d| d as u32),
+        })
+    }
+
+    fn same_track(&self, other: &Track) -> bool {
+        self.artist == other.artist && self.title == other.title
+    }
+
+    /// How long we need to listen before this counts as a play
+    fn required_listen(&self) -> Option<Duration> {
+        match self
+            .duration_secs
+            .map(|d| Duration::from_secs(u64::from(d)))
+        {
+            Some(duration) if duration <= MIN_LISTENED => None,
+            Some(duration) => Some((duration / 2).clamp(MIN_LISTENED, MAX_REQUIRED)),
+            None => Some(MAX_REQUIRED),
+        }
+    }
+}
+
+impl TabTrack {
+    fn new(track: Track) -> Self {
+        Self {
+            track,
+            started: Utc::now(),
+            playing_since: None,
+            listened: Duration::ZERO,
+            scrobbled: false,
+        }
+    }
+
+    fn pause(&mut self, now: Instant) {
+        if let Some(since) = self.playing_since.take() {
+            self.listened += now.saturating_duration_since(since);
+        }
+    }
+
+    /// Our scrobble, the first time we've heard enough of the track
+    fn take_due(&mut self, now: Instant) -> Option<(Track, DateTime<Utc>)> {
+        let listened = self.listened
+            + self
+                .playing_since
+                .map_or(Duration::ZERO, |since| now.saturating_duration_since(since));
+        let required = self.track.required_listen()?;
+        if self.scrobbled || listened < required {
+            return None;
+        }
+        self.scrobbled = true;
+
+        Some((self.track.clone(), self.started))
+    }
+}
+
+impl Retry {
+    fn is_due(&self, now: Instant) -> bool {
+        self.next_attempt.is_none_or(|next| now >= next)
+    }
+
+    fn update(&mut self, failed: bool, now: Instant) {
+        if failed {
+            self.next_attempt = Some(now + self.backoff);
+            self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+        } else {
+            self.next_attempt = None;
+            self.backoff = INITIAL_BACKOFF;
+        }
+    }
+}
+
+fn default_sources() -> BTreeSet<MediaSource> {
+    BTreeSet::from([
+        MediaSource::AppleMusic,
+        MediaSource::SoundCloud,
+        MediaSource::YouTube,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+    use std::sync::Arc;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::{TcpListener, TcpStream};
+
+    const STARTED: i64 = 1_700_000_000;
+
+    /// Just enough of an HTTP server to stand in for our backends
+    struct Server {
+        url: String,
+        requests: Arc<Mutex<Vec<Request>>>,
+        responses: Arc<Mutex<HashMap<String, (u16, String)>>>,
+    }
+
+    #[derive(Debug, Clone)]
+    struct Request {
+        path: String,
+        authorization: Option<String>,
+        body: String,
+    }
+
+    impl Server {
+        async fn start() -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let server = Self {
+                url: format!("http://{}", listener.local_addr().unwrap()),
+                requests: Arc::default(),
+                responses: Arc::default(),
+            };
+
+            let requests = server.requests.clone();
+            let responses = server.responses.clone();
+            tokio::spawn(async move {
+                loop {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    handle(stream, &requests, &responses).await;
+                }
+            });
+
+            server
+        }
+
+        fn respond(&self, path: &str, status: u16, body: Value) {
+            self.responses
+                .lock()
+                .unwrap()
+                .insert(path.to_string(), (status, body.to_string()));
+        }
+
+        fn requests(&self, path: &str) -> Vec<Request> {
+            self.requests
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|r| r.path == path)
+                .cloned()
+                .collect()
+        }
+
+        fn config(&self, last_fm: bool, listen_brainz: bool) -> ScrobblerConfig {
+            ScrobblerConfig {
+                sources: default_sources(),
+                last_fm: last_fm.then(|| LastFmConfig {
+                    api_key: "key".to_string(),
+                    api_secret: "secret".to_string(),
+                    username: "user".to_string(),
+                    session_key: "session".to_string(),
+                    api_url: format!("{}/2.0/", self.url),
+                }),
+                listen_brainz: listen_brainz.then(|| ListenBrainzConfig {
+                    token: "token".to_string(),
+                    username: "user".to_string(),
+                    api_url: self.url.clone(),
+                }),
+            }
+        }
+    }
+
+    /// Answers one request, we close every connection so there's only ever one per connection
+    async fn handle(
+        stream: TcpStream,
+        requests: &Mutex<Vec<Request>>,
+        responses: &Mutex<HashMap<String, (u16, String)>>,
+    ) {
+        let mut stream = BufReader::new(stream);
+        let mut line = String::new();
+        stream.read_line(&mut line).await.unwrap();
+        let path = line.split_whitespace().nth(1).unwrap().to_string();
+
+        let mut content_length = 0;
+        let mut authorization = None;
+        loop {
+            line.clear();
+            stream.read_line(&mut line).await.unwrap();
+            let Some((name, value)) = line.trim_end().split_once(':') else {
+                break;
+            };
+            match name.to_ascii_lowercase().as_str() {
+                "content-length" => content_length = value.trim().parse().unwrap(),
+                "authorization" => authorization = Some(value.trim().to_string()),
+                _ => (),
+            }
+        }
+        let mut body = vec![0; content_length];
+        stream.read_exact(&mut body).await.unwrap();
+
+        let (status, response) = responses
+            .lock()
+            .unwrap()
+            .get(&path)
+            .cloned()
+            .unwrap_or((404, "{}".to_string()));
+        requests.lock().unwrap().push(Request {
+            path,
+            authorization,
+            body: String::from_utf8(body).unwrap(),
+        });
+
+        let reply = format!(
+            "HTTP/1.1 {status} Status\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{response}",
+            response.len()
+        );
+        stream.get_mut().write_all(reply.as_bytes()).await.unwrap();
+    }
+
+    fn track(title: &str, duration_secs: Option<u32>) -> Track {
+        Track {
+            artist: "Artist".to_string(),
+            title: title.to_string(),
+            duration_secs,
+        }
+    }
+
+    fn queued(title: &str, pending: &[BackendKind]) -> QueuedScrobble {
+        QueuedScrobble {
+            track: track(title, Some(200)),
+            started: DateTime::from_timestamp(STARTED, 0).unwrap(),
+            pending: pending.iter().copied().collect(),
+        }
+    }
+
+    fn form(request: &Request) -> HashMap<String, String> {
+        url::form_urlencoded::parse(request.body.as_bytes())
+            .into_owned()
+            .collect()
+    }
+
+    fn listens(request: &Request) -> Value {
+        serde_json::from_str(&request.body).unwrap()
+    }
+
+    fn ok_last_fm(server: &Server) {
+        server.respond(
+            "/2.0/",
+            200,
+            json!({ "scrobbles": { "@attr": { "accepted": 1, "ignored": 0 } } }),
+        );
+    }
+
+    fn ok_listen_brainz(server: &Server) {
+        server.respond("/1/submit-listens", 200, json!({ "status": "ok" }));
+    }
+
+    #[test]
+    fn required_listen() {
+        let cases = [
+            (None, Some(MAX_REQUIRED)),
+            (Some(20), None),
+            (Some(30), None),
+            (Some(40), Some(MIN_LISTENED)),
+            (Some(180), Some(Duration::from_secs(90))),
+            (Some(480), Some(MAX_REQUIRED)),
+            (Some(3600), Some(MAX_REQUIRED)),
+        ];
+
+        for (duration_secs, expected) in cases {
+            assert_eq!(
+                track("Title", duration_secs).required_listen(),
+                expected,
+                "{duration_secs:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn due_after_thirty_seconds_half_the_track_or_four_minutes() {
+        let cases = [
+            (Some(40), 29, false),
+            (Some(40), 30, true),
+            (Some(180), 89, false),
+            (Some(180), 90, true),
+            (Some(3600), 239, false),
+            (Some(3600), 240, true),
+            (None, 239, false),
+            (None, 240, true),
+            (Some(25), 600, false),
+        ];
+
+        for (duration_secs, listened, due) in cases {
+            let start = Instant::now();
+            let mut tab_track = TabTrack::new(track("Title", duration_secs));
+            tab_track.playing_since = Some(start);
+
+            let scrobble = tab_track.take_due(start + Duration::from_secs(listened));
+            assert_eq!(scrobble.is_some(), due, "{duration_secs:?} {listened}");
+        }
+    }
+
+    #[test]
+    fn paused_time_doesnt_count() {
+        let start = Instant::now();
+        let mut tab_track = TabTrack::new(track("Title", Some(180)));
+
+        tab_track.playing_since = Some(start);
+        tab_track.pause(start + Duration::from_secs(60));
+        assert!(
+            tab_track
+                .take_due(start + Duration::from_mins(60))
+                .is_none()
+        );
+
+        let resumed = start + Duration::from_mins(60);
+        tab_track.playing_since = Some(resumed);
+        assert!(
+            tab_track
+                .take_due(resumed + Duration::from_secs(29))
+                .is_none()
+        );
+        assert!(
+            tab_track
+                .take_due(resumed + Duration::from_secs(30))
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn scrobbles_each_play_once() {
+        let start = Instant::now();
+        let mut tab_track = TabTrack::new(track("Title", Some(180)));
+        tab_track.playing_since = Some(start);
+
+        let (scrobbled, started) = tab_track.take_due(start + Duration::from_secs(90)).unwrap();
+        assert_eq!(scrobbled, track("Title", Some(180)));
+        assert_eq!(started, tab_track.started);
+        assert!(
+            tab_track
+                .take_due(start + Duration::from_secs(180))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn retry_backs_off_until_we_succeed() {
+        let now = Instant::now();
+        let mut retry = Retry {
+            next_attempt: None,
+            backoff: INITIAL_BACKOFF,
+        };
+        assert!(retry.is_due(now));
+
+        retry.update(true, now);
+        assert!(!retry.is_due(now + INITIAL_BACKOFF - Duration::from_secs(1)));
+        assert!(retry.is_due(now + INITIAL_BACKOFF));
+
+        retry.update(true, now);
+        assert!(!retry.is_due(now + INITIAL_BACKOFF));
+        assert!(retry.is_due(now + INITIAL_BACKOFF * 2));
+
+        for _ in 0..10 {
+            retry.update(true, now);
+        }
+        assert_eq!(retry.next_attempt, Some(now + MAX_BACKOFF));
+        assert_eq!(retry.backoff, MAX_BACKOFF);
+
+        retry.update(false, now);
+        assert!(retry.is_due(now));
+        assert_eq!(retry.backoff, INITIAL_BACKOFF);
+    }
+
+    #[tokio::test]
+    async fn sends_queue_to_every_backend() {
+        let server = Server::start().await;
+        ok_last_fm(&server);
+        ok_listen_brainz(&server);
+        let all = [BackendKind::LastFm, BackendKind::ListenBrainz];
+        let mut queue = vec![queued("First", &all), queued("Second", &all)];
+
+        let failed = send_queue(
+            &reqwest::Client::new(),
+            &server.config(true, true),
+            &mut queue,
+        )
+        .await;
+
+        assert!(!failed);
+        assert!(queue.is_empty());
+
+        let [listen_brainz] = server.requests("/1/submit-listens").try_into().unwrap();
+        assert_eq!(listen_brainz.authorization.as_deref(), Some("Token token"));
+        let listens = listens(&listen_brainz);
+        assert_eq!(listens["listen_type"], "import");
+        assert_eq!(listens["payload"][0]["listened_at"], STARTED);
+        assert_eq!(
+            listens["payload"][1]["track_metadata"]["track_name"],
+            "Second"
+        );
+
+        let [last_fm] = server.requests("/2.0/").try_into().unwrap();
+        let form = form(&last_fm);
+        assert_eq!(form["method"], "track.scrobble");
+        assert_eq!(form["sk"], "session");
+        assert_eq!(form["artist[0]"], "Artist");
+        assert_eq!(form["track[1]"], "Second");
+        assert_eq!(form["timestamp[0]"], STARTED.to_string());
+    }
+
+    #[tokio::test]
+    async fn keeps_scrobbles_for_failing_backends() {
+        let server = Server::start().await;
+        let client = reqwest::Client::new();
+        let config = server.config(true, true);
+        ok_last_fm(&server);
+        server.respond("/1/submit-listens", 503, json!({}));
+        let mut queue = vec![queued(
+            "Title",
+            &[BackendKind::LastFm, BackendKind::ListenBrainz],
+        )];
+
+        assert!(send_queue(&client, &config, &mut queue).await);
+        assert_eq!(queue.len(), 1);
+        assert_eq!(
+            queue[0].pending,
+            BTreeSet::from([BackendKind::ListenBrainz])
+        );
+
+        ok_listen_brainz(&server);
+        assert!(!send_queue(&client, &config, &mut queue).await);
+        assert!(queue.is_empty());
+
+        // Last.fm already had it
+        assert_eq!(server.requests("/2.0/").len(), 1);
+        assert_eq!(server.requests("/1/submit-listens").len(), 2);
+    }
+
+    #[tokio::test]
+    async fn last_fm_errors_are_failures() {
+        let server = Server::start().await;
+        server.respond(
+            "/2.0/",
+            200,
+            json!({ "error": 16, "message": "Temporarily unavailable" }),
+        );
+        let mut queue = vec![queued("Title", &[BackendKind::LastFm])];
+
+        let failed = send_queue(
+            &reqwest::Client::new(),
+            &server.config(true, false),
+            &mut queue,
+        )
+        .await;
+
+        assert!(failed);
+        assert_eq!(queue[0].pending, BTreeSet::from([BackendKind::LastFm]));
+    }
+
+    #[tokio::test]
+    async fn drains_the_queue_in_batches() {
+        let server = Server::start().await;
+        let client = reqwest::Client::new();
+        let config = server.config(false, true);
+        ok_listen_brainz(&server);
+        let mut queue = (0..60)
+            .map(|i| queued(&format!("Track {i}"), &[BackendKind::ListenBrainz]))
+            .collect::<Vec<_>>();
+
+        assert!(!send_queue(&client, &config, &mut queue).await);
+        assert_eq!(queue.len(), 60 - BATCH_SIZE);
+        assert_eq!(queue[0].track.title, "Track 50");
+
+        assert!(!send_queue(&client, &config, &mut queue).await);
+        assert!(queue.is_empty());
+
+        let sizes = server
+            .requests("/1/submit-listens")
+            .iter()
+            .map(|r| listens(r)["payload"].as_array().unwrap().len())
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, [BATCH_SIZE, 10]);
+    }
+
+    #[tokio::test]
+    async fn drops_scrobbles_for_disconnected_backends() {
+        let server = Server::start().await;
+        ok_listen_brainz(&server);
+        let mut queue = vec![
+            queued("Both", &[BackendKind::LastFm, BackendKind::ListenBrainz]),
+            queued("Last.fm", &[BackendKind::LastFm]),
+        ];
+
+        let failed = send_queue(
+            &reqwest::Client::new(),
+            &server.config(false, true),
+            &mut queue,
+        )
+        .await;
+
+        assert!(!failed);
+        assert!(queue.is_empty());
+        assert!(server.requests("/2.0/").is_empty());
+    }
+
+    #[tokio::test]
+    async fn listen_brainz_validates_tokens_against_our_api_url() {
+        let server = Server::start().await;
+        let client = reqwest::Client::new();
+        server.respond(
+            "/1/validate-token",
+            200,
+            json!({ "code": 200, "valid": true, "user_name": "user" }),
+        );
+
+        let config = ListenBrainzConfig::connect(
+            &client,
+            "token".to_string(),
+            Some(format!("{}/", server.url)),
+        )
+        .await
+        .unwrap();
+        assert_eq!(config.username, "user");
+        assert_eq!(config.api_url, server.url);
+
+        let [request] = server.requests("/1/validate-token").try_into().unwrap();
+        assert_eq!(request.authorization.as_deref(), Some("Token token"));
+
+        server.respond(
+            "/1/validate-token",
+            200,
+            json!({ "code": 200, "valid": false }),
+        );
+        let result =
+            ListenBrainzConfig::connect(&client, "bad".to_string(), Some(server.url.clone())).await;
+        assert!(result.is_err());
+    }
+}
@@ -1,16 +1,57 @@
+use crate::EnhancedManager;
+use crate::fullscreen;
 use crate::state::{AppState, EnhancerAppStateManager, EnhancerAppStateManagerEmitter};
-use log::info;
-use tauri::AppHandle;
+use crate::utils::EnhancedWindow;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use tauri::{
+    AppHandle, LogicalPosition, LogicalSize, PhysicalPosition, PhysicalSize, Runtime, Window,
+};
 use tauri_plugin_store::StoreExt;
 
 const STORE_PATH: &str = "medyia-session.json";
 const SESSION_KEY: &str = "session";
+const WINDOW_KEY: &str = "window";
+
+pub const DEFAULT_WINDOW_SIZE: LogicalSize<f64> = LogicalSize::new(1400., 1000.);
+pub const MIN_WINDOW_SIZE: LogicalSize<f64> = LogicalSize::new(800., 600.);
+
+/// Where our main window was, relative to its monitor so we can place it on another
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct WindowGeometry {
+    monitor: Option<String>,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    maximized: bool,
+    fullscreen: bool,
+}
+
+/// Where to build our main window, clamped to a monitor we still have
+#[derive(Debug, Clone, Copy)]
+pub struct WindowPlacement {
+    pub position: LogicalPosition<f64>,
+    pub size: LogicalSize<f64>,
+    maximized: bool,
+    fullscreen: bool,
+}
 
 pub fn save_session(app: &AppHandle) -> anyhow::Result<()> {
     let session = app.app_state(AppState::read_session);
 
     if let Ok(store) = app.store(STORE_PATH) {
         store.set(SESSION_KEY, serde_json::to_value(&session)?);
+
+        let previous = store
+            .get(WINDOW_KEY)
+            .and_then(|geometry| serde_json::from_value(geometry).ok());
+        let tab_fullscreen = app.app_state(|state| state.fullscreen_tab_key().is_some());
+        let geometry = window_geometry(&app.main_window(), previous, tab_fullscreen)?;
+        debug!("Saving window geometry {geometry:?}");
+        store.set(WINDOW_KEY, serde_json::to_value(&geometry)?);
+
         store.save()?;
         info!("Session saved to {STORE_PATH} @ {SESSION_KEY}");
     }
@@ -28,3 +69,210 @@ pub fn restore_session(app: &AppHandle) -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// Where our main window should go, if we saved one last time
+pub fn window_placement(app: &AppHandle) -> Option<WindowPlacement> {
+    let store = app.store(STORE_PATH).ok()?;
+    let geometry = serde_json::from_value::<WindowGeometry>(store.get(WINDOW_KEY)?).ok()?;
+    let monitors = app.available_monitors().ok()?;
+
+    let saved_monitor = monitors
+        .iter()
+        .find(|m| geometry.monitor.is_some() && m.name() == geometry.monitor.as_ref());
+    let primary = app.primary_monitor().ok().flatten();
+    let monitor = saved_monitor.or(primary.as_ref()).or(monitors.first())?;
+
+    Some(WindowPlacement::clamped(
+        &geometry,
+        *monitor.position(),
+        *monitor.size(),
+        monitor.scale_factor(),
+        saved_monitor.is_some(),
+    ))
+}
+
+fn window_geometry<R: Runtime>(
+    window: &Window<R>,
+    previous: Option<WindowGeometry>,
+    tab_fullscreen: bool,
+) -> anyhow::Result<WindowGeometry> {
+    let maximized = window.is_maximized()?;
+    let window_fullscreen = window.is_fullscreen()?;
+    // a tab's fullscreen video isn't how the user left our window
+    let fullscreen = if tab_fullscreen {
+        fullscreen::window_was_fullscreen()
+    } else {
+        window_fullscreen
+    };
+
+    // we restore the size we'd return to rather than the whole screen
+    if let Some(previous) = previous.filter(|_| maximized || window_fullscreen) {
+        return Ok(WindowGeometry {
+            maximized,
+            fullscreen,
+            ..previous
+        });
+    }
+
+    let scale = window.scale_factor()?;
+    let position = window.outer_position()?.to_logical::<f64>(scale);
+    let size = window.size()?;
+    let monitor = window.current_monitor()?;
+    let origin = monitor.as_ref().map_or(LogicalPosition::new(0., 0.), |m| {
+        m.position().to_logical(scale)
+    });
+
+    Ok(WindowGeometry {
+        monitor: monitor.and_then(|m| m.name().cloned()),
+        x: position.x - origin.x,
+        y: position.y - origin.y,
+        width: size.width,
+        height: size.height,
+        maximized,
+        fullscreen,
+    })
+}
+
+impl WindowPlacement {
+    /// Keep the whole window on the monitor at `origin`, centering it when it's not the monitor we saved it on
+    fn clamped(
+        geometry: &WindowGeometry,
+        origin: PhysicalPosition<i32>,
+        size: PhysicalSize<u32>,
+        scale: f64,
+        same_monitor: bool,
+    ) -> Self {
+        let origin = origin.to_logical::<f64>(scale);
+        let bounds = size.to_logical::<f64>(scale);
+
+        let width = geometry.width.min(bounds.width).max(MIN_WINDOW_SIZE.width);
+        let height = geometry
+            .height
+            .min(bounds.height)
+            .max(MIN_WINDOW_SIZE.height);
+        let (x, y) = if same_monitor {
+            (
+                geometry.x.clamp(0., (bounds.width - width).max(0.)),
+                geometry.y.clamp(0., (bounds.height - height).max(0.)),
+            )
+        } else {
+            (
+                ((bounds.width - width) / 2.).max(0.),
+                ((bounds.height - height) / 2.).max(0.),
+            )
+        };
+
+        Self {
+            position: LogicalPosition::new(origin.x + x, origin.y + y),
+            size: LogicalSize::new(width, height),
+            maximized: geometry.maximized,
+            fullscreen: geometry.fullscreen,
+        }
+    }
+
+    /// Maximized and fullscreen can only be applied once our window exists
+    pub fn apply<R: Runtime>(&self, window: &Window<R>) -> tauri::Result<()> {
+        if self.fullscreen {
+            window.set_fullscreen(true)?;
+        } else if self.maximized {
+            window.maximize()?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn geometry(x: f64, y: f64, width: f64, height: f64) -> WindowGeometry {
+        WindowGeometry {
+            monitor: Some("Display".to_string()),
+            x,
+            y,
+            width,
+            height,
+            maximized: false,
+            fullscreen: false,
+        }
+    }
+
+    #[test]
+    fn clamps_to_the_monitor() {
+        let laptop = (
+            PhysicalPosition::new(0, 0),
+            PhysicalSize::new(1920, 1080),
+            1.,
+        );
+        // to the right of the laptop, at twice the scale
+        let external = (
+            PhysicalPosition::new(1920, 0),
+            PhysicalSize::new(2560, 1440),
+            2.,
+        );
+        let tiny = (PhysicalPosition::new(0, 0), PhysicalSize::new(640, 480), 1.);
+
+        let cases = [
+            (
+                "same monitor, on screen",
+                geometry(100., 50., 1000., 700.),
+                laptop,
+                true,
+                (100., 50., 1000., 700.),
+            ),
+            (
+                "same monitor, off screen",
+                geometry(5000., -100., 1000., 700.),
+                external,
+                true,
+                (960. + 280., 0., 1000., 700.),
+            ),
+            (
+                "monitor gone, centered",
+                geometry(50., 50., 1000., 600.),
+                laptop,
+                false,
+                (460., 240., 1000., 600.),
+            ),
+            (
+                "larger than monitor",
+                geometry(200., 200., 3000., 2000.),
+                laptop,
+                true,
+                (0., 0., 1920., 1080.),
+            ),
+            (
+                "larger than monitor, centered",
+                geometry(200., 200., 3000., 2000.),
+                external,
+                false,
+                (960., 0., 1280., 720.),
+            ),
+            // we'd rather overflow a tiny monitor than go under our minimum
+            (
+                "monitor smaller than our minimum",
+                geometry(10., 10., 1000., 700.),
+                tiny,
+                true,
+                (0., 0., 800., 600.),
+            ),
+        ];
+
+        for (description, geometry, (origin, size, scale), same_monitor, expected) in cases {
+            let placement = WindowPlacement::clamped(&geometry, origin, size, scale, same_monitor);
+            let (x, y, width, height) = expected;
+
+            assert_eq!(
+                placement.position,
+                LogicalPosition::new(x, y),
+                "{description}"
+            );
+            assert_eq!(
+                placement.size,
+                LogicalSize::new(width, height),
+                "{description}"
+            );
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use crate::media_sources::MediaSource;
+use crate::state::{EnhancerAppStateManager, EnhancerAppStateManagerEmitter};
+use crate::{EnhancedResult, SETTINGS_CHANGED_EVENT};
+use anyhow::bail;
+use log::{LevelFilter, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+use tauri_plugin_store::StoreExt;
+use tokio::sync::watch;
+
+const STORE_PATH: &str = "medyia-settings.json";
+const SETTINGS_KEY: &str = "settings";
+
+const MAX_SOURCE_NAME_LENGTH: usize = 32;
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    /// Tabs that aren't showing or playing are unloaded after this long, never when unset
+    pub unload_timeout_mins: Option<u64>,
+    /// How often we look for tabs to unload
+    pub memory_check_interval_secs: u64,
+    pub tab_bar_height: f64,
+    pub source_bar_width: f64,
+    pub log_level: LogLevel,
+    /// Our sidebar order, sources missing from it follow in their usual order
+    pub source_order: Vec<MediaSource>,
+    pub hidden_sources: BTreeSet<MediaSource>,
+    pub source_names: BTreeMap<MediaSource, String>,
+    /// Whether sources open several tabs, sources missing from it use their default
+    pub multi_instance: BTreeMap<MediaSource, bool>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+/// Our current settings, subsystems subscribe to pick up changes as they're made
+pub struct AppSettings(watch::Sender<Settings>);
+
+pub fn setup_settings(app: &AppHandle) {
+    let settings = match load(app) {
+        Ok(settings) => settings,
+        Err(e) => {
+            warn!("Failed to load our settings, using the defaults: {e}");
+            Settings::default()
+        }
+    };
+    settings.apply();
+    app.app_state_mut(|state| {
+        state.set_multi_instance_sources(settings.multi_instance_sources(), app)?;
+        state.set_hidden_sources(settings.hidden_sources.clone(), false, app)
+    })
+    .log_error();
+    app.manage(AppSettings(watch::channel(settings).0));
+}
+
+/// Our current settings, or the defaults before we've loaded them
+pub fn get<R: Runtime>(app: &impl Manager<R>) -> Settings {
+    app.try_state::<AppSettings>()
+        .map(|settings| settings.0.borrow().clone())
+        .unwrap_or_default()
+}
+
+pub fn subscribe(app: &AppHandle) -> watch::Receiver<Settings> {
+    match app.try_state::<AppSettings>() {
+        Some(settings) => settings.0.subscribe(),
+        None => watch::channel(Settings::default()).1,
+    }
+}
+
+/// Update our settings, tabs of sources we're newly hiding are closed with `close_hidden_tabs` or otherwise kept
+pub fn update(app: &AppHandle, settings: Settings, close_hidden_tabs: bool) -> anyhow::Result<()> {
+    settings.validate()?;
+    // don't save settings we can't apply
+    let multi_instance_sources = settings.multi_instance_sources();
+    app.app_state(|state| state.ensure_can_set_multi_instance_sources(&multi_instance_sources))?;
+
+    let store = app.store(STORE_PATH)?;
+    store.set(SETTINGS_KEY, serde_json::to_value(&settings)?);
+    store.save()?;
+    info!("Updated our settings: {settings:?}");
+
+    settings.apply();
+    if let Some(current) = app.try_state::<AppSettings>() {
+        current.0.send_replace(settings.clone());
+    }
+    app.emit(SETTINGS_CHANGED_EVENT, &settings)?;
+
+    app.app_state_mut(|state| {
+        state.set_multi_instance_sources(multi_instance_sources, app)?;
+        state.set_hidden_sources(settings.hidden_sources, close_hidden_tabs, app)?;
+        // our tab bar and source bar may have changed size
+        state.relayout(app)
+    })
+}
+
+fn load(app: &AppHandle) -> anyhow::Result<Settings> {
+    let store = app.store(STORE_PATH)?;
+    let Some(settings) = store.get(SETTINGS_KEY) else {
+        return Ok(Settings::default());
+    };
+    let settings = serde_json::from_value::<Settings>(settings)?;
+    settings.validate()?;
+
+    Ok(settings)
+}
+
+impl Settings {
+    /// Every source in our sidebar order, hidden or not
+    pub fn ordered_sources(&self) -> Vec<MediaSource> {
+        let mut sources = self.source_order.clone();
+        sources.extend(
+            MediaSource::ALL
+                .iter()
+                .filter(|source| !self.source_order.contains(source)),
+        );
+
+        sources
+    }
+
+    /// The sources in our sidebar, in order
+    pub fn visible_sources(&self) -> Vec<MediaSource> {
+        let mut sources = self.ordered_sources();
+        sources.retain(|source| !self.hidden_sources.contains(source));
+
+        sources
+    }
+
+    pub fn source_name(&self, source: MediaSource) -> &str {
+        self.source_names
+            .get(&source)
+            .map_or(source.name(), String::as_str)
+    }
+
+    pub fn is_multi_instance(&self, source: MediaSource) -> bool {
+        self.multi_instance
+            .get(&source)
+            .copied()
+            .unwrap_or(source.multi_instance())
+    }
+
+    pub fn multi_instance_sources(&self) -> BTreeSet<MediaSource> {
+        MediaSource::ALL
+            .iter()
+            .copied()
+            .filter(|source| self.is_multi_instance(*source))
+            .collect()
+    }
+
+    pub fn unload_timeout(&self) -> Option<Duration> {
+        self.unload_timeout_mins.map(Duration::from_mins)
+    }
+
+    pub fn memory_check_interval(&self) -> Duration {
+        Duration::from_secs(self.memory_check_interval_secs)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.unload_timeout_mins == Some(0) {
+            bail!("Tabs need at least a minute before they're unloaded");
+        }
+        if !(5..=3600).contains(&self.memory_check_interval_secs) {
+            bail!("Check for idle tabs between every 5 seconds and every hour");
+        }
+        if !(32. ..=120.).contains(&self.tab_bar_height) {
+            bail!("The tab bar must be between 32 and 120 points high");
+        }
+        if !(48. ..=200.).contains(&self.source_bar_width) {
+            bail!("The source bar must be between 48 and 200 points wide");
+        }
+        let unique = self.source_order.iter().collect::<BTreeSet<_>>();
+        if unique.len() != self.source_order.len() {
+            bail!("Sources can only appear once in our order");
+        }
+        for name in self.source_names.values() {
+            if name.trim().is_empty() || name.chars().count() > MAX_SOURCE_NAME_LENGTH {
+                bail!("Source names need between 1 and {MAX_SOURCE_NAME_LENGTH} characters");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Apply what we can't read on demand
+    fn apply(&self) {
+        log::set_max_level(self.log_level.into());
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            unload_timeout_mins: Some(15),
+            memory_check_interval_secs: 30,
+            tab_bar_height: 56.,
+            source_bar_width: 76.,
+            log_level: LogLevel::Debug,
+            source_order: Vec::new(),
+            hidden_sources: BTreeSet::new(),
+            source_names: BTreeMap::new(),
+            multi_instance: BTreeMap::new(),
+        }
+    }
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Off => LevelFilter::Off,
+            LogLevel::Error => LevelFilter::Error,
+            LogLevel::Warn => LevelFilter::Warn,
+            LogLevel::Info => LevelFilter::Info,
+            LogLevel::Debug => LevelFilter::Debug,
+            LogLevel::Trace => LevelFilter::Trace,
+        }
+    }
+}
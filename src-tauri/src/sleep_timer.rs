@@ -0,0 +1,38 @@
+use crate::EnhancedResult;
+use crate::state::{AppState, EnhancerAppStateManager, EnhancerAppStateManagerEmitter};
+use log::debug;
+use std::time::Duration;
+use tauri::AppHandle;
+use tokio::time;
+
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+pub fn start_sleep_timer_monitor(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut interval = time::interval(TICK_INTERVAL);
+        loop {
+            interval.tick().await;
+            tick(&app).await.log_error();
+        }
+    });
+}
+
+async fn tick(app: &AppHandle) -> anyhow::Result<()> {
+    if !app.app_state(AppState::has_sleep_timer) {
+        return Ok(());
+    }
+
+    let track_remaining = match app.app_state(AppState::sleep_timer_position_request)? {
+        Some(pending) => match pending.reply().await {
+            Ok(position) => position.remaining(),
+            Err(e) => {
+                // we'll try again next tick
+                debug!("Couldn't get the playing position: {e}");
+                None
+            }
+        },
+        None => None,
+    };
+
+    app.app_state_mut(|state| state.tick_sleep_timer(track_remaining, time::Instant::now()))
+}
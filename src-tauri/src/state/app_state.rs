@@ -1,27 +1,68 @@
-use crate::EnhancedManager;
+use crate::controls::{GetPosition, Position};
 use crate::media_sources::MediaSource;
-use crate::osx_utils::title_bar_height;
+use crate::playback::{PlaybackEvent, PlaybackPolicy};
+use crate::rpc::{PendingRpc, RpcError};
+use crate::settings;
+use crate::state::focus_state::{FocusConfig, FocusPhase, FocusSessionRecord, FocusSessionState};
+use crate::state::interruptions::InterruptionStack;
 use crate::state::media_state::MediaStateInternal;
 use crate::state::media_state::{MediaState, TabCloseState};
+use crate::state::sleep_timer_state::{SleepTimerMode, SleepTimerState};
 use crate::state::tabs_state::TabState;
 use crate::state::{TabKey, TabKeyRef};
 use crate::utils::EnhancedWindow;
-use log::error;
+use crate::{EnhancedManager, EnhancedResult};
+use anyhow::{anyhow, bail};
+use log::{debug, error};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::sync::Mutex;
-use tauri::{Manager, Runtime};
+use std::time::{Duration, Instant};
+use tauri::{Manager, Runtime, Window};
+use tokio::time;
 
 #[derive(Serialize, Clone, Debug)]
 #[serde(rename_all = "camelCase", bound = "")]
 pub struct AppState<R: Runtime> {
     media: HashMap<MediaSource, MediaState<R>>,
+    /// Mute every tab we restore from our session
+    restore_muted: bool,
+    playback_policy: PlaybackPolicy,
+    interruptions: InterruptionStack,
+    /// Sources we never resume automatically after they were interrupted
+    no_auto_resume: BTreeSet<MediaSource>,
+    /// Sources hidden from our sidebar, their tabs may still be open in the background
+    hidden_sources: BTreeSet<MediaSource>,
+    /// Sources that open several tabs
+    multi_instance_sources: BTreeSet<MediaSource>,
+    /// Our main window is hidden behind our mini-player
+    mini_player: bool,
+    /// The tab our mini-player controls, we keep it loaded even while it's paused
+    mini_player_tab: Option<TabKey>,
+    sleep_timer: Option<SleepTimerState>,
+    focus_session: Option<FocusSessionState>,
+    focus_history: Vec<FocusSessionRecord>,
 }
 
 impl<R: Runtime> Default for AppState<R> {
     fn default() -> Self {
         Self {
             media: HashMap::default(),
+            restore_muted: false,
+            playback_policy: PlaybackPolicy::default(),
+            interruptions: InterruptionStack::default(),
+            no_auto_resume: BTreeSet::default(),
+            hidden_sources: BTreeSet::default(),
+            multi_instance_sources: MediaSource::ALL
+                .iter()
+                .copied()
+                .filter(|source| source.multi_instance())
+                .collect(),
+            mini_player: false,
+            mini_player_tab: None,
+            sleep_timer: None,
+            focus_session: None,
+            focus_history: Vec::default(),
         }
     }
 }
@@ -36,6 +77,8 @@ impl<R: Runtime> AppState<R> {
         source: MediaSource,
         url_override: Option<String>,
     ) -> anyhow::Result<TabKey> {
+        self.ensure_not_blocked(source)?;
+        self.ensure_not_hidden(source)?;
         self.state_mut(source).create_tab(url_override)
     }
 
@@ -44,6 +87,9 @@ impl<R: Runtime> AppState<R> {
         source: MediaSource,
         app: &impl Manager<R>,
     ) -> anyhow::Result<()> {
+        self.ensure_not_blocked(source)?;
+        self.ensure_not_hidden(source)?;
+
         for state in self.media.values_mut() {
             if state.source() != source {
                 state.hide_source()?
@@ -53,7 +99,19 @@ impl<R: Runtime> AppState<R> {
         self.state_mut(source).show_source(app)
     }
 
+    pub fn hide_sources(&mut self) -> anyhow::Result<()> {
+        for state in self.media.values_mut() {
+            state.hide_source()?;
+        }
+
+        Ok(())
+    }
+
     pub fn show_tab(&mut self, key: TabKeyRef, app: &impl Manager<R>) -> anyhow::Result<()> {
+        if let Some(tab) = self.tab(key) {
+            self.ensure_not_blocked(tab.source)?;
+        }
+
         for media in self.media.values_mut() {
             media.show_tab(key, app)?;
         }
@@ -84,6 +142,25 @@ impl<R: Runtime> AppState<R> {
         }
     }
 
+    pub fn pause_all(&mut self) -> anyhow::Result<()> {
+        for tab in self.tabs_mut().filter(|t| t.is_playing) {
+            tab.request_pause()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn pause_all_except(&mut self, source: MediaSource) -> anyhow::Result<()> {
+        for tab in self
+            .tabs_mut()
+            .filter(|t| t.is_playing && t.source != source)
+        {
+            tab.request_pause()?;
+        }
+
+        Ok(())
+    }
+
     pub fn toggle_playing(&mut self, app: &impl Manager<R>) -> anyhow::Result<()> {
         if self.pause_playing_tab() {
             Ok(())
@@ -92,6 +169,467 @@ impl<R: Runtime> AppState<R> {
         }
     }
 
+    /// Our mini-player starts with the playing tab, or our active one when nothing is playing
+    pub fn set_mini_player(&mut self, enabled: bool) {
+        self.mini_player = enabled;
+        self.mini_player_tab = if enabled {
+            self.playing_tab()
+                .map(|t| t.key.clone())
+                .or_else(|| self.active_tab_key())
+        } else {
+            None
+        };
+    }
+
+    pub fn mini_player_tab_key(&self) -> Option<TabKey> {
+        self.mini_player_tab.clone()
+    }
+
+    /// Float `key` in our picture-in-picture `window`, our main window moves on to another of its source's tabs
+    pub fn detach_tab(
+        &mut self,
+        key: TabKeyRef,
+        window: &Window<R>,
+        app: &impl Manager<R>,
+    ) -> anyhow::Result<()> {
+        let tab = self
+            .tab_mut(key)
+            .ok_or_else(|| anyhow!("No tab found for {key}"))?;
+        let source = tab.source;
+        let was_active = tab.is_active;
+        tab.detach(window)?;
+
+        let next = self
+            .tabs()
+            .find(|t| t.source == source && !t.detached)
+            .map(|t| t.key.clone());
+        if was_active && let Some(next) = next {
+            self.show_tab(&next, app)?;
+        }
+
+        Ok(())
+    }
+
+    /// Return our floating tab to our main window, showing it there unless its source is hidden or blocked
+    pub fn attach_tab(&mut self, app: &impl Manager<R>) -> anyhow::Result<Option<TabKey>> {
+        let Some(tab) = self.tabs().find(|t| t.detached) else {
+            return Ok(None);
+        };
+        let key = tab.key.clone();
+        let source = tab.source;
+
+        if self.hidden_sources.contains(&source) || self.ensure_not_blocked(source).is_err() {
+            self.tab_mut(&key).unwrap().attach(app)?;
+        } else {
+            for state in self.media.values_mut() {
+                if state.source() != source {
+                    state.hide_source()?
+                }
+            }
+            self.show_tab(&key, app)?;
+        }
+
+        Ok(Some(key))
+    }
+
+    pub fn detached_tab_key(&self) -> Option<TabKey> {
+        self.tabs().find(|t| t.detached).map(|t| t.key.clone())
+    }
+
+    /// Give `key` our whole window, or with none restore our usual layout
+    pub fn set_fullscreen_tab(&mut self, key: Option<TabKeyRef>) {
+        for tab in self.tabs_mut() {
+            tab.fullscreen = key.is_some_and(|key| tab.key == key);
+        }
+    }
+
+    pub fn fullscreen_tab_key(&self) -> Option<TabKey> {
+        self.tabs().find(|t| t.fullscreen).map(|t| t.key.clone())
+    }
+
+    pub fn set_cached_artwork(&mut self, key: TabKeyRef, page_artwork: &str, cached: String) {
+        if let Some(tab) = self.tab_mut(key) {
+            tab.set_cached_artwork(page_artwork, cached);
+        }
+    }
+
+    pub fn update_playback(&mut self, event: &PlaybackEvent) -> anyhow::Result<()> {
+        let mut errors = TabErrors::default();
+        // a ducked tab resuming shouldn't push back on the tab that ducked it
+        let started = event.playing
+            && self
+                .tab(&event.label)
+                .is_some_and(|t| !t.is_playing && !t.ducked);
+
+        if started {
+            // whether we resumed it or the user did it isn't waiting anymore
+            self.interruptions.remove_interrupted(&event.label);
+            // our mini-player follows whatever we're listening to
+            if self.mini_player {
+                self.mini_player_tab = Some(event.label.clone());
+            }
+
+            let policy = self.playback_policy;
+            let mut interrupted = Vec::new();
+            for tab in self.tabs_mut() {
+                if tab.is_playing && tab.key != event.label {
+                    let result = match policy {
+                        PlaybackPolicy::Exclusive => {
+                            // we count it as paused even if the page didn't hear us
+                            interrupted.push((tab.key.clone(), tab.source));
+                            tab.request_pause()
+                        }
+                        PlaybackPolicy::AllowConcurrent => Ok(()),
+                        PlaybackPolicy::DuckOthers { .. } if !tab.ducked => {
+                            tab.set_ducked(policy.duck_factor())
+                        }
+                        PlaybackPolicy::DuckOthers { .. } => Ok(()),
+                    };
+                    errors.record(&tab.key, result);
+                }
+            }
+
+            for (key, source) in interrupted {
+                if !self.no_auto_resume.contains(&source) {
+                    self.interruptions.push(key, event.label.clone());
+                }
+            }
+        }
+
+        if let Some(tab) = self.tab_mut(&event.label) {
+            tab.update_playback(event)?;
+        }
+
+        if let Some(sleep_timer) = &mut self.sleep_timer {
+            if started {
+                sleep_timer.follow(&event.label);
+            }
+            sleep_timer.on_playback(event);
+        }
+
+        if event.ended {
+            self.resume_interrupted(&event.label);
+        }
+
+        self.release_ducked()?;
+        errors.into_result()
+    }
+
+    /// Whether a tab is waiting for `by` to stop so it can resume
+    pub fn has_interrupted(&self, by: TabKeyRef) -> bool {
+        self.interruptions.has_interrupted(by)
+    }
+
+    /// Resume the tab `by` interrupted, as long as `by` and everything else is still quiet
+    ///
+    /// A tab we can't resume stays paused, the user can still play it themselves
+    pub fn resume_interrupted(&mut self, by: TabKeyRef) {
+        if self.tab(by).is_some_and(|t| t.is_playing) {
+            return;
+        }
+        if self.tabs().any(|t| t.is_playing) {
+            // something else is playing now so whatever we interrupted stays paused
+            self.interruptions.remove_interrupted_by(by);
+            return;
+        }
+
+        if let Some(key) = self.interruptions.pop_interrupted_by(by)
+            && let Some(tab) = self.tab_mut(&key)
+        {
+            if !tab.is_loaded() {
+                // resuming would reload the page from the start of the track
+                debug!("Not resuming unloaded tab {key}");
+            } else if let Err(e) = tab.request_play() {
+                error!("Failed to resume {key}: {e}");
+            }
+        }
+    }
+
+    pub fn set_auto_resume(&mut self, source: MediaSource, enabled: bool) {
+        if enabled {
+            self.no_auto_resume.remove(&source);
+        } else {
+            self.no_auto_resume.insert(source);
+        }
+    }
+
+    /// Restore the volume of ducked tabs once nothing is playing over them
+    fn release_ducked(&mut self) -> anyhow::Result<()> {
+        let mut errors = TabErrors::default();
+        if !self.tabs().any(|t| t.is_playing && !t.ducked) {
+            for tab in self.tabs_mut().filter(|t| t.ducked) {
+                errors.record(&tab.key, tab.set_ducked(None));
+            }
+        }
+
+        errors.into_result()
+    }
+
+    pub fn set_playback_policy(&mut self, policy: PlaybackPolicy) -> anyhow::Result<()> {
+        self.playback_policy = policy;
+
+        match policy {
+            PlaybackPolicy::Exclusive => {
+                // keep whichever tab we'd consider the main one and pause the rest
+                let keep = self
+                    .tabs()
+                    .find(|t| t.is_playing && !t.ducked)
+                    .map(|t| t.key.clone());
+                let mut errors = TabErrors::default();
+                for tab in self.tabs_mut() {
+                    if tab.is_playing && Some(&tab.key) != keep.as_ref() {
+                        errors.record(&tab.key, tab.request_pause());
+                    }
+                }
+                self.release_ducked()?;
+                errors.into_result()
+            }
+            PlaybackPolicy::AllowConcurrent => {
+                let mut errors = TabErrors::default();
+                for tab in self.tabs_mut().filter(|t| t.ducked) {
+                    errors.record(&tab.key, tab.set_ducked(None));
+                }
+                errors.into_result()
+            }
+            PlaybackPolicy::DuckOthers { .. } => {
+                let mut errors = TabErrors::default();
+                for tab in self.tabs_mut().filter(|t| t.ducked) {
+                    errors.record(&tab.key, tab.set_ducked(policy.duck_factor()));
+                }
+                errors.into_result()
+            }
+        }
+    }
+
+    pub fn set_restore_muted(&mut self, restore_muted: bool) {
+        self.restore_muted = restore_muted;
+    }
+
+    pub fn start_sleep_timer(&mut self, mode: SleepTimerMode) -> anyhow::Result<()> {
+        self.cancel_sleep_timer()?;
+        let tab = self.playing_tab().map(|t| t.key.clone());
+        self.sleep_timer = Some(SleepTimerState::new(mode, tab, time::Instant::now()));
+
+        Ok(())
+    }
+
+    pub fn extend_sleep_timer(&mut self, amount: u32) -> anyhow::Result<()> {
+        if let Some(sleep_timer) = &mut self.sleep_timer {
+            sleep_timer.extend(amount, time::Instant::now());
+            if !sleep_timer.is_fading() {
+                self.reset_fade()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn cancel_sleep_timer(&mut self) -> anyhow::Result<()> {
+        if let Some(sleep_timer) = self.sleep_timer.take()
+            && sleep_timer.is_fading()
+        {
+            self.reset_fade()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn has_sleep_timer(&self) -> bool {
+        self.sleep_timer.is_some()
+    }
+
+    /// Ask the tab our sleep timer follows where it is when we're waiting on the end of its track
+    pub fn sleep_timer_position_request(&self) -> Result<Option<PendingRpc<Position>>, RpcError> {
+        let tab = self
+            .sleep_timer
+            .as_ref()
+            .filter(|sleep_timer| sleep_timer.needs_track_remaining())
+            .and_then(|sleep_timer| sleep_timer.tab())
+            .and_then(|key| self.tab(key))
+            .filter(|tab| tab.is_playing);
+
+        match tab {
+            Some(tab) => tab.request(&GetPosition).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn tick_sleep_timer(
+        &mut self,
+        track_remaining: Option<Duration>,
+        now: time::Instant,
+    ) -> anyhow::Result<()> {
+        let Some(sleep_timer) = &mut self.sleep_timer else {
+            return Ok(());
+        };
+
+        if let Some(track_remaining) = track_remaining {
+            sleep_timer.set_track_remaining(track_remaining, now);
+        }
+        sleep_timer.update(now);
+
+        if sleep_timer.is_expired(now) {
+            debug!("Sleep timer expired");
+            self.sleep_timer = None;
+            self.pause_all()?;
+            self.reset_fade()?;
+        } else if let Some(factor) = sleep_timer.fade_factor(now) {
+            for tab in self.tabs().filter(|t| t.is_playing) {
+                tab.set_fade(factor)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn reset_fade(&self) -> anyhow::Result<()> {
+        for tab in self.tabs() {
+            tab.set_fade(1.)?;
+        }
+
+        Ok(())
+    }
+
+    /// Start a new focus session, returning the session it replaced
+    pub fn start_focus_session(&mut self, config: FocusConfig) -> Option<FocusSessionRecord> {
+        let replaced = self.stop_focus_session();
+        self.focus_session = Some(FocusSessionState::new(config, Instant::now()));
+
+        replaced
+    }
+
+    pub fn stop_focus_session(&mut self) -> Option<FocusSessionRecord> {
+        let record = self.focus_session.take()?.record();
+        self.focus_history.push(record.clone());
+
+        Some(record)
+    }
+
+    pub fn has_focus_session(&self) -> bool {
+        self.focus_session.is_some()
+    }
+
+    pub fn focus_config(&self) -> Option<FocusConfig> {
+        self.focus_session.as_ref().map(|s| s.config().clone())
+    }
+
+    /// Advance our focus session, returning the phase we just entered
+    pub fn tick_focus_session(&mut self, now: Instant) -> Option<FocusPhase> {
+        self.focus_session.as_mut()?.tick(now)
+    }
+
+    pub fn focus_history(&self) -> &[FocusSessionRecord] {
+        &self.focus_history
+    }
+
+    pub fn set_focus_history(&mut self, history: Vec<FocusSessionRecord>) {
+        self.focus_history = history;
+    }
+
+    /// A work block has started, so stop showing what it blocks
+    pub fn hide_blocked_sources(&mut self) -> anyhow::Result<()> {
+        let Some(focus_session) = &self.focus_session else {
+            return Ok(());
+        };
+
+        for media in self
+            .media
+            .values_mut()
+            .filter(|m| focus_session.is_blocked(m.source()))
+        {
+            media.hide_source()?;
+        }
+
+        Ok(())
+    }
+
+    fn ensure_not_blocked(&self, source: MediaSource) -> anyhow::Result<()> {
+        if let Some(focus_session) = &self.focus_session
+            && focus_session.is_blocked(source)
+        {
+            bail!("{} is blocked during a focus block", source.name());
+        }
+
+        Ok(())
+    }
+
+    fn ensure_not_hidden(&self, source: MediaSource) -> anyhow::Result<()> {
+        if self.hidden_sources.contains(&source) {
+            bail!("{} is hidden", source.name());
+        }
+
+        Ok(())
+    }
+
+    /// Whether we can convert our sources to `sources` without closing any of their tabs
+    pub fn ensure_can_set_multi_instance_sources(
+        &self,
+        sources: &BTreeSet<MediaSource>,
+    ) -> anyhow::Result<()> {
+        for (source, media) in &self.media {
+            media.ensure_can_set_multi_instance(sources.contains(source))?;
+        }
+
+        Ok(())
+    }
+
+    /// Convert our sources between one tab and several, keeping their open tab
+    ///
+    /// We refuse to turn off multiple tabs for a source while it has more than one open
+    pub fn set_multi_instance_sources(
+        &mut self,
+        sources: BTreeSet<MediaSource>,
+        app: &impl Manager<R>,
+    ) -> anyhow::Result<()> {
+        if sources == self.multi_instance_sources {
+            return Ok(());
+        }
+        // check everything first so we never convert only some of our sources
+        self.ensure_can_set_multi_instance_sources(&sources)?;
+        self.multi_instance_sources = sources;
+
+        for (source, media) in &mut self.media {
+            media.set_multi_instance(self.multi_instance_sources.contains(source))?;
+        }
+
+        // tabs gain or lose our tab bar
+        self.relayout(app)
+    }
+
+    /// Hide `hidden` from our sidebar, tabs of newly hidden sources are closed with `close_tabs` or otherwise kept
+    pub fn set_hidden_sources(
+        &mut self,
+        hidden: BTreeSet<MediaSource>,
+        close_tabs: bool,
+        app: &impl Manager<R>,
+    ) -> anyhow::Result<()> {
+        let newly_hidden = hidden
+            .difference(&self.hidden_sources)
+            .copied()
+            .collect::<Vec<_>>();
+        self.hidden_sources = hidden;
+
+        for source in newly_hidden {
+            let Some(media) = self.media.get_mut(&source) else {
+                continue;
+            };
+
+            if close_tabs {
+                let keys = media.tabs().map(|t| t.key.clone()).collect::<Vec<_>>();
+                debug!("Closing {} tabs of hidden {source:?}", keys.len());
+                for key in keys {
+                    // one tab failing to close shouldn't keep the rest open
+                    self.close_tab(&key, app).log_error();
+                }
+            } else if media.is_active() {
+                // kept tabs carry on in the background
+                media.hide_source()?;
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn close_active_tab(&mut self, app: &impl Manager<R>) -> anyhow::Result<()> {
         if let Some(active_tab) = self.active_tab_key() {
             self.close_tab(&active_tab, app)?;
@@ -106,7 +644,19 @@ impl<R: Runtime> AppState<R> {
                 TabCloseState::Closed | TabCloseState::ClosedActive(true) => break,
                 TabCloseState::ClosedActive(false) => {
                     // we closed our tab but the source didn't have any other tabs so choose another one
-                    if let Some(tab) = self.tabs_mut().next() {
+                    let hidden = &self.hidden_sources;
+                    let focus_session = &self.focus_session;
+                    if let Some(tab) =
+                        self.media
+                            .values_mut()
+                            .flat_map(|s| s.tabs_mut())
+                            .find(|t| {
+                                !hidden.contains(&t.source)
+                                    && !focus_session
+                                        .as_ref()
+                                        .is_some_and(|f| f.is_blocked(t.source))
+                            })
+                    {
                         tab.show(app)?;
                     }
                     break;
@@ -115,16 +665,28 @@ impl<R: Runtime> AppState<R> {
             }
         }
 
-        Ok(())
+        if self.mini_player_tab.as_deref() == Some(key) {
+            self.mini_player_tab = self
+                .playing_tab()
+                .map(|t| t.key.clone())
+                .or_else(|| self.active_tab_key());
+        }
+
+        // the tab we closed may have been playing over other tabs
+        self.interruptions.remove_interrupted(key);
+        self.resume_interrupted(key);
+        self.release_ducked()
     }
 
     pub fn relayout(&self, app: &impl Manager<R>) -> anyhow::Result<()> {
-        match app.main_window().available_size() {
+        let window = app.main_window();
+        match window.available_size() {
             Ok(window_size) => {
-                let title_height = title_bar_height(&app.main_window());
+                let title_height = window.title_bar_height();
+                let settings = settings::get(app);
 
                 for source in self.media.values() {
-                    source.relayout_advanced(window_size, title_height)?;
+                    source.relayout_advanced(window_size, title_height, &settings)?;
                 }
             }
             Err(e) => {
@@ -138,16 +700,34 @@ impl<R: Runtime> AppState<R> {
     pub fn read_session(&self) -> AppStateSession<R> {
         AppStateSession {
             tabs: self.tabs().cloned().collect(),
+            restore_muted: self.restore_muted,
+            playback_policy: self.playback_policy,
+            no_auto_resume: self.no_auto_resume.clone(),
         }
     }
 
     pub fn restore_session(
         &mut self,
-        AppStateSession { tabs }: AppStateSession<R>,
+        AppStateSession {
+            tabs,
+            restore_muted,
+            playback_policy,
+            no_auto_resume,
+        }: AppStateSession<R>,
         app: &impl Manager<R>,
     ) -> anyhow::Result<()> {
-        for tab in tabs {
-            let is_active = tab.is_active.then(|| tab.key.clone());
+        self.restore_muted = restore_muted;
+        self.playback_policy = playback_policy;
+        self.no_auto_resume = no_auto_resume;
+
+        for mut tab in tabs {
+            if restore_muted {
+                tab.muted = true;
+            }
+
+            // we don't show tabs of sources that have since been hidden
+            let is_active = (tab.is_active && !self.hidden_sources.contains(&tab.source))
+                .then(|| tab.key.clone());
             self.state_mut(tab.source).create_tab_advanced(tab)?;
 
             if let Some(key) = is_active {
@@ -178,6 +758,15 @@ impl<R: Runtime> AppState<R> {
         self.tabs_mut().find(|t| t.is_playing)
     }
 
+    /// Whether each of our tabs is playing
+    pub fn playing_by_tab(&self) -> HashMap<TabKey, bool> {
+        self.tabs().map(|t| (t.key.clone(), t.is_playing)).collect()
+    }
+
+    pub fn tab(&self, key: TabKeyRef) -> Option<&TabState<R>> {
+        self.tabs().find(|t| t.key == key)
+    }
+
     pub fn tab_mut(&mut self, key: TabKeyRef) -> Option<&mut TabState<R>> {
         self.tabs_mut().find(|t| t.key == key)
     }
@@ -191,26 +780,51 @@ impl<R: Runtime> AppState<R> {
     }
 
     pub fn state_json(&self) -> anyhow::Result<String> {
-        let active_sources = self
-            .media
-            .values()
-            .filter(|s| s.is_active())
-            .collect::<Vec<_>>();
-        let playing_sources = self
-            .media
-            .values()
-            .filter(|s| s.is_playing())
+        Ok(serde_json::to_string(&self)?)
+    }
+
+    /// Pages report their own playback and can fail our requests, so we fix up whatever they've left inconsistent
+    pub(super) fn repair_invariants(&mut self) {
+        let active = self
+            .tabs()
+            .filter(|t| t.is_active)
+            .map(|t| t.key.clone())
             .collect::<Vec<_>>();
-        assert!(
-            active_sources.len() <= 1,
-            "Found multiple active media: {active_sources:#?}"
-        );
-        assert!(
-            playing_sources.len() <= 1,
-            "Found multiple playing media: {playing_sources:#?}"
-        );
+        if let [keep, rest @ ..] = active.as_slice()
+            && !rest.is_empty()
+        {
+            error!("Found multiple active tabs {active:?}, keeping {keep}");
+            for tab in self.tabs_mut().filter(|t| t.is_active && &t.key != keep) {
+                tab.hide().log_error();
+            }
+        }
 
-        Ok(serde_json::to_string(&self)?)
+        match self.playback_policy {
+            PlaybackPolicy::Exclusive => {
+                let playing = self
+                    .tabs()
+                    .filter(|t| t.is_playing)
+                    .map(|t| t.key.clone())
+                    .collect::<Vec<_>>();
+                if let [keep, rest @ ..] = playing.as_slice()
+                    && !rest.is_empty()
+                {
+                    error!(
+                        "Found multiple playing tabs {playing:?} with an exclusive policy, keeping {keep}"
+                    );
+                    for tab in self.tabs_mut().filter(|t| t.is_playing && &t.key != keep) {
+                        tab.request_pause().log_error();
+                    }
+                }
+            }
+            PlaybackPolicy::AllowConcurrent => {
+                for tab in self.tabs_mut().filter(|t| t.ducked) {
+                    error!("Found {} ducked without a ducking policy", tab.key);
+                    tab.set_ducked(None).log_error();
+                }
+            }
+            PlaybackPolicy::DuckOthers { .. } => (),
+        }
     }
 
     fn maybe_state_mut(&mut self, source: MediaSource) -> Option<&mut MediaState<R>> {
@@ -218,9 +832,10 @@ impl<R: Runtime> AppState<R> {
     }
 
     fn state_mut(&mut self, source: MediaSource) -> &mut MediaState<R> {
+        let multi_instance = self.multi_instance_sources.contains(&source);
         self.media
             .entry(source)
-            .or_insert_with(|| MediaState::new(source))
+            .or_insert_with(|| MediaState::new(source, multi_instance))
     }
 }
 
@@ -228,4 +843,257 @@ impl<R: Runtime> AppState<R> {
 #[serde(bound = "")]
 pub struct AppStateSession<R: Runtime> {
     tabs: Vec<TabState<R>>,
+    #[serde(default)]
+    restore_muted: bool,
+    #[serde(default)]
+    playback_policy: PlaybackPolicy,
+    #[serde(default)]
+    no_auto_resume: BTreeSet<MediaSource>,
+}
+
+/// Failures from requests to several tabs, we carry on past them so one page can't leave the rest half updated
+#[derive(Default)]
+struct TabErrors(Vec<(TabKey, RpcError)>);
+
+impl TabErrors {
+    fn record<T>(&mut self, key: TabKeyRef, result: Result<T, RpcError>) {
+        if let Err(e) = result {
+            self.0.push((key.to_string(), e));
+        }
+    }
+
+    fn into_result(self) -> anyhow::Result<()> {
+        if self.0.is_empty() {
+            return Ok(());
+        }
+
+        let errors = self
+            .0
+            .iter()
+            .map(|(key, e)| format!("{key}: {e}"))
+            .collect::<Vec<_>>();
+        bail!("Failed to update tabs: {}", errors.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::focus_state::BreakAction;
+    use tauri::test::{MockRuntime, mock_builder, mock_context, noop_assets};
+
+    fn event(label: &str, playing: bool, ended: bool) -> PlaybackEvent {
+        PlaybackEvent {
+            label: label.to_string(),
+            playing,
+            ended,
+            audible: playing,
+            muted: false,
+            title: None,
+            artist: None,
+            artwork_url: None,
+            url: None,
+            duration: None,
+        }
+    }
+
+    fn playing(state: &AppState<MockRuntime>) -> Vec<&str> {
+        let mut keys = state
+            .tabs()
+            .filter(|t| t.is_playing)
+            .map(|t| t.key.as_str())
+            .collect::<Vec<_>>();
+        keys.sort();
+        keys
+    }
+
+    /// Our tabs are never loaded here, so resuming only ever forgets what we interrupted
+    fn setup() -> (AppState<MockRuntime>, TabKey, TabKey) {
+        let mut state = AppState::default();
+        let first = state.create_tab(MediaSource::YouTube, None).unwrap();
+        let second = state.create_tab(MediaSource::SoundCloud, None).unwrap();
+
+        state.update_playback(&event(&first, true, false)).unwrap();
+        state.update_playback(&event(&second, true, false)).unwrap();
+
+        (state, first, second)
+    }
+
+    #[test]
+    fn starting_a_tab_interrupts_the_playing_one() {
+        let (state, first, second) = setup();
+
+        assert_eq!(playing(&state), [second.as_str()]);
+        assert!(state.has_interrupted(&second));
+        assert!(!state.has_interrupted(&first));
+    }
+
+    #[test]
+    fn interrupted_tab_resumes_when_interrupter_stops() {
+        let (mut state, _, second) = setup();
+
+        state
+            .update_playback(&event(&second, false, false))
+            .unwrap();
+        assert!(state.has_interrupted(&second));
+
+        state.resume_interrupted(&second);
+        assert!(!state.has_interrupted(&second));
+        assert_eq!(playing(&state), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn interrupted_tab_resumes_when_interrupter_ends() {
+        let (mut state, _, second) = setup();
+
+        state.update_playback(&event(&second, false, true)).unwrap();
+        assert!(!state.has_interrupted(&second));
+    }
+
+    #[test]
+    fn interrupter_still_playing_keeps_waiting() {
+        let (mut state, _, second) = setup();
+
+        state.resume_interrupted(&second);
+        assert!(state.has_interrupted(&second));
+    }
+
+    #[test]
+    fn something_else_playing_forgets_interruptions() {
+        let (mut state, _, second) = setup();
+        let third = state.create_tab(MediaSource::YouTube, None).unwrap();
+        state
+            .set_playback_policy(PlaybackPolicy::AllowConcurrent)
+            .unwrap();
+
+        state.update_playback(&event(&third, true, false)).unwrap();
+        state
+            .update_playback(&event(&second, false, false))
+            .unwrap();
+        state.resume_interrupted(&second);
+
+        assert!(!state.has_interrupted(&second));
+        assert_eq!(playing(&state), [third.as_str()]);
+    }
+
+    #[test]
+    fn starting_interrupted_tab_forgets_it() {
+        let (mut state, first, second) = setup();
+
+        state.update_playback(&event(&first, true, false)).unwrap();
+
+        assert!(!state.has_interrupted(&second));
+        assert!(state.has_interrupted(&first));
+        assert_eq!(playing(&state), [first.as_str()]);
+    }
+
+    #[test]
+    fn no_auto_resume_sources_are_not_remembered() {
+        let mut state = AppState::<MockRuntime>::default();
+        state.set_auto_resume(MediaSource::YouTube, false);
+        let first = state.create_tab(MediaSource::YouTube, None).unwrap();
+        let second = state.create_tab(MediaSource::SoundCloud, None).unwrap();
+
+        state.update_playback(&event(&first, true, false)).unwrap();
+        state.update_playback(&event(&second, true, false)).unwrap();
+
+        assert!(!state.has_interrupted(&second));
+        assert_eq!(playing(&state), [second.as_str()]);
+    }
+
+    #[test]
+    fn closing_interrupter_with_unloaded_tab_succeeds() {
+        let app = mock_builder().build(mock_context(noop_assets())).unwrap();
+        let (mut state, first, second) = setup();
+
+        state.close_tab(&second, &app).unwrap();
+
+        assert!(state.tab(&second).is_none());
+        assert!(!state.tab(&first).unwrap().is_playing());
+        assert!(!state.has_interrupted(&second));
+    }
+
+    #[test]
+    fn closing_interrupted_tab_forgets_it() {
+        let app = mock_builder().build(mock_context(noop_assets())).unwrap();
+        let (mut state, first, second) = setup();
+
+        state.close_tab(&first, &app).unwrap();
+
+        assert!(!state.has_interrupted(&second));
+        assert_eq!(playing(&state), [second.as_str()]);
+    }
+
+    #[test]
+    fn refuses_to_close_tabs_turning_off_multiple_tabs() {
+        let mut state = AppState::<MockRuntime>::default();
+        let single = BTreeSet::new();
+        let first = state.create_tab(MediaSource::YouTube, None).unwrap();
+        assert!(state.ensure_can_set_multi_instance_sources(&single).is_ok());
+
+        let second = state.create_tab(MediaSource::YouTube, None).unwrap();
+        state.update_playback(&event(&second, true, false)).unwrap();
+        assert!(
+            state
+                .ensure_can_set_multi_instance_sources(&single)
+                .is_err()
+        );
+
+        let media = state.media.get_mut(&MediaSource::YouTube).unwrap();
+        assert!(media.set_multi_instance(false).is_err());
+        assert!(state.tab(&first).is_some());
+        assert!(state.tab(&second).is_some_and(|t| t.is_playing()));
+    }
+
+    #[test]
+    fn hiding_sources_closes_every_tab() {
+        let app = mock_builder().build(mock_context(noop_assets())).unwrap();
+        let (mut state, first, second) = setup();
+        let third = state.create_tab(MediaSource::YouTube, None).unwrap();
+
+        let hidden = BTreeSet::from([MediaSource::YouTube, MediaSource::SoundCloud]);
+        state.set_hidden_sources(hidden, true, &app).unwrap();
+
+        for key in [first, second, third] {
+            assert!(state.tab(&key).is_none(), "{key} is still open");
+        }
+    }
+
+    fn blocking_youtube() -> FocusConfig {
+        FocusConfig {
+            work_minutes: 25,
+            break_minutes: 5,
+            break_action: BreakAction::Pause,
+            block_distracting: true,
+            blocked_sources: BTreeSet::from([MediaSource::YouTube]),
+        }
+    }
+
+    #[test]
+    fn work_blocks_hide_blocked_sources() {
+        let mut state = AppState::<MockRuntime>::default();
+        let youtube = state.create_tab(MediaSource::YouTube, None).unwrap();
+        state.tab_mut(&youtube).unwrap().is_active = true;
+
+        state.start_focus_session(blocking_youtube());
+        state.hide_blocked_sources().unwrap();
+
+        assert_eq!(state.active_tab_key(), None);
+        assert!(state.tab(&youtube).is_some());
+    }
+
+    #[test]
+    fn closing_a_tab_doesnt_fall_back_to_a_blocked_one() {
+        let app = mock_builder().build(mock_context(noop_assets())).unwrap();
+        let mut state = AppState::<MockRuntime>::default();
+        let youtube = state.create_tab(MediaSource::YouTube, None).unwrap();
+        let soundcloud = state.create_tab(MediaSource::SoundCloud, None).unwrap();
+        state.tab_mut(&soundcloud).unwrap().is_active = true;
+        state.start_focus_session(blocking_youtube());
+
+        state.close_tab(&soundcloud, &app).unwrap();
+
+        assert_eq!(state.active_tab_key(), None);
+        assert!(state.tab(&youtube).is_some_and(|t| !t.is_active));
+    }
 }
@@ -0,0 +1,208 @@
+use crate::media_sources::MediaSource;
+use anyhow::bail;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::time::{Duration, Instant};
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FocusConfig {
+    pub work_minutes: u32,
+    pub break_minutes: u32,
+    #[serde(default)]
+    pub break_action: BreakAction,
+    /// Refuse to show `blocked_sources` during work blocks
+    #[serde(default)]
+    pub block_distracting: bool,
+    #[serde(default = "default_blocked_sources")]
+    pub blocked_sources: BTreeSet<MediaSource>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum BreakAction {
+    #[default]
+    Pause,
+    SwitchTo {
+        source: MediaSource,
+    },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum FocusPhase {
+    Work,
+    Break,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct FocusSessionState {
+    config: FocusConfig,
+    phase: FocusPhase,
+    completed_work_blocks: u32,
+    remaining_secs: u64,
+    started: DateTime<Utc>,
+    #[serde(skip)]
+    phase_ends: Instant,
+}
+
+/// A finished focus session
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FocusSessionRecord {
+    pub started: DateTime<Utc>,
+    pub ended: DateTime<Utc>,
+    pub work_blocks: u32,
+    pub work_minutes: u32,
+}
+
+impl FocusConfig {
+    /// Empty phases would flip between work and break on every tick
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.work_minutes == 0 {
+            bail!("Work blocks need at least a minute");
+        }
+        if self.break_minutes == 0 {
+            bail!("Breaks need at least a minute");
+        }
+
+        Ok(())
+    }
+}
+
+impl FocusSessionState {
+    pub(super) fn new(config: FocusConfig, now: Instant) -> Self {
+        let phase_ends = now + Duration::from_mins(u64::from(config.work_minutes));
+        let mut state = Self {
+            config,
+            phase: FocusPhase::Work,
+            completed_work_blocks: 0,
+            remaining_secs: 0,
+            started: Utc::now(),
+            phase_ends,
+        };
+        state.update(now);
+
+        state
+    }
+
+    /// Move on to our next phase once the current one is over, returning the phase we entered
+    pub(super) fn tick(&mut self, now: Instant) -> Option<FocusPhase> {
+        let entered = if now >= self.phase_ends {
+            let (phase, minutes) = match self.phase {
+                FocusPhase::Work => {
+                    self.completed_work_blocks += 1;
+                    (FocusPhase::Break, self.config.break_minutes)
+                }
+                FocusPhase::Break => (FocusPhase::Work, self.config.work_minutes),
+            };
+            self.phase = phase;
+            self.phase_ends = now + Duration::from_mins(u64::from(minutes));
+
+            Some(phase)
+        } else {
+            None
+        };
+        self.update(now);
+
+        entered
+    }
+
+    pub(super) fn is_blocked(&self, source: MediaSource) -> bool {
+        self.phase == FocusPhase::Work
+            && self.config.block_distracting
+            && self.config.blocked_sources.contains(&source)
+    }
+
+    pub(super) fn config(&self) -> &FocusConfig {
+        &self.config
+    }
+
+    pub(super) fn record(&self) -> FocusSessionRecord {
+        FocusSessionRecord {
+            started: self.started,
+            ended: Utc::now(),
+            work_blocks: self.completed_work_blocks,
+            work_minutes: self.completed_work_blocks * self.config.work_minutes,
+        }
+    }
+
+    fn update(&mut self, now: Instant) {
+        self.remaining_secs = self.phase_ends.saturating_duration_since(now).as_secs();
+    }
+}
+
+fn default_blocked_sources() -> BTreeSet<MediaSource> {
+    BTreeSet::from([MediaSource::YouTube])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(work_minutes: u32, break_minutes: u32) -> FocusConfig {
+        FocusConfig {
+            work_minutes,
+            break_minutes,
+            break_action: BreakAction::Pause,
+            block_distracting: true,
+            blocked_sources: default_blocked_sources(),
+        }
+    }
+
+    #[test]
+    fn validate_cases() {
+        let cases = [
+            (25, 5, true),
+            (1, 1, true),
+            (0, 5, false),
+            (25, 0, false),
+            (0, 0, false),
+        ];
+
+        for (work_minutes, break_minutes, valid) in cases {
+            assert_eq!(
+                config(work_minutes, break_minutes).validate().is_ok(),
+                valid,
+                "{work_minutes} {break_minutes}"
+            );
+        }
+    }
+
+    #[test]
+    fn blocks_only_during_work() {
+        let now = Instant::now();
+        let mut state = FocusSessionState::new(config(25, 5), now);
+        assert!(state.is_blocked(MediaSource::YouTube));
+        assert!(!state.is_blocked(MediaSource::BrainFm));
+
+        assert_eq!(
+            state.tick(now + Duration::from_mins(25)),
+            Some(FocusPhase::Break)
+        );
+        assert!(!state.is_blocked(MediaSource::YouTube));
+
+        assert_eq!(
+            state.tick(now + Duration::from_mins(30)),
+            Some(FocusPhase::Work)
+        );
+        assert!(state.is_blocked(MediaSource::YouTube));
+    }
+
+    #[test]
+    fn phases_last_their_minutes() {
+        let now = Instant::now();
+        let mut state = FocusSessionState::new(config(25, 5), now);
+
+        assert_eq!(state.tick(now + Duration::from_secs(25 * 60 - 1)), None);
+        assert_eq!(
+            state.tick(now + Duration::from_mins(25)),
+            Some(FocusPhase::Break)
+        );
+        assert_eq!(state.tick(now + Duration::from_mins(26)), None);
+        assert_eq!(state.record().work_blocks, 1);
+        assert_eq!(state.record().work_minutes, 25);
+    }
+}
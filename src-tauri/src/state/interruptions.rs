@@ -0,0 +1,95 @@
+use crate::state::{TabKey, TabKeyRef};
+use serde::Serialize;
+
+/// Tabs that were paused because another tab started playing, most recent last
+#[derive(Serialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct InterruptionStack {
+    interruptions: Vec<Interruption>,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+struct Interruption {
+    interrupted: TabKey,
+    by: TabKey,
+}
+
+impl InterruptionStack {
+    pub(super) fn push(&mut self, interrupted: TabKey, by: TabKey) {
+        // a tab only waits on whoever interrupted it last
+        self.remove_interrupted(&interrupted);
+        self.interruptions.push(Interruption { interrupted, by });
+    }
+
+    /// Forget about `key` waiting to be resumed, either it resumed or it's gone
+    pub(super) fn remove_interrupted(&mut self, key: TabKeyRef) {
+        self.interruptions.retain(|i| i.interrupted != key);
+    }
+
+    /// Forget everything `by` interrupted, the user moved on to something else
+    pub(super) fn remove_interrupted_by(&mut self, by: TabKeyRef) {
+        self.interruptions.retain(|i| i.by != by);
+    }
+
+    /// Pop the most recent interruption if it was caused by `by`
+    pub(super) fn pop_interrupted_by(&mut self, by: TabKeyRef) -> Option<TabKey> {
+        self.interruptions
+            .pop_if(|i| i.by == by)
+            .map(|i| i.interrupted)
+    }
+
+    pub(super) fn has_interrupted(&self, by: TabKeyRef) -> bool {
+        self.interruptions.iter().any(|i| i.by == by)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(stack: &InterruptionStack) -> Vec<(&str, &str)> {
+        stack
+            .interruptions
+            .iter()
+            .map(|i| (i.interrupted.as_str(), i.by.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn resumes_most_recent_first() {
+        let mut stack = InterruptionStack::default();
+        stack.push("a".into(), "b".into());
+        stack.push("b".into(), "c".into());
+
+        assert_eq!(stack.pop_interrupted_by("b"), None);
+        assert_eq!(stack.pop_interrupted_by("c"), Some("b".into()));
+        assert_eq!(stack.pop_interrupted_by("b"), Some("a".into()));
+        assert_eq!(stack.pop_interrupted_by("b"), None);
+    }
+
+    #[test]
+    fn waits_on_latest_interrupter() {
+        let mut stack = InterruptionStack::default();
+        stack.push("a".into(), "b".into());
+        stack.push("a".into(), "c".into());
+
+        assert_eq!(keys(&stack), [("a", "c")]);
+        assert!(!stack.has_interrupted("b"));
+        assert!(stack.has_interrupted("c"));
+    }
+
+    #[test]
+    fn forgets_closed_tabs() {
+        let mut stack = InterruptionStack::default();
+        stack.push("a".into(), "c".into());
+        stack.push("b".into(), "c".into());
+        stack.push("c".into(), "d".into());
+
+        stack.remove_interrupted("a");
+        assert_eq!(keys(&stack), [("b", "c"), ("c", "d")]);
+
+        stack.remove_interrupted_by("c");
+        assert_eq!(keys(&stack), [("c", "d")]);
+    }
+}
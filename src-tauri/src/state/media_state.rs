@@ -1,10 +1,13 @@
 use crate::media_sources::MediaSource;
+use crate::settings::Settings;
 use crate::state::tabs_state::TabState;
 use crate::state::{TabKey, TabKeyRef};
+use anyhow::bail;
 use enum_dispatch::enum_dispatch;
 use log::debug;
 use serde::Serialize;
 use std::error::Error;
+use std::mem;
 use std::time::Duration;
 use tauri::{LogicalSize, Manager, Runtime};
 
@@ -32,13 +35,56 @@ pub(super) struct MultiMediaState<R: Runtime> {
     last_active: Option<TabKey>,
 }
 impl<R: Runtime> MediaState<R> {
-    pub(super) fn new(source: MediaSource) -> Self {
-        if source.multi_instance() {
+    pub(super) fn new(source: MediaSource, multi_instance: bool) -> Self {
+        if multi_instance {
             Self::Multi(MultiMediaState::new(source))
         } else {
             Self::Single(SingleMediaState::new(source))
         }
     }
+
+    /// Whether we can convert to `multi_instance` without closing any tabs
+    pub(super) fn ensure_can_set_multi_instance(&self, multi_instance: bool) -> anyhow::Result<()> {
+        if let Self::Multi(state) = self
+            && !multi_instance
+            && state.tabs.len() > 1
+        {
+            bail!(
+                "Close all but one {} tab before turning off multiple tabs",
+                state.source.name()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Convert between one tab and several, keeping our open tab as it is so switching doesn't interrupt anything
+    pub(super) fn set_multi_instance(&mut self, multi_instance: bool) -> anyhow::Result<()> {
+        self.ensure_can_set_multi_instance(multi_instance)?;
+
+        let source = self.source();
+        match self {
+            Self::Single(state) if multi_instance => {
+                let tab = state.tab.take();
+                debug!("Converting {source:?} to multi instance");
+
+                *self = Self::Multi(MultiMediaState {
+                    source,
+                    last_active: tab.as_ref().map(|t| t.key.clone()),
+                    tabs: tab.into_iter().collect(),
+                });
+            }
+            Self::Multi(state) if !multi_instance => {
+                let tab = mem::take(&mut state.tabs).pop();
+                debug!("Converting {source:?} to single instance");
+
+                *self = Self::Single(SingleMediaState { source, tab });
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
 }
 
 // impl<R: Runtime> MediaState<R> {
@@ -193,9 +239,10 @@ pub trait MediaStateInternal<R: Runtime> {
         &self,
         window_size: LogicalSize<f64>,
         title_bar_height: f64,
+        settings: &Settings,
     ) -> tauri::Result<()> {
         for tab in self.tabs() {
-            tab.relayout_advanced(window_size, title_bar_height)?;
+            tab.relayout_advanced(window_size, title_bar_height, settings)?;
         }
 
         Ok(())
@@ -336,10 +383,13 @@ impl<R: Runtime> MediaStateInternal<R> for MultiMediaState<R> {
     fn show_source(&mut self, app: &impl Manager<R>) -> anyhow::Result<()> {
         debug!("Showing source: {:?}", self.source);
 
+        // we leave a floating tab where it is unless it's all we have
         let tab_key = if let Some(last_active) = &self.last_active
-            && self.tab(last_active).is_some()
+            && self.tab(last_active).is_some_and(|t| !t.detached)
         {
             last_active.clone()
+        } else if let Some(tab) = self.tabs.iter().find(|t| !t.detached) {
+            tab.key.clone()
         } else if !self.tabs.is_empty() {
             self.tabs[0].key.clone()
         } else {
@@ -415,19 +465,12 @@ impl<R: Runtime> MediaStateInternal<R> for MultiMediaState<R> {
     }
 
     fn is_active(&self) -> bool {
-        match self.tabs.iter().filter(|t| t.is_active).count() {
-            0 => false,
-            1 => true,
-            multiple => panic!("{multiple} tabs are active"),
-        }
+        // more than one active tab is repaired by our app state
+        self.tabs.iter().any(|t| t.is_active)
     }
 
     fn is_playing(&self) -> bool {
-        match self.tabs.iter().filter(|t| t.is_playing).count() {
-            0 => false,
-            1 => true,
-            multiple => panic!("{multiple} tabs are playing"),
-        }
+        self.tabs.iter().any(|t| t.is_playing)
     }
 
     fn tabs(&self) -> Box<dyn Iterator<Item = &TabState<R>> + '_> {
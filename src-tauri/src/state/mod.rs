@@ -1,9 +1,15 @@
 mod app_state;
+mod focus_state;
+mod interruptions;
 mod media_state;
+mod sleep_timer_state;
 mod tabs_state;
 
-use crate::BACKEND_STATE_EVENT;
+use crate::{BACKEND_STATE_EVENT, EnhancedResult};
+use crate::{media_bridge, mqtt, now_playing};
 pub use app_state::*;
+pub use focus_state::{BreakAction, FocusConfig, FocusPhase, FocusSessionRecord};
+pub use sleep_timer_state::SleepTimerMode;
 use std::sync::Mutex;
 use tauri::{Emitter, Manager, Runtime};
 
@@ -51,18 +57,64 @@ where
         let result = {
             let mutex = self.state::<Mutex<AppState<R>>>();
             let mut state = mutex.lock().unwrap();
-            f(&mut state)?
+            let result = f(&mut state);
+            // even a failed update may have left some tabs changed
+            state.repair_invariants();
+            result
         };
 
-        // after mutating our event emit an updated tab event
-        self.emit_app_state()?;
+        // after mutating our event emit an updated tab event, failed or not
+        let emitted = self.emit_app_state();
+        let value = result?;
+        emitted?;
 
-        Ok(result)
+        Ok(value)
     }
 
     fn emit_app_state(&self) -> anyhow::Result<()> {
         let json = self.app_state(AppState::state_json)?;
         self.emit_str(BACKEND_STATE_EVENT, json)?;
+        now_playing::export(self).log_error();
+        mqtt::update(self);
+        media_bridge::update(self);
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::media_sources::MediaSource;
+    use tauri::Listener;
+    use tauri::test::{MockRuntime, mock_builder, mock_context, noop_assets};
+
+    #[test]
+    fn failed_updates_still_emit_their_changes() {
+        let app = mock_builder()
+            .plugin(tauri_plugin_store::Builder::new().build())
+            .manage(AppState::<MockRuntime>::new())
+            .build(mock_context(noop_assets()))
+            .unwrap();
+        let handle = app.handle();
+        let emitted = std::sync::Arc::new(Mutex::new(Vec::new()));
+        let listener = emitted.clone();
+        handle.listen(BACKEND_STATE_EVENT, move |event| {
+            listener.lock().unwrap().push(event.payload().to_string());
+        });
+
+        let mut created = String::new();
+        let result: anyhow::Result<()> = handle.app_state_mut(|state| {
+            created = state.create_tab(MediaSource::YouTube, None)?;
+            anyhow::bail!("a later tab failed")
+        });
+
+        assert_eq!(result.unwrap_err().to_string(), "a later tab failed");
+        let emitted = emitted.lock().unwrap();
+        assert_eq!(emitted.len(), 1);
+        assert!(
+            emitted[0].contains(&created),
+            "{created} missing from {}",
+            emitted[0]
+        );
+    }
+}
@@ -0,0 +1,330 @@
+use crate::playback::PlaybackEvent;
+use crate::state::{TabKey, TabKeyRef};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// How long we spend fading out before pausing
+pub const FADE_DURATION: Duration = Duration::from_secs(30);
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "mode", rename_all = "camelCase")]
+pub enum SleepTimerMode {
+    /// Pause after a fixed amount of time
+    Minutes { minutes: u32 },
+    /// Pause once the current track finishes
+    EndOfTrack,
+    /// Pause once `count` tracks have finished, including the current one
+    Tracks { count: u32 },
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct SleepTimerState {
+    mode: SleepTimerMode,
+    /// Seconds until we pause, `None` until we know when the last track ends
+    remaining_secs: Option<u64>,
+    tracks_remaining: Option<u32>,
+    fading: bool,
+    #[serde(skip)]
+    deadline: Option<Instant>,
+    /// The tab whose tracks we count, whichever last started playing
+    #[serde(skip)]
+    tab: Option<TabKey>,
+    #[serde(skip)]
+    current_track: Option<(Option<String>, Option<String>)>,
+}
+
+impl SleepTimerState {
+    pub(super) fn new(mode: SleepTimerMode, tab: Option<TabKey>, now: Instant) -> Self {
+        let (deadline, tracks_remaining) = match mode {
+            SleepTimerMode::Minutes { minutes } => {
+                (Some(now + Duration::from_mins(u64::from(minutes))), None)
+            }
+            SleepTimerMode::EndOfTrack => (None, Some(1)),
+            SleepTimerMode::Tracks { count } => (None, Some(count.max(1))),
+        };
+
+        let mut state = Self {
+            mode,
+            remaining_secs: None,
+            tracks_remaining,
+            fading: false,
+            deadline,
+            tab,
+            current_track: None,
+        };
+        state.update(now);
+
+        state
+    }
+
+    /// Add `amount` minutes or tracks, depending on our mode
+    pub(super) fn extend(&mut self, amount: u32, now: Instant) {
+        match self.mode {
+            SleepTimerMode::Minutes { minutes } => {
+                self.mode = SleepTimerMode::Minutes {
+                    minutes: minutes.saturating_add(amount),
+                };
+                self.deadline = self
+                    .deadline
+                    .map(|d| d + Duration::from_mins(u64::from(amount)));
+            }
+            SleepTimerMode::EndOfTrack | SleepTimerMode::Tracks { .. } => {
+                let tracks_remaining = self.tracks_remaining.unwrap_or(0).saturating_add(amount);
+                self.mode = SleepTimerMode::Tracks {
+                    count: tracks_remaining,
+                };
+                self.tracks_remaining = Some(tracks_remaining);
+                // we're no longer on our last track
+                self.deadline = None;
+            }
+        }
+
+        self.update(now);
+    }
+
+    /// Count tracks on `tab` from now on, the user switched to it
+    pub(super) fn follow(&mut self, tab: TabKeyRef) {
+        if self.tab.as_deref() != Some(tab) {
+            self.tab = Some(tab.to_string());
+            // whatever it's playing is the track we're on
+            self.current_track = None;
+        }
+    }
+
+    pub(super) fn on_playback(&mut self, event: &PlaybackEvent) {
+        let Some(tracks_remaining) = &mut self.tracks_remaining else {
+            return;
+        };
+        if self.tab.as_deref() != Some(event.label.as_str()) {
+            return;
+        }
+
+        let track = Some((event.title.clone(), event.artist.clone()));
+        if event.ended {
+            *tracks_remaining = tracks_remaining.saturating_sub(1);
+            // the next track starting isn't another change
+            self.current_track = None;
+        } else if event.playing && self.current_track != track {
+            // pages fill in their title and artist a little after the track starts
+            let late_metadata = self
+                .current_track
+                .as_ref()
+                .is_some_and(|(title, _)| title.is_none() || *title == event.title);
+            if self.current_track.is_some() && !late_metadata {
+                *tracks_remaining = tracks_remaining.saturating_sub(1);
+                self.deadline = None;
+            }
+            self.current_track = track;
+        }
+    }
+
+    /// The tab whose tracks we count
+    pub(super) fn tab(&self) -> Option<TabKeyRef<'_>> {
+        self.tab.as_deref()
+    }
+
+    /// Whether we're waiting on the playing tab to tell us how long its track has left
+    pub(super) fn needs_track_remaining(&self) -> bool {
+        self.tracks_remaining == Some(1)
+    }
+
+    pub(super) fn set_track_remaining(&mut self, remaining: Duration, now: Instant) {
+        if self.needs_track_remaining() {
+            self.deadline = Some(now + remaining);
+            self.update(now);
+        }
+    }
+
+    pub(super) fn update(&mut self, now: Instant) {
+        self.remaining_secs = self.remaining(now).map(|r| r.as_secs());
+        self.fading = self.fade_factor(now).is_some();
+    }
+
+    pub(super) fn is_expired(&self, now: Instant) -> bool {
+        self.tracks_remaining == Some(0) || self.deadline.is_some_and(|d| d <= now)
+    }
+
+    pub(super) fn is_fading(&self) -> bool {
+        self.fading
+    }
+
+    /// How much of the original volume to keep while we fade out
+    pub(super) fn fade_factor(&self, now: Instant) -> Option<f64> {
+        self.remaining(now)
+            .filter(|remaining| *remaining < FADE_DURATION)
+            .map(|remaining| remaining.as_secs_f64() / FADE_DURATION.as_secs_f64())
+    }
+
+    fn remaining(&self, now: Instant) -> Option<Duration> {
+        self.deadline.map(|d| d.saturating_duration_since(now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::advance;
+
+    const TAB: &str = "youtube-abcdef";
+    const OTHER_TAB: &str = "soundcloud-ghijkl";
+
+    fn event(label: &str, title: Option<&str>, playing: bool, ended: bool) -> PlaybackEvent {
+        PlaybackEvent {
+            label: label.to_string(),
+            playing,
+            ended,
+            audible: playing,
+            muted: false,
+            title: title.map(str::to_string),
+            artist: Some("Artist".to_string()),
+            artwork_url: None,
+            url: None,
+            duration: None,
+        }
+    }
+
+    fn tracks(count: u32) -> SleepTimerState {
+        let mut state = SleepTimerState::new(
+            SleepTimerMode::Tracks { count },
+            Some(TAB.into()),
+            Instant::now(),
+        );
+        state.on_playback(&event(TAB, Some("First"), true, false));
+        state
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn minutes_count_down() {
+        let mut state =
+            SleepTimerState::new(SleepTimerMode::Minutes { minutes: 2 }, None, Instant::now());
+        assert_eq!(state.remaining_secs, Some(120));
+        assert!(!state.is_fading());
+
+        advance(Duration::from_secs(60)).await;
+        state.update(Instant::now());
+        assert_eq!(state.remaining_secs, Some(60));
+        assert!(!state.is_expired(Instant::now()));
+
+        advance(Duration::from_secs(60)).await;
+        state.update(Instant::now());
+        assert_eq!(state.remaining_secs, Some(0));
+        assert!(state.is_expired(Instant::now()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fades_out_before_expiring() {
+        let mut state =
+            SleepTimerState::new(SleepTimerMode::Minutes { minutes: 1 }, None, Instant::now());
+        assert_eq!(state.fade_factor(Instant::now()), None);
+
+        advance(Duration::from_secs(45)).await;
+        state.update(Instant::now());
+        assert!(state.is_fading());
+        assert_eq!(state.fade_factor(Instant::now()), Some(0.5));
+
+        advance(FADE_DURATION).await;
+        assert_eq!(state.fade_factor(Instant::now()), Some(0.));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn extending_minutes_pushes_back_deadline() {
+        let mut state =
+            SleepTimerState::new(SleepTimerMode::Minutes { minutes: 1 }, None, Instant::now());
+        advance(Duration::from_secs(45)).await;
+        state.update(Instant::now());
+        assert!(state.is_fading());
+
+        state.extend(5, Instant::now());
+        assert_eq!(state.mode, SleepTimerMode::Minutes { minutes: 6 });
+        assert_eq!(state.remaining_secs, Some(315));
+        assert!(!state.is_fading());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn extending_saturates() {
+        let mut state =
+            SleepTimerState::new(SleepTimerMode::Minutes { minutes: 1 }, None, Instant::now());
+        state.extend(u32::MAX, Instant::now());
+        assert_eq!(state.mode, SleepTimerMode::Minutes { minutes: u32::MAX });
+
+        let mut state = tracks(2);
+        state.extend(u32::MAX, Instant::now());
+        assert_eq!(state.tracks_remaining, Some(u32::MAX));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn end_of_track_waits_on_track_remaining() {
+        let mut state =
+            SleepTimerState::new(SleepTimerMode::EndOfTrack, Some(TAB.into()), Instant::now());
+        assert!(state.needs_track_remaining());
+        assert_eq!(state.remaining_secs, None);
+        assert!(!state.is_expired(Instant::now()));
+
+        state.set_track_remaining(Duration::from_secs(90), Instant::now());
+        assert_eq!(state.remaining_secs, Some(90));
+
+        advance(Duration::from_secs(90)).await;
+        assert!(state.is_expired(Instant::now()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn counts_finished_and_skipped_tracks() {
+        let mut state = tracks(3);
+
+        state.on_playback(&event(TAB, Some("First"), false, true));
+        assert_eq!(state.tracks_remaining, Some(2));
+        state.on_playback(&event(TAB, Some("Second"), true, false));
+        assert_eq!(state.tracks_remaining, Some(2));
+
+        // skipping to the next track
+        state.on_playback(&event(TAB, Some("Third"), true, false));
+        assert_eq!(state.tracks_remaining, Some(1));
+        assert!(state.needs_track_remaining());
+
+        state.on_playback(&event(TAB, Some("Third"), false, true));
+        assert!(state.is_expired(Instant::now()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn pausing_and_late_metadata_are_the_same_track() {
+        let mut state = SleepTimerState::new(
+            SleepTimerMode::Tracks { count: 2 },
+            Some(TAB.into()),
+            Instant::now(),
+        );
+
+        state.on_playback(&event(TAB, None, true, false));
+        state.on_playback(&event(TAB, Some("First"), true, false));
+        state.on_playback(&event(TAB, Some("First"), false, false));
+        state.on_playback(&event(TAB, Some("First"), true, false));
+
+        assert_eq!(state.tracks_remaining, Some(2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ignores_other_tabs() {
+        let mut state = tracks(2);
+
+        state.on_playback(&event(OTHER_TAB, None, true, false));
+        state.on_playback(&event(OTHER_TAB, Some("Other"), true, false));
+        state.on_playback(&event(OTHER_TAB, Some("Other"), false, true));
+
+        assert_eq!(state.tracks_remaining, Some(2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn switching_tabs_follows_the_new_one() {
+        let mut state = tracks(2);
+
+        state.follow(OTHER_TAB);
+        state.on_playback(&event(OTHER_TAB, Some("Other"), true, false));
+        assert_eq!(state.tracks_remaining, Some(2));
+        assert_eq!(state.tab(), Some(OTHER_TAB));
+
+        state.on_playback(&event(TAB, Some("Second"), true, false));
+        state.on_playback(&event(OTHER_TAB, Some("Another"), true, false));
+        assert_eq!(state.tracks_remaining, Some(1));
+    }
+}
@@ -1,20 +1,27 @@
+use crate::controls::{ExitPictureInPicture, Pause, Play, SetDucked, SetFade, SetMuted};
+use crate::fullscreen;
 use crate::media_sources::MediaSource;
 use crate::osx_utils::enable_swipe_navigation;
+use crate::playback::PlaybackEvent;
+use crate::rpc;
+use crate::rpc::{PendingRpc, RpcError, RpcRequest};
+use crate::settings::{self, Settings};
 use crate::state::TabKey;
 use crate::utils::EnhancedWindow;
+use crate::window_metrics::{self, TabChrome};
 use crate::{EnhancedManager, EnhancedResult};
+use anyhow::anyhow;
 use log::{debug, error};
 use serde::{Deserialize, Serialize};
 use std::fmt::{Debug, Formatter};
 use std::time::{Duration, Instant};
 use std::{fmt, mem};
-use tauri::{LogicalPosition, LogicalSize, Manager, Runtime, Webview, WebviewBuilder, WebviewUrl};
+use tauri::{
+    LogicalPosition, LogicalSize, Manager, Runtime, Webview, WebviewBuilder, WebviewUrl, Window,
+};
 use tokio::time::sleep;
 use url::Url;
 
-pub const TAB_BAR_HEIGHT: f64 = 56.0;
-pub const MEDIA_SOURCE_BAR_WIDTH: f64 = 76.;
-
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase", bound = "")]
 pub struct TabState<R: Runtime> {
@@ -23,6 +30,32 @@ pub struct TabState<R: Runtime> {
     pub url: Url,
     pub(super) is_active: bool,
     pub(super) is_playing: bool,
+    /// Playing and actually making sound, a muted video is playing but not audible
+    #[serde(default)]
+    pub(super) audible: bool,
+    /// Our tab level mute, this is reapplied whenever the tab is loaded
+    #[serde(default)]
+    pub muted: bool,
+    /// Our volume is lowered because another tab is playing over us
+    #[serde(default)]
+    pub(super) ducked: bool,
+    /// Our cached copy of the page's artwork, or the page's own URL until we've cached it
+    #[serde(skip_deserializing)]
+    pub artwork: Option<String>,
+    /// The artwork URL the page last reported
+    #[serde(skip)]
+    page_artwork: Option<String>,
+    /// The track the page last reported, after normalization
+    #[serde(skip_deserializing)]
+    pub title: Option<String>,
+    #[serde(skip_deserializing)]
+    pub artist: Option<String>,
+    /// The page is fullscreen, so we give it our whole window
+    #[serde(skip_deserializing)]
+    pub(super) fullscreen: bool,
+    /// Floating in our picture-in-picture window, a detached tab is never active in our main window
+    #[serde(skip_deserializing)]
+    pub(super) detached: bool,
     pub display_name: String,
     #[serde(skip, default = "Instant::now")]
     pub last_interaction: Instant,
@@ -42,6 +75,15 @@ impl<R: Runtime> TabState<R> {
             url,
             is_active: false,
             is_playing: false,
+            audible: false,
+            muted: false,
+            ducked: false,
+            artwork: None,
+            page_artwork: None,
+            title: None,
+            artist: None,
+            fullscreen: false,
+            detached: false,
             display_name: source.name().to_string(),
             last_interaction: Instant::now(),
             webview: None,
@@ -50,6 +92,7 @@ impl<R: Runtime> TabState<R> {
 
     pub fn show(&mut self, app: &impl Manager<R>) -> anyhow::Result<()> {
         debug!("Showing Tab: {}", self.key);
+        self.attach(app)?;
         self.load_tab(app)?.show()?;
         self.relayout(app)?;
 
@@ -59,7 +102,10 @@ impl<R: Runtime> TabState<R> {
     }
 
     pub fn hide(&mut self) -> tauri::Result<()> {
-        if let Some(webview) = &self.webview {
+        // our picture-in-picture window keeps showing a detached tab
+        if let Some(webview) = &self.webview
+            && !self.detached
+        {
             debug!("Hiding Tab: {}", self.key);
             webview.hide()?;
         }
@@ -69,6 +115,50 @@ impl<R: Runtime> TabState<R> {
         Ok(())
     }
 
+    /// Float our webview in our picture-in-picture `window`, the page already shows only its video
+    pub fn detach(&mut self, window: &Window<R>) -> anyhow::Result<()> {
+        let webview = self
+            .webview
+            .as_ref()
+            .ok_or_else(|| anyhow!("{} isn't loaded", self.key))?;
+        webview.reparent(window)?;
+        webview.show()?;
+
+        self.is_active = false;
+        self.detached = true;
+        self.fit_detached()?;
+
+        debug!("Detached Tab: {}", self.key);
+
+        Ok(())
+    }
+
+    /// Bring our webview back to our main window, closing the picture-in-picture window it leaves empty
+    pub fn attach(&mut self, app: &impl Manager<R>) -> anyhow::Result<()> {
+        if !mem::take(&mut self.detached) {
+            return Ok(());
+        }
+
+        if let Some(webview) = &self.webview {
+            let pip_window = webview.window();
+            let window = app.main_window();
+            let settings = settings::get(app);
+            webview.reparent(&window)?;
+            webview.hide()?;
+            webview.set_size(self.size(window.available_size()?, &settings))?;
+            webview.set_position(self.position(window.title_bar_height(), &settings))?;
+            self.request(&ExitPictureInPicture)?;
+
+            if pip_window.label() != window.label() {
+                pip_window.destroy()?;
+            }
+        }
+
+        debug!("Attached Tab: {}", self.key);
+
+        Ok(())
+    }
+
     #[deprecated]
     pub fn play(&mut self, app: &impl Manager<R>) -> anyhow::Result<()> {
         self.load_tab(app)?;
@@ -108,9 +198,114 @@ impl<R: Runtime> TabState<R> {
         }
     }
 
+    pub fn is_playing(&self) -> bool {
+        self.is_playing
+    }
+
+    /// Swap the page's `page_artwork` for our `cached` copy, unless the page has moved on since
+    pub fn set_cached_artwork(&mut self, page_artwork: &str, cached: String) {
+        if self.page_artwork.as_deref() == Some(page_artwork) {
+            self.artwork = Some(cached);
+        }
+    }
+
+    /// Our memory monitor unloads tabs that have been inactive for a while
+    pub fn is_loaded(&self) -> bool {
+        self.webview.is_some()
+    }
+
+    pub fn update_playback(&mut self, event: &PlaybackEvent) -> Result<(), RpcError> {
+        self.is_playing = event.playing;
+        self.audible = event.audible;
+        self.title = event.title.clone();
+        self.artist = event.artist.clone();
+        if self.page_artwork != event.artwork_url {
+            // never show the last track's artwork while we cache this one
+            self.page_artwork = event.artwork_url.clone();
+            self.artwork = event.artwork_url.clone();
+        }
+
+        if event.muted != self.muted {
+            // the page navigated and ran our init script with a stale mute state
+            self.request(&SetMuted { muted: self.muted })?;
+        }
+
+        Ok(())
+    }
+
+    /// Ask the page to play, it reports its new state back through `playback-state`
+    pub fn request_play(&mut self) -> Result<(), RpcError> {
+        self.request(&Play)?;
+
+        debug!("{} Tab -> Play", self.key);
+
+        Ok(())
+    }
+
+    /// Ask the page to pause, it reports its new state back through `playback-state`
+    pub fn request_pause(&mut self) -> Result<(), RpcError> {
+        // we stop counting it as playing even if the page doesn't hear us, its next report corrects us
+        self.is_playing = false;
+        self.audible = false;
+        if self.webview.is_some() {
+            self.request(&Pause)?;
+        }
+
+        debug!("{} Tab -> Pause", self.key);
+
+        Ok(())
+    }
+
+    pub fn set_ducked(&mut self, duck_factor: Option<f64>) -> Result<(), RpcError> {
+        self.ducked = duck_factor.is_some();
+        if self.webview.is_some() {
+            self.request(&SetDucked {
+                factor: duck_factor.unwrap_or(1.),
+            })?;
+        }
+
+        debug!("{} Tab -> Ducked: {duck_factor:?}", self.key);
+
+        Ok(())
+    }
+
+    pub fn set_fade(&self, factor: f64) -> Result<(), RpcError> {
+        if self.webview.is_some() {
+            self.request(&SetFade { factor })?;
+        }
+
+        Ok(())
+    }
+
+    pub fn set_muted(&mut self, muted: bool) -> Result<Option<PendingRpc<()>>, RpcError> {
+        self.muted = muted;
+        if muted {
+            self.audible = false;
+        }
+
+        debug!("{} Tab -> Muted: {muted}", self.key);
+
+        if self.webview.is_some() {
+            self.request(&SetMuted { muted }).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn request<Req: RpcRequest>(
+        &self,
+        request: &Req,
+    ) -> Result<PendingRpc<Req::Response>, RpcError> {
+        match &self.webview {
+            Some(webview) => rpc::send(webview, request),
+            None => Err(RpcError::NotLoaded(self.key.clone())),
+        }
+    }
+
     pub fn try_unload_inactive(&mut self, max_age: Duration) -> tauri::Result<()> {
         if !self.is_active
             && !self.is_playing
+            && !self.detached
             && Instant::now().duration_since(self.last_interaction) >= max_age
         {
             self.unload()?;
@@ -123,9 +318,17 @@ impl<R: Runtime> TabState<R> {
         let was_active = self.is_active;
         self.hide()?;
         if let Some(webview) = mem::take(&mut self.webview) {
+            rpc::cancel_pending(webview.label());
             if let Ok(url) = webview.url() {
                 self.url = url;
             }
+            // nothing's left to own our fullscreen window
+            if self.fullscreen && !self.detached {
+                fullscreen::restore_window(&webview.window()).log_error();
+            }
+
+            // our picture-in-picture window has nothing left to show
+            let pip_window = mem::take(&mut self.detached).then(|| webview.window());
 
             webview.navigate(Url::parse("about:blank").unwrap())?;
             tauri::async_runtime::spawn(async move {
@@ -134,10 +337,19 @@ impl<R: Runtime> TabState<R> {
                 if let Err(e) = webview.close() {
                     error!("Couldn't close webview: {e:?}");
                 }
+                if let Some(window) = pip_window
+                    && let Err(e) = window.destroy()
+                {
+                    error!("Couldn't close our picture-in-picture window: {e:?}");
+                }
             });
         }
         self.is_active = false;
         self.is_playing = false;
+        self.audible = false;
+        self.fullscreen = false;
+        self.detached = false;
+        self.ducked = false;
 
         Ok(was_active)
     }
@@ -146,14 +358,15 @@ impl<R: Runtime> TabState<R> {
         if let Some(webview) = &self.webview {
             Ok(webview.clone())
         } else {
-            let init_script = self.source.init_script(&self.key);
+            let init_script = self.source.init_script(&self.key, self.muted);
 
             let window = app.main_window();
+            let settings = settings::get(app);
             let webview = window.add_child(
                 WebviewBuilder::new(&self.key, WebviewUrl::External(self.url.clone()))
                     .initialization_script(&init_script),
-                self.position(window.title_bar_height()),
-                self.size(window.available_size()?),
+                self.position(window.title_bar_height(), &settings),
+                self.size(window.available_size()?, &settings),
             )?;
 
             enable_swipe_navigation(&webview);
@@ -166,7 +379,11 @@ impl<R: Runtime> TabState<R> {
 
     fn relayout(&self, app: &impl Manager<R>) -> tauri::Result<()> {
         let window = app.main_window();
-        self.relayout_advanced(window.available_size()?, window.title_bar_height())?;
+        self.relayout_advanced(
+            window.available_size()?,
+            window.title_bar_height(),
+            &settings::get(app),
+        )?;
         Ok(())
     }
 
@@ -174,12 +391,28 @@ impl<R: Runtime> TabState<R> {
         &self,
         window_size: LogicalSize<f64>,
         title_bar_height: f64,
+        settings: &Settings,
     ) -> tauri::Result<()> {
+        if self.detached {
+            return self.fit_detached();
+        }
+
         if let Some(webview) = &self.webview
             && self.is_active
         {
-            webview.set_size(self.size(window_size))?;
-            webview.set_position(self.position(title_bar_height))?;
+            webview.set_size(self.size(window_size, settings))?;
+            webview.set_position(self.position(title_bar_height, settings))?;
+        }
+        Ok(())
+    }
+
+    /// A detached tab fills our picture-in-picture window
+    fn fit_detached(&self) -> tauri::Result<()> {
+        if let Some(webview) = &self.webview
+            && self.detached
+        {
+            webview.set_position(LogicalPosition::new(0., 0.))?;
+            webview.set_size(webview.window().inner_size()?)?;
         }
         Ok(())
     }
@@ -193,28 +426,29 @@ impl<R: Runtime> TabState<R> {
         }
     }
 
-    fn size(
-        &self,
-        LogicalSize {
-            width: window_width,
-            height: window_height,
-        }: LogicalSize<f64>,
-    ) -> LogicalSize<f64> {
-        if self.source.multi_instance() {
-            LogicalSize::new(
-                window_width - MEDIA_SOURCE_BAR_WIDTH,
-                window_height - TAB_BAR_HEIGHT,
-            )
-        } else {
-            LogicalSize::new(window_width - MEDIA_SOURCE_BAR_WIDTH, window_height)
-        }
+    fn size(&self, window_size: LogicalSize<f64>, settings: &Settings) -> LogicalSize<f64> {
+        window_metrics::tab_size(window_size, self.chrome(settings))
     }
 
-    fn position(&self, title_bar_height: f64) -> LogicalPosition<f64> {
-        if self.source.multi_instance() {
-            LogicalPosition::new(MEDIA_SOURCE_BAR_WIDTH, TAB_BAR_HEIGHT + title_bar_height)
+    fn position(&self, title_bar_height: f64, settings: &Settings) -> LogicalPosition<f64> {
+        window_metrics::tab_position(title_bar_height, self.chrome(settings))
+    }
+
+    /// The bars we leave room for, a fullscreen tab covers them all
+    fn chrome(&self, settings: &Settings) -> TabChrome {
+        if self.fullscreen {
+            TabChrome::default()
+        } else if settings.is_multi_instance(self.source) {
+            // multi instance sources have a tab bar above them
+            TabChrome {
+                source_bar_width: settings.source_bar_width,
+                tab_bar_height: settings.tab_bar_height,
+            }
         } else {
-            LogicalPosition::new(MEDIA_SOURCE_BAR_WIDTH, title_bar_height)
+            TabChrome {
+                source_bar_width: settings.source_bar_width,
+                tab_bar_height: 0.,
+            }
         }
     }
 }
@@ -227,6 +461,15 @@ impl<R: Runtime> Clone for TabState<R> {
             url: self.url.clone(),
             is_active: self.is_active,
             is_playing: self.is_playing,
+            audible: self.audible,
+            muted: self.muted,
+            ducked: self.ducked,
+            artwork: self.artwork.clone(),
+            page_artwork: self.page_artwork.clone(),
+            title: self.title.clone(),
+            artist: self.artist.clone(),
+            fullscreen: self.fullscreen,
+            detached: self.detached,
             display_name: self.display_name.clone(),
             last_interaction: self.last_interaction,
             webview: self.webview.clone(),
@@ -242,6 +485,14 @@ impl<R: Runtime> Debug for TabState<R> {
             .field("url", &self.url.to_string())
             .field("is_active", &self.is_active)
             .field("is_playing", &self.is_playing)
+            .field("audible", &self.audible)
+            .field("muted", &self.muted)
+            .field("ducked", &self.ducked)
+            .field("artwork", &self.artwork)
+            .field("title", &self.title)
+            .field("artist", &self.artist)
+            .field("fullscreen", &self.fullscreen)
+            .field("detached", &self.detached)
             .field("last_interaction", &self.last_interaction)
             .finish()
     }
@@ -252,3 +503,68 @@ impl<R: Runtime> Drop for TabState<R> {
         self.unload().log_error();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tauri::test::MockRuntime;
+
+    fn tab(source: MediaSource, fullscreen: bool) -> TabState<MockRuntime> {
+        let mut tab = TabState::new(source);
+        tab.fullscreen = fullscreen;
+        tab
+    }
+
+    #[test]
+    fn layout_leaves_room_for_our_bars() {
+        let mut settings = Settings::default();
+        settings.multi_instance.insert(MediaSource::YouTube, true);
+        settings
+            .multi_instance
+            .insert(MediaSource::SoundCloud, false);
+        let window_size = LogicalSize::new(1200., 800.);
+        let cases = [
+            (MediaSource::YouTube, false, 0., (76., 56.), (1124., 744.)),
+            (MediaSource::YouTube, false, 28., (76., 84.), (1124., 744.)),
+            (MediaSource::SoundCloud, false, 0., (76., 0.), (1124., 800.)),
+            (
+                MediaSource::SoundCloud,
+                false,
+                28.,
+                (76., 28.),
+                (1124., 800.),
+            ),
+            (MediaSource::YouTube, true, 0., (0., 0.), (1200., 800.)),
+            (MediaSource::SoundCloud, true, 28., (0., 28.), (1200., 800.)),
+        ];
+
+        for (source, fullscreen, title_bar_height, (x, y), (width, height)) in cases {
+            let tab = tab(source, fullscreen);
+            assert_eq!(
+                tab.position(title_bar_height, &settings),
+                LogicalPosition::new(x, y),
+                "{source:?} {fullscreen} {title_bar_height}"
+            );
+            assert_eq!(
+                tab.size(window_size, &settings),
+                LogicalSize::new(width, height),
+                "{source:?} {fullscreen} {title_bar_height}"
+            );
+        }
+    }
+
+    #[test]
+    fn layout_follows_our_bar_settings() {
+        let mut settings = Settings::default();
+        settings.multi_instance.insert(MediaSource::YouTube, true);
+        settings.source_bar_width = 100.;
+        settings.tab_bar_height = 40.;
+        let tab = tab(MediaSource::YouTube, false);
+
+        assert_eq!(tab.position(0., &settings), LogicalPosition::new(100., 40.));
+        assert_eq!(
+            tab.size(LogicalSize::new(1200., 800.), &settings),
+            LogicalSize::new(1100., 760.)
+        );
+    }
+}
@@ -0,0 +1,533 @@
+use crate::history::{self, History};
+use crate::media_sources::MediaSource;
+use anyhow::anyhow;
+use chrono::{Local, NaiveDate, TimeDelta};
+use rusqlite::{Connection, params};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+/// How many artists and tracks we rank
+const TOP_LIMIT: u32 = 10;
+
+/// The days we report on, relative ranges end today
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum StatsRange {
+    Week,
+    Month,
+    Year,
+    AllTime,
+    /// Both days are included
+    Custom {
+        from: NaiveDate,
+        to: NaiveDate,
+    },
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ListeningStats {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub listened_secs: u64,
+    pub by_source: Vec<SourceTotal>,
+    pub by_day: Vec<DayTotal>,
+    pub top_artists: Vec<ArtistTotal>,
+    pub top_tracks: Vec<TrackTotal>,
+    /// Consecutive days we've listened up to today, or yesterday if we haven't yet today
+    pub current_streak: u32,
+    /// The most consecutive days we listened within the range
+    pub longest_streak: u32,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceTotal {
+    pub source: MediaSource,
+    pub listened_secs: u64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DayTotal {
+    pub day: NaiveDate,
+    pub listened_secs: u64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtistTotal {
+    pub artist: String,
+    pub listened_secs: u64,
+    pub plays: u32,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackTotal {
+    pub title: String,
+    pub artist: Option<String>,
+    pub listened_secs: u64,
+    pub plays: u32,
+}
+
+pub fn get_stats(app: &AppHandle, range: StatsRange) -> anyhow::Result<ListeningStats> {
+    let history = app
+        .try_state::<History>()
+        .ok_or_else(|| anyhow!("Listening history isn't available"))?;
+    let today = Local::now().date_naive();
+    let (from, to) = range.days(today);
+
+    history.with_connection(|connection| compute(connection, from, to, today))
+}
+
+impl StatsRange {
+    fn days(self, today: NaiveDate) -> (NaiveDate, NaiveDate) {
+        let days_back = |days| today - TimeDelta::days(days - 1);
+        match self {
+            StatsRange::Week => (days_back(7), today),
+            StatsRange::Month => (days_back(30), today),
+            StatsRange::Year => (days_back(365), today),
+            StatsRange::AllTime => (NaiveDate::MIN, today),
+            StatsRange::Custom { from, to } => (from, to),
+        }
+    }
+}
+
+fn compute(
+    connection: &Connection,
+    from: NaiveDate,
+    to: NaiveDate,
+    today: NaiveDate,
+) -> anyhow::Result<ListeningStats> {
+    let by_source = connection
+        .prepare(
+            "SELECT source, sum(listened_ms) FROM daily_listening
+             WHERE day BETWEEN ?1 AND ?2
+             GROUP BY source
+             ORDER BY 2 DESC",
+        )?
+        .query_map(params![from, to], |row| {
+            Ok(SourceTotal {
+                source: history::source_column(row, 0)?,
+                listened_secs: row.get::<_, u64>(1)? / 1000,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let by_day = connection
+        .prepare(
+            "SELECT day, sum(listened_ms) FROM daily_listening
+             WHERE day BETWEEN ?1 AND ?2
+             GROUP BY day
+             HAVING sum(listened_ms) > 0
+             ORDER BY day",
+        )?
+        .query_map(params![from, to], |row| {
+            Ok(DayTotal {
+                day: row.get(0)?,
+                listened_secs: row.get::<_, u64>(1)? / 1000,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let top_artists = connection
+        .prepare(
+            "SELECT artist, sum(listened_ms), sum(plays) FROM daily_listening
+             WHERE day BETWEEN ?1 AND ?2 AND artist != ''
+             GROUP BY artist
+             ORDER BY 2 DESC
+             LIMIT ?3",
+        )?
+        .query_map(params![from, to, TOP_LIMIT], |row| {
+            Ok(ArtistTotal {
+                artist: row.get(0)?,
+                listened_secs: row.get::<_, u64>(1)? / 1000,
+                plays: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let top_tracks = connection
+        .prepare(
+            "SELECT title, artist, sum(listened_ms), sum(plays) FROM daily_listening
+             WHERE day BETWEEN ?1 AND ?2
+             GROUP BY title, artist
+             ORDER BY 3 DESC
+             LIMIT ?3",
+        )?
+        .query_map(params![from, to, TOP_LIMIT], |row| {
+            let artist: String = row.get(1)?;
+            Ok(TrackTotal {
+                title: row.get(0)?,
+                artist: Some(artist).filter(|a| !a.is_empty()),
+                listened_secs: row.get::<_, u64>(2)? / 1000,
+                plays: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let listened_days = connection
+        .prepare(
+            "SELECT day FROM daily_listening
+             GROUP BY day
+             HAVING sum(listened_ms) > 0
+             ORDER BY day DESC",
+        )?
+        .query_map([], |row| row.get::<_, NaiveDate>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(ListeningStats {
+        from,
+        to,
+        listened_secs: by_source.iter().map(|s| s.listened_secs).sum(),
+        current_streak: current_streak(&listened_days, today),
+        longest_streak: longest_streak(by_day.iter().map(|d| d.day)),
+        by_source,
+        by_day,
+        top_artists,
+        top_tracks,
+    })
+}
+
+/// `days` are newest first
+fn current_streak(days: &[NaiveDate], today: NaiveDate) -> u32 {
+    let Some(&latest) = days.first() else {
+        return 0;
+    };
+    // today isn't over yet, so it doesn't break the streak
+    if latest < today - TimeDelta::days(1) {
+        return 0;
+    }
+
+    let mut streak = 1;
+    for pair in days.windows(2) {
+        if pair[0] - pair[1] != TimeDelta::days(1) {
+            break;
+        }
+        streak += 1;
+    }
+
+    streak
+}
+
+/// `days` are oldest first
+fn longest_streak(days: impl Iterator<Item = NaiveDate>) -> u32 {
+    let mut longest = 0;
+    let mut streak = 0;
+    let mut previous: Option<NaiveDate> = None;
+    for day in days {
+        streak = match previous {
+            Some(previous) if day - previous == TimeDelta::days(1) => streak + 1,
+            _ => 1,
+        };
+        longest = longest.max(streak);
+        previous = Some(day);
+    }
+
+    longest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(day: &str) -> NaiveDate {
+        day.parse().unwrap()
+    }
+
+    fn open() -> Connection {
+        let mut connection = Connection::open_in_memory().unwrap();
+        history::migrate(&mut connection).unwrap();
+        connection
+    }
+
+    fn listen(
+        connection: &Connection,
+        on: &str,
+        source: MediaSource,
+        artist: &str,
+        title: &str,
+        secs: u64,
+        plays: u32,
+    ) {
+        connection
+            .execute(
+                "INSERT INTO daily_listening (day, source, artist, title, listened_ms, plays)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![on, source.source_id(), artist, title, secs * 1000, plays],
+            )
+            .unwrap();
+    }
+
+    /// Listening from the 1st to the 5th of March, with some either side
+    fn fixture() -> Connection {
+        let connection = open();
+        let listens = [
+            ("2024-02-28", MediaSource::YouTube, "A", "One", 600, 1),
+            ("2024-03-01", MediaSource::YouTube, "A", "One", 300, 1),
+            ("2024-03-01", MediaSource::SoundCloud, "B", "Two", 120, 1),
+            ("2024-03-02", MediaSource::YouTube, "A", "Three", 200, 2),
+            ("2024-03-02", MediaSource::AppleMusic, "", "Untitled", 60, 1),
+            ("2024-03-03", MediaSource::YouTube, "B", "Two", 100, 1),
+            // started but never heard
+            ("2024-03-04", MediaSource::YouTube, "A", "One", 0, 1),
+            ("2024-03-05", MediaSource::SoundCloud, "C", "Four", 50, 1),
+            ("2024-03-07", MediaSource::YouTube, "A", "One", 40, 1),
+        ];
+        for (on, source, artist, title, secs, plays) in listens {
+            listen(&connection, on, source, artist, title, secs, plays);
+        }
+
+        connection
+    }
+
+    #[test]
+    fn range_days() {
+        let today = day("2024-03-10");
+        let cases = [
+            (StatsRange::Week, "2024-03-04"),
+            (StatsRange::Month, "2024-02-10"),
+            (StatsRange::Year, "2023-03-12"),
+        ];
+
+        for (range, from) in cases {
+            assert_eq!(range.days(today), (day(from), today), "{range:?}");
+        }
+        assert_eq!(StatsRange::AllTime.days(today), (NaiveDate::MIN, today));
+        assert_eq!(
+            StatsRange::Custom {
+                from: day("2024-01-01"),
+                to: day("2024-01-31"),
+            }
+            .days(today),
+            (day("2024-01-01"), day("2024-01-31"))
+        );
+    }
+
+    #[test]
+    fn range_from_json() {
+        let week: StatsRange = serde_json::from_str(r#"{"type": "week"}"#).unwrap();
+        assert!(matches!(week, StatsRange::Week));
+
+        let all_time: StatsRange = serde_json::from_str(r#"{"type": "allTime"}"#).unwrap();
+        assert!(matches!(all_time, StatsRange::AllTime));
+
+        let custom: StatsRange =
+            serde_json::from_str(r#"{"type": "custom", "from": "2024-03-01", "to": "2024-03-05"}"#)
+                .unwrap();
+        assert!(matches!(
+            custom,
+            StatsRange::Custom { from, to } if from == day("2024-03-01") && to == day("2024-03-05")
+        ));
+    }
+
+    #[test]
+    fn totals_within_range() {
+        let stats = compute(
+            &fixture(),
+            day("2024-03-01"),
+            day("2024-03-05"),
+            day("2024-03-08"),
+        )
+        .unwrap();
+
+        assert_eq!(stats.listened_secs, 830);
+        assert_eq!(
+            stats
+                .by_source
+                .iter()
+                .map(|s| (s.source, s.listened_secs))
+                .collect::<Vec<_>>(),
+            [
+                (MediaSource::YouTube, 600),
+                (MediaSource::SoundCloud, 170),
+                (MediaSource::AppleMusic, 60),
+            ]
+        );
+        assert_eq!(
+            stats
+                .by_day
+                .iter()
+                .map(|d| (d.day, d.listened_secs))
+                .collect::<Vec<_>>(),
+            [
+                (day("2024-03-01"), 420),
+                (day("2024-03-02"), 260),
+                (day("2024-03-03"), 100),
+                (day("2024-03-05"), 50),
+            ]
+        );
+    }
+
+    #[test]
+    fn top_artists_and_tracks_within_range() {
+        let stats = compute(
+            &fixture(),
+            day("2024-03-01"),
+            day("2024-03-05"),
+            day("2024-03-08"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            stats
+                .top_artists
+                .iter()
+                .map(|a| (a.artist.as_str(), a.listened_secs, a.plays))
+                .collect::<Vec<_>>(),
+            [("A", 500, 4), ("B", 220, 2), ("C", 50, 1)]
+        );
+        assert_eq!(
+            stats
+                .top_tracks
+                .iter()
+                .map(|t| (
+                    t.title.as_str(),
+                    t.artist.as_deref(),
+                    t.listened_secs,
+                    t.plays
+                ))
+                .collect::<Vec<_>>(),
+            [
+                ("One", Some("A"), 300, 2),
+                ("Two", Some("B"), 220, 2),
+                ("Three", Some("A"), 200, 2),
+                ("Untitled", None, 60, 1),
+                ("Four", Some("C"), 50, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn ranges_include_both_days() {
+        let stats = compute(
+            &fixture(),
+            day("2024-03-02"),
+            day("2024-03-03"),
+            day("2024-03-08"),
+        )
+        .unwrap();
+
+        assert_eq!(stats.listened_secs, 360);
+        assert_eq!(stats.longest_streak, 2);
+    }
+
+    #[test]
+    fn streaks() {
+        let stats = compute(
+            &fixture(),
+            day("2024-03-01"),
+            day("2024-03-05"),
+            day("2024-03-08"),
+        )
+        .unwrap();
+
+        assert_eq!(stats.longest_streak, 3);
+        // the current streak doesn't care about our range, yesterday's listening counts
+        assert_eq!(stats.current_streak, 1);
+
+        let stats = compute(
+            &fixture(),
+            day("2024-03-01"),
+            day("2024-03-05"),
+            day("2024-03-09"),
+        )
+        .unwrap();
+        assert_eq!(stats.current_streak, 0);
+    }
+
+    #[test]
+    fn only_ranks_the_top() {
+        let connection = open();
+        for i in 0..TOP_LIMIT + 5 {
+            let name = format!("{i:02}");
+            listen(
+                &connection,
+                "2024-03-01",
+                MediaSource::YouTube,
+                &name,
+                &name,
+                u64::from(i + 1) * 10,
+                1,
+            );
+        }
+
+        let stats = compute(
+            &connection,
+            day("2024-03-01"),
+            day("2024-03-01"),
+            day("2024-03-01"),
+        )
+        .unwrap();
+
+        assert_eq!(stats.top_artists.len(), TOP_LIMIT as usize);
+        assert_eq!(stats.top_artists[0].artist, "14");
+        assert_eq!(stats.top_tracks.len(), TOP_LIMIT as usize);
+        assert_eq!(stats.top_tracks[0].title, "14");
+    }
+
+    #[test]
+    fn nothing_listened() {
+        let stats = compute(
+            &open(),
+            NaiveDate::MIN,
+            day("2024-03-01"),
+            day("2024-03-01"),
+        )
+        .unwrap();
+
+        assert_eq!(stats.listened_secs, 0);
+        assert!(stats.by_source.is_empty());
+        assert!(stats.by_day.is_empty());
+        assert!(stats.top_artists.is_empty());
+        assert!(stats.top_tracks.is_empty());
+        assert_eq!((stats.current_streak, stats.longest_streak), (0, 0));
+    }
+
+    #[test]
+    fn current_streak_cases() {
+        let today = day("2024-03-10");
+        let cases: [(&[&str], u32); 6] = [
+            (&[], 0),
+            (&["2024-03-10"], 1),
+            (&["2024-03-09"], 1),
+            (&["2024-03-08"], 0),
+            (&["2024-03-10", "2024-03-09", "2024-03-08", "2024-03-06"], 3),
+            (&["2024-03-09", "2024-03-08", "2024-03-07"], 3),
+        ];
+
+        for (days, expected) in cases {
+            let days = days.iter().map(|d| day(d)).collect::<Vec<_>>();
+            assert_eq!(current_streak(&days, today), expected, "{days:?}");
+        }
+    }
+
+    #[test]
+    fn longest_streak_cases() {
+        let cases: [(&[&str], u32); 5] = [
+            (&[], 0),
+            (&["2024-03-01"], 1),
+            (&["2024-03-01", "2024-03-03", "2024-03-05"], 1),
+            (
+                &[
+                    "2024-03-01",
+                    "2024-03-02",
+                    "2024-03-04",
+                    "2024-03-05",
+                    "2024-03-06",
+                ],
+                3,
+            ),
+            (&["2024-02-28", "2024-02-29", "2024-03-01"], 3),
+        ];
+
+        for (days, expected) in cases {
+            assert_eq!(
+                longest_streak(days.iter().map(|d| day(d))),
+                expected,
+                "{days:?}"
+            );
+        }
+    }
+}
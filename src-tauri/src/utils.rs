@@ -1,5 +1,5 @@
 use crate::MAIN_WEBVIEW;
-use crate::osx_utils::title_bar_height;
+use crate::window_metrics::{self, content_insets};
 use tauri::{LogicalSize, Manager, Runtime, Window};
 
 #[cfg(debug_assertions)]
@@ -19,8 +19,11 @@ impl<R: Runtime> EnhancedWindow<R> for Window<R> {
     }
 
     fn available_size(&self) -> tauri::Result<LogicalSize<f64>> {
-        let mut window_size = self.size()?;
-        window_size.height -= self.title_bar_height();
+        let mut window_size = window_metrics::available_size(
+            self.inner_size()?,
+            self.scale_factor()?,
+            content_insets(self),
+        );
 
         #[cfg(debug_assertions)]
         if let Some(webview) = self.get_webview(MAIN_WEBVIEW)
@@ -32,7 +35,8 @@ impl<R: Runtime> EnhancedWindow<R> for Window<R> {
         Ok(window_size)
     }
 
+    /// How far below the top of our window our tabs start
     fn title_bar_height(&self) -> f64 {
-        title_bar_height(self)
+        content_insets(self).top
     }
 }
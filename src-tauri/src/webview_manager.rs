@@ -1,7 +1,20 @@
+use crate::controls::Play;
+use crate::hooks::{self, HookEvent};
 use crate::media_sources::MediaSource;
-use crate::state::{EnhancerAppStateManagerEmitter, TabKey};
+use crate::playback::PlaybackPolicy;
+use crate::rpc;
+use crate::state::{
+    AppState, EnhancerAppStateManager, EnhancerAppStateManagerEmitter, SleepTimerMode, TabKey,
+};
+use anyhow::{anyhow, bail};
 use log::debug;
-use tauri::AppHandle;
+use std::time::Duration;
+use tauri::{AppHandle, Wry};
+use tokio::time;
+
+/// How often we try to start playback while a freshly opened tab loads its player
+const PLAY_ATTEMPTS: u32 = 20;
+const PLAY_RETRY_INTERVAL: Duration = Duration::from_secs(1);
 
 pub fn create_tab(
     source: MediaSource,
@@ -16,21 +29,114 @@ pub fn create_tab(
 
         Ok(tab_key)
     })
+    .inspect(|tab_key| hooks::fire(app, HookEvent::TabOpened, tab_key))
 }
 
 pub fn switch_to_source(app: &AppHandle, source: MediaSource) -> anyhow::Result<()> {
-    app.app_state_mut(|state| state.show_source(source, app))
-        .map(|_| ())
+    let active_source = |state: &AppState<Wry>| {
+        let key = state.active_tab_key()?;
+        state.tab(&key).map(|t| (key, t.source))
+    };
+    let previous = app.app_state(active_source);
+    app.app_state_mut(|state| state.show_source(source, app))?;
+
+    match app.app_state(active_source) {
+        Some((key, active)) if previous.is_none_or(|(_, previous)| previous != active) => {
+            hooks::fire(app, HookEvent::SourceSwitched, &key)
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Open `source`, or a new tab at `url`, and keep trying to play it until its player loads
+pub async fn play_source(
+    app: &AppHandle,
+    source: MediaSource,
+    url: Option<String>,
+) -> anyhow::Result<()> {
+    let key = match url {
+        Some(url) => create_tab(source, Some(url), app)?,
+        None => {
+            switch_to_source(app, source)?;
+            app.app_state(|state| state.active_tab_key())
+                .ok_or_else(|| anyhow!("{source:?} didn't open a tab"))?
+        }
+    };
+
+    for attempt in 1..=PLAY_ATTEMPTS {
+        match rpc::call(app, &key, Play).await {
+            Ok(()) => return Ok(()),
+            Err(e) => debug!("Couldn't play {key} yet ({attempt}/{PLAY_ATTEMPTS}): {e}"),
+        }
+        time::sleep(PLAY_RETRY_INTERVAL).await;
+    }
+
+    bail!("Gave up trying to play {key}")
 }
 
 pub fn switch_to_tab(app: &AppHandle, key: &str) -> anyhow::Result<()> {
     app.app_state_mut(|state| state.show_tab(key, app))
 }
 
+/// Hide our tabs so our own landing page shows, they keep playing in the background
+pub fn show_home(app: &AppHandle) -> anyhow::Result<()> {
+    app.app_state_mut(|state| state.hide_sources())
+}
+
 pub fn close_tab(app: &AppHandle, key: &str) -> anyhow::Result<()> {
     app.app_state_mut(|state| state.close_tab(key, app))
 }
 
+/// Toggle the tab mute and wait for the page to apply it, returns whether the tab is now muted
+pub async fn toggle_tab_mute(app: &AppHandle, key: &str) -> anyhow::Result<bool> {
+    let (muted, pending) = app.app_state_mut(|state| {
+        let tab = state
+            .tab_mut(key)
+            .ok_or_else(|| anyhow!("No tab found for {key}"))?;
+        let muted = !tab.muted;
+
+        Ok((muted, tab.set_muted(muted)?))
+    })?;
+
+    if let Some(pending) = pending {
+        pending.reply().await?;
+    }
+
+    Ok(muted)
+}
+
+pub fn set_restore_muted(app: &AppHandle, restore_muted: bool) -> anyhow::Result<()> {
+    app.app_state_mut(|state| {
+        state.set_restore_muted(restore_muted);
+        Ok(())
+    })
+}
+
+pub fn set_playback_policy(app: &AppHandle, policy: PlaybackPolicy) -> anyhow::Result<()> {
+    app.app_state_mut(|state| state.set_playback_policy(policy))
+}
+
+pub fn set_auto_resume(app: &AppHandle, source: MediaSource, enabled: bool) -> anyhow::Result<()> {
+    app.app_state_mut(|state| {
+        state.set_auto_resume(source, enabled);
+        Ok(())
+    })
+}
+
+pub fn start_sleep_timer(app: &AppHandle, mode: SleepTimerMode) -> anyhow::Result<()> {
+    app.app_state_mut(|state| state.start_sleep_timer(mode))
+}
+
+pub fn extend_sleep_timer(app: &AppHandle, amount: u32) -> anyhow::Result<()> {
+    app.app_state_mut(|state| state.extend_sleep_timer(amount))
+}
+
+pub fn cancel_sleep_timer(app: &AppHandle) -> anyhow::Result<()> {
+    app.app_state_mut(|state| state.cancel_sleep_timer())
+}
+
 pub fn relayout(app: &AppHandle) -> anyhow::Result<()> {
     app.app_state_mut(|state| state.relayout(app))
 }
@@ -0,0 +1,279 @@
+use tauri::{LogicalPosition, LogicalSize, PhysicalSize, Runtime, Window};
+
+/// How much of our main window's inner size our tabs can't use, in logical points
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ContentInsets {
+    /// Drawn over the top of our content, so our tabs are offset below it
+    pub top: f64,
+    /// Decorations our inner size includes that our tabs are already laid out below
+    pub decorations: f64,
+}
+
+/// The bars our frontend draws beside and above a tab, in logical points
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TabChrome {
+    pub source_bar_width: f64,
+    pub tab_bar_height: f64,
+}
+
+/// What's left of `inner_size` for our content, in logical points
+pub fn available_size(
+    inner_size: PhysicalSize<u32>,
+    scale_factor: f64,
+    insets: ContentInsets,
+) -> LogicalSize<f64> {
+    let inner = inner_size.to_logical::<f64>(scale_factor);
+
+    LogicalSize::new(
+        inner.width,
+        (inner.height - insets.top - insets.decorations).max(0.),
+    )
+}
+
+/// A tab fills what's left of `available` beside and below our bars
+pub fn tab_size(available: LogicalSize<f64>, chrome: TabChrome) -> LogicalSize<f64> {
+    LogicalSize::new(
+        (available.width - chrome.source_bar_width).max(0.),
+        (available.height - chrome.tab_bar_height).max(0.),
+    )
+}
+
+pub fn tab_position(title_bar_height: f64, chrome: TabChrome) -> LogicalPosition<f64> {
+    LogicalPosition::new(
+        chrome.source_bar_width,
+        chrome.tab_bar_height + title_bar_height,
+    )
+}
+
+/// macOS draws its title bar over our full size content view
+#[cfg(target_os = "macos")]
+pub fn content_insets<R: Runtime>(window: &Window<R>) -> ContentInsets {
+    use objc2::msg_send;
+    use objc2::runtime::AnyObject;
+    use objc2_foundation::NSRect;
+
+    let top = unsafe {
+        let ns_window = window.ns_window().unwrap() as *mut AnyObject;
+        let frame: NSRect = msg_send![ns_window, frame];
+        let content_rect: NSRect = msg_send![ns_window, contentLayoutRect];
+
+        frame.size.height - content_rect.size.height
+    };
+
+    ContentInsets {
+        top,
+        decorations: 0.,
+    }
+}
+
+/// GTK lays our children out below its client side header bar, which X11 and Wayland may both report within our
+/// inner size, so we compare what GTK gave our content with what we were told
+#[cfg(target_os = "linux")]
+pub fn content_insets<R: Runtime>(window: &Window<R>) -> ContentInsets {
+    use gtk::prelude::*;
+
+    if !window.is_decorated().unwrap_or(true) || window.is_fullscreen().unwrap_or(false) {
+        return ContentInsets::default();
+    }
+
+    let decorations = (|| {
+        let gtk_window = window.gtk_window().ok()?;
+        let header_bar = gtk_window
+            .titlebar()
+            .filter(|title_bar| title_bar.is_visible())
+            .map_or(0, |title_bar| title_bar.allocated_height());
+        // GTK allocates in logical points already
+        let inner_height = window
+            .inner_size()
+            .ok()?
+            .to_logical::<f64>(window.scale_factor().ok()?)
+            .height;
+
+        Some(gtk_decorations(
+            inner_height,
+            f64::from(gtk_window.allocated_height()),
+            f64::from(header_bar),
+        ))
+    })();
+
+    ContentInsets {
+        top: 0.,
+        decorations: decorations.unwrap_or(0.),
+    }
+}
+
+/// Windows reports an inner size that already excludes its title bar
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+pub fn content_insets<R: Runtime>(_window: &Window<R>) -> ContentInsets {
+    ContentInsets::default()
+}
+
+/// How much of `header_bar` our inner size includes, our content gets what's left of the window's allocation below it
+#[cfg_attr(not(any(target_os = "linux", test)), allow(dead_code))]
+fn gtk_decorations(inner_height: f64, window_allocation: f64, header_bar: f64) -> f64 {
+    let content = window_allocation - header_bar;
+
+    (inner_height - content).clamp(0., header_bar.max(0.))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIDEBAR: TabChrome = TabChrome {
+        source_bar_width: 76.,
+        tab_bar_height: 0.,
+    };
+    const SIDEBAR_AND_TABS: TabChrome = TabChrome {
+        source_bar_width: 76.,
+        tab_bar_height: 56.,
+    };
+
+    #[test]
+    fn available_size_cases() {
+        let cases = [
+            // no decorations
+            ((1200, 800), 1., ContentInsets::default(), (1200., 800.)),
+            ((2400, 1600), 2., ContentInsets::default(), (1200., 800.)),
+            ((1500, 1000), 1.25, ContentInsets::default(), (1200., 800.)),
+            // macOS' title bar over our content
+            (
+                (2400, 1600),
+                2.,
+                ContentInsets {
+                    top: 28.,
+                    decorations: 0.,
+                },
+                (1200., 772.),
+            ),
+            // a GTK header bar within our inner size
+            (
+                (1200, 846),
+                1.,
+                ContentInsets {
+                    top: 0.,
+                    decorations: 46.,
+                },
+                (1200., 800.),
+            ),
+            (
+                (2400, 1692),
+                2.,
+                ContentInsets {
+                    top: 0.,
+                    decorations: 46.,
+                },
+                (1200., 800.),
+            ),
+            (
+                (1500, 1057),
+                1.25,
+                ContentInsets {
+                    top: 0.,
+                    decorations: 45.6,
+                },
+                (1200., 800.),
+            ),
+            // smaller than its decorations
+            (
+                (200, 20),
+                1.,
+                ContentInsets {
+                    top: 0.,
+                    decorations: 46.,
+                },
+                (200., 0.),
+            ),
+        ];
+
+        for ((width, height), scale, insets, (expected_width, expected_height)) in cases {
+            let size = available_size(PhysicalSize::new(width, height), scale, insets);
+            assert!(
+                (size.width - expected_width).abs() < 1e-9
+                    && (size.height - expected_height).abs() < 1e-9,
+                "{width}x{height} @{scale} {insets:?}: {size:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn tab_layout_cases() {
+        let available = LogicalSize::new(1200., 800.);
+        let cases = [
+            (TabChrome::default(), 0., (0., 0.), (1200., 800.)),
+            (TabChrome::default(), 28., (0., 28.), (1200., 800.)),
+            (SIDEBAR, 0., (76., 0.), (1124., 800.)),
+            (SIDEBAR, 28., (76., 28.), (1124., 800.)),
+            (SIDEBAR_AND_TABS, 0., (76., 56.), (1124., 744.)),
+            (SIDEBAR_AND_TABS, 28., (76., 84.), (1124., 744.)),
+        ];
+
+        for (chrome, title_bar_height, (x, y), (width, height)) in cases {
+            assert_eq!(
+                tab_position(title_bar_height, chrome),
+                LogicalPosition::new(x, y),
+                "{chrome:?} {title_bar_height}"
+            );
+            assert_eq!(
+                tab_size(available, chrome),
+                LogicalSize::new(width, height),
+                "{chrome:?} {title_bar_height}"
+            );
+        }
+    }
+
+    #[test]
+    fn tabs_fill_available_size_at_any_scale() {
+        for scale in [1., 1.25, 1.5, 2.] {
+            let insets = ContentInsets {
+                top: 0.,
+                decorations: 46.,
+            };
+            let inner = LogicalSize::new(1200., 846.).to_physical::<u32>(scale);
+            let available = available_size(inner, scale, insets);
+
+            let position = tab_position(0., SIDEBAR_AND_TABS);
+            let size = tab_size(available, SIDEBAR_AND_TABS);
+
+            assert!(
+                (position.x + size.width - 1200.).abs() < 1.,
+                "{scale}: {position:?} {size:?}"
+            );
+            assert!(
+                (position.y + size.height - 800.).abs() < 1.,
+                "{scale}: {position:?} {size:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn tabs_never_get_negative_sizes() {
+        assert_eq!(
+            tab_size(LogicalSize::new(50., 30.), SIDEBAR_AND_TABS),
+            LogicalSize::new(0., 0.)
+        );
+    }
+
+    #[test]
+    fn gtk_decorations_cases() {
+        let cases = [
+            // Wayland reports the header bar within our inner size
+            (846., 846., 46., 46.),
+            // X11 reports our inner size below it
+            (800., 846., 46., 0.),
+            // server side decorations, or none at all
+            (800., 800., 0., 0.),
+            // a resize GTK hasn't allocated yet shouldn't look like more decorations
+            (900., 846., 46., 46.),
+            (700., 846., 46., 0.),
+        ];
+
+        for (inner_height, window_allocation, header_bar, expected) in cases {
+            assert_eq!(
+                gtk_decorations(inner_height, window_allocation, header_bar),
+                expected,
+                "{inner_height} {window_allocation} {header_bar}"
+            );
+        }
+    }
+}
@@ -1,26 +1,58 @@
 import {useEffect, useState} from "react";
 import "./App.css";
-import {getSources, MediaDefinition, MediaSource} from "./commands.ts";
-import {MediaSourcesContext} from "./utils.tsx";
+import {getAllSources, getSettings, MediaDefinition, MediaSource, Settings} from "./commands.ts";
+import {AllMediaSourcesContext, MediaSourcesContext} from "./utils.tsx";
 import {MediaSourceController} from "./components/MediaSource.tsx";
+import {listen, UnlistenFn} from "@tauri-apps/api/event";
+
+// Our native tabs are laid out around these, so they come from our settings
+function applyLayout(settings: Settings) {
+    const style = document.documentElement.style;
+    style.setProperty("--tab-bar-height", `${settings.tabBarHeight}px`);
+    style.setProperty("--source-bar-width", `${settings.sourceBarWidth}px`);
+}
 
 export default function App() {
     const [sources, setSources] = useState<Map<MediaSource, MediaDefinition>>(new Map());
+    const [allSources, setAllSources] = useState<Map<MediaSource, MediaDefinition>>(new Map());
 
-    useEffect(() => {
-        getSources().then((sources) => {
-            // Our map should preserve insertion order
-            const sourcesMap = new Map();
-            for (const source of sources) {
+    const refreshSources = async () => {
+        const sources = await getAllSources();
+        // Our maps should preserve insertion order
+        const sourcesMap = new Map();
+        const allSourcesMap = new Map();
+        for (const source of sources) {
+            allSourcesMap.set(source.id, source);
+            if (!source.hidden) {
                 sourcesMap.set(source.id, source);
             }
-            setSources(sourcesMap);
-        });
+        }
+        setSources(sourcesMap);
+        setAllSources(allSourcesMap);
+    };
+
+    useEffect(() => {
+        let unlistenFn: UnlistenFn | null = null;
+        (async () => {
+            unlistenFn = await listen<Settings>('SETTINGS_CHANGED_EVENT', async (event) => {
+                applyLayout(event.payload);
+                // our sources are ordered, hidden and renamed by our settings
+                await refreshSources();
+            });
+            applyLayout(await getSettings());
+            await refreshSources();
+        })();
+
+        return () => {
+            unlistenFn && unlistenFn();
+        };
     }, []);
 
     return (
-        <MediaSourcesContext value={sources}>
-            <MediaSourceController/>
-        </MediaSourcesContext>
+        <AllMediaSourcesContext value={allSources}>
+            <MediaSourcesContext value={sources}>
+                <MediaSourceController/>
+            </MediaSourcesContext>
+        </AllMediaSourcesContext>
     );
 }
\ No newline at end of file
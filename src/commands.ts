@@ -8,6 +8,7 @@ export interface MediaDefinition {
     iconUrl: string;
     defaultUrl: string;
     multiInstance: boolean;
+    hidden: boolean;
 }
 
 export type TabKey = string;
@@ -20,11 +21,181 @@ export interface TabState {
     url: string;
     isActive: boolean;
     isPlaying: boolean;
+    audible: boolean;
+    muted: boolean;
+    ducked: boolean;
+    fullscreen: boolean;
+    detached: boolean;
+    artwork: string | null;
+    title: string | null;
+    artist: string | null;
     displayName: string;
 }
 
+export interface Position {
+    currentTime: number;
+    duration: number | null;
+    playbackRate: number;
+}
+
+export interface TrackMetadata {
+    title: string | null;
+    artist: string | null;
+    artworkUrl: string | null;
+}
+
+export type PlaybackPolicy =
+    | { mode: "exclusive" }
+    | { mode: "allowConcurrent" }
+    | { mode: "duckOthers", percent: number };
+
+export type SleepTimerMode =
+    | { mode: "minutes", minutes: number }
+    | { mode: "endOfTrack" }
+    | { mode: "tracks", count: number };
+
+export interface SleepTimerState {
+    mode: SleepTimerMode;
+    remainingSecs: number | null;
+    tracksRemaining: number | null;
+    fading: boolean;
+}
+
+export type BreakAction =
+    | { type: "pause" }
+    | { type: "switchTo", source: MediaSource };
+
+export interface FocusConfig {
+    workMinutes: number;
+    breakMinutes: number;
+    breakAction?: BreakAction;
+    blockDistracting?: boolean;
+    blockedSources?: MediaSource[];
+}
+
+export interface FocusSessionState {
+    config: FocusConfig;
+    phase: "work" | "break";
+    completedWorkBlocks: number;
+    remainingSecs: number;
+    started: string;
+}
+
+export interface FocusSessionRecord {
+    started: string;
+    ended: string;
+    workBlocks: number;
+    workMinutes: number;
+}
+
+export interface HistoryEntry {
+    id: number;
+    source: MediaSource;
+    url: string | null;
+    title: string;
+    artist: string | null;
+    startedAt: string;
+    listenedSecs: number;
+}
+
+export interface HistoryQuery {
+    from?: string;
+    to?: string;
+    source?: MediaSource;
+    text?: string;
+    limit?: number;
+    offset?: number;
+}
+
+export type ExportFormat = "csv" | "json";
+
+export type StatsRange =
+    | { type: "week" }
+    | { type: "month" }
+    | { type: "year" }
+    | { type: "allTime" }
+    | { type: "custom", from: string, to: string };
+
+export interface ListeningStats {
+    from: string;
+    to: string;
+    listenedSecs: number;
+    bySource: { source: MediaSource, listenedSecs: number }[];
+    byDay: { day: string, listenedSecs: number }[];
+    topArtists: { artist: string, listenedSecs: number, plays: number }[];
+    topTracks: { title: string, artist: string | null, listenedSecs: number, plays: number }[];
+    currentStreak: number;
+    longestStreak: number;
+}
+
+export type ScrobbleBackend = "lastFm" | "listenBrainz";
+
+export interface ScrobblerConfig {
+    sources: MediaSource[];
+    lastFm: { apiKey: string, apiSecret: string, username: string, sessionKey: string, apiUrl: string } | null;
+    listenBrainz: { token: string, username: string, apiUrl: string } | null;
+}
+
+export interface MetadataRule {
+    source: MediaSource | null;
+    artist: string | null;
+    titleContains: string | null;
+    setArtist: string | null;
+    setTitle: string | null;
+    keepTitle?: boolean;
+}
+
+export interface DiscordConfig {
+    enabled: boolean;
+    clientId: string | null;
+    hiddenSources: MediaSource[];
+}
+
+export type LogLevel = "off" | "error" | "warn" | "info" | "debug" | "trace";
+
+export interface Settings {
+    unloadTimeoutMins: number | null;
+    memoryCheckIntervalSecs: number;
+    tabBarHeight: number;
+    sourceBarWidth: number;
+    logLevel: LogLevel;
+    sourceOrder: MediaSource[];
+    hiddenSources: MediaSource[];
+    sourceNames: Partial<Record<MediaSource, string>>;
+    multiInstance: Partial<Record<MediaSource, boolean>>;
+}
+
+export interface MqttConfig {
+    enabled: boolean;
+    host: string;
+    port: number;
+    username: string | null;
+    password: string | null;
+    tls: boolean;
+    caFile: string | null;
+    topicPrefix: string;
+    discoveryPrefix: string | null;
+}
+
+export interface NowPlayingExportConfig {
+    enabled: boolean;
+    directory: string | null;
+    templates: { fileName: string, template: string }[];
+    json: boolean;
+    artwork: boolean;
+}
+
 export interface AppState {
     media: Record<MediaSource, MediaState>,
+    restoreMuted: boolean,
+    playbackPolicy: PlaybackPolicy,
+    interruptions: { interruptions: { interrupted: TabKey, by: TabKey }[] },
+    noAutoResume: MediaSource[],
+    miniPlayer: boolean,
+    miniPlayerTab: TabKey | null,
+    sleepTimer: SleepTimerState | null,
+    focusSession: FocusSessionState | null,
+    focusHistory: FocusSessionRecord[],
 }
 
 export type MediaState = SingleMediaState | MultiMediaState;
@@ -61,6 +232,257 @@ export async function closeTab(key: TabKey) {
     await invoke("close_tab", {key});
 }
 
+export async function playTab(key: TabKey) {
+    await invoke("play_tab", {key});
+}
+
+export async function pauseTab(key: TabKey) {
+    await invoke("pause_tab", {key});
+}
+
+export async function nextTrack(key: TabKey) {
+    await invoke("next_track", {key});
+}
+
+export async function previousTrack(key: TabKey) {
+    await invoke("previous_track", {key});
+}
+
+export async function getPosition(key: TabKey): Promise<Position> {
+    return await invoke("get_position", {key});
+}
+
+export async function seek(key: TabKey, seconds: number) {
+    await invoke("seek", {key, seconds});
+}
+
+export async function seekRelative(key: TabKey, seconds: number) {
+    await invoke("seek_relative", {key, seconds});
+}
+
+export async function setVolume(key: TabKey, volume: number) {
+    await invoke("set_volume", {key, volume});
+}
+
+export async function setMuted(key: TabKey, muted: boolean) {
+    await invoke("set_muted", {key, muted});
+}
+
+export async function setRate(key: TabKey, rate: number) {
+    await invoke("set_rate", {key, rate});
+}
+
+export async function getTabMetadata(key: TabKey): Promise<TrackMetadata> {
+    return await invoke("get_tab_metadata", {key});
+}
+
+export async function toggleTabMute(key: TabKey): Promise<boolean> {
+    console.debug("[medyia] toggling tab mute:", key);
+    return await invoke("toggle_tab_mute", {key});
+}
+
+export async function setRestoreMuted(restoreMuted: boolean) {
+    await invoke("set_restore_muted", {restoreMuted});
+}
+
+export async function setPlaybackPolicy(policy: PlaybackPolicy) {
+    console.debug("[medyia] setting playback policy:", policy);
+    await invoke("set_playback_policy", {policy});
+}
+
+export async function setAutoResume(source: MediaSource, enabled: boolean) {
+    await invoke("set_auto_resume", {source, enabled});
+}
+
+export async function startSleepTimer(mode: SleepTimerMode) {
+    console.debug("[medyia] starting sleep timer:", mode);
+    await invoke("start_sleep_timer", {mode});
+}
+
+export async function extendSleepTimer(amount: number) {
+    await invoke("extend_sleep_timer", {amount});
+}
+
+export async function cancelSleepTimer() {
+    await invoke("cancel_sleep_timer");
+}
+
+export type ScheduledAction =
+    | { type: "play", source: MediaSource, url: string | null }
+    | { type: "pauseAll" };
+
+export interface ScheduleDefinition {
+    name: string;
+    cron: string;
+    timeZone: string | null;
+    action: ScheduledAction;
+}
+
+export interface Schedule extends ScheduleDefinition {
+    id: string;
+    enabled: boolean;
+    created: string;
+    lastRun: string | null;
+}
+
+export async function getSchedules(): Promise<Schedule[]> {
+    return await invoke("get_schedules");
+}
+
+export async function addSchedule(definition: ScheduleDefinition): Promise<Schedule> {
+    console.debug("[medyia] adding schedule:", definition);
+    return await invoke("add_schedule", {definition});
+}
+
+export async function removeSchedule(id: string) {
+    await invoke("remove_schedule", {id});
+}
+
+export async function setScheduleEnabled(id: string, enabled: boolean) {
+    await invoke("set_schedule_enabled", {id, enabled});
+}
+
+export type HookEvent = "track-changed" | "play" | "pause" | "tab-opened" | "source-switched";
+
+export interface HookDefinition {
+    name: string;
+    event: HookEvent;
+    command: string;
+    timeoutSecs: number | null;
+}
+
+export interface Hook extends HookDefinition {
+    id: string;
+    enabled: boolean;
+}
+
+export async function getHooks(): Promise<Hook[]> {
+    return await invoke("get_hooks");
+}
+
+export async function addHook(definition: HookDefinition): Promise<Hook> {
+    console.debug("[medyia] adding hook:", definition);
+    return await invoke("add_hook", {definition});
+}
+
+export async function removeHook(id: string) {
+    await invoke("remove_hook", {id});
+}
+
+export async function setHookEnabled(id: string, enabled: boolean) {
+    await invoke("set_hook_enabled", {id, enabled});
+}
+
+export async function startFocusSession(config: FocusConfig) {
+    console.debug("[medyia] starting focus session:", config);
+    await invoke("start_focus_session", {config});
+}
+
+export async function stopFocusSession() {
+    await invoke("stop_focus_session");
+}
+
+export async function queryHistory(query: HistoryQuery): Promise<HistoryEntry[]> {
+    return await invoke("query_history", {query});
+}
+
+export async function exportHistory(query: HistoryQuery, format: ExportFormat, path: string) {
+    console.debug("[medyia] exporting history:", format, path);
+    await invoke("export_history", {query, format, path});
+}
+
+export async function getHistoryRetention(): Promise<number | null> {
+    return await invoke("get_history_retention");
+}
+
+export async function setHistoryRetention(days: number | null) {
+    await invoke("set_history_retention", {days});
+}
+
+export async function getStats(range: StatsRange): Promise<ListeningStats> {
+    return await invoke("get_stats", {range});
+}
+
+export async function getScrobblerConfig(): Promise<ScrobblerConfig> {
+    return await invoke("get_scrobbler_config");
+}
+
+export async function setScrobbleSources(sources: MediaSource[]) {
+    await invoke("set_scrobble_sources", {sources});
+}
+
+export async function connectLastFm(apiKey: string, apiSecret: string, username: string, password: string) {
+    console.debug("[medyia] connecting to Last.fm as", username);
+    await invoke("connect_last_fm", {apiKey, apiSecret, username, password});
+}
+
+export async function connectListenBrainz(token: string, apiUrl?: string) {
+    console.debug("[medyia] connecting to ListenBrainz");
+    await invoke("connect_listen_brainz", {token, apiUrl});
+}
+
+export async function disconnectScrobbler(backend: ScrobbleBackend) {
+    await invoke("disconnect_scrobbler", {backend});
+}
+
+export async function getMetadataRules(): Promise<MetadataRule[]> {
+    return await invoke("get_metadata_rules");
+}
+
+export async function setMetadataRules(rules: MetadataRule[]) {
+    await invoke("set_metadata_rules", {rules});
+}
+
+export async function getDiscordConfig(): Promise<DiscordConfig> {
+    return await invoke("get_discord_config");
+}
+
+export async function setDiscordConfig(config: DiscordConfig) {
+    await invoke("set_discord_config", {config});
+}
+
+export async function getMqttConfig(): Promise<MqttConfig> {
+    return await invoke("get_mqtt_config");
+}
+
+export async function setMqttConfig(config: MqttConfig) {
+    await invoke("set_mqtt_config", {config});
+}
+
+export async function getSettings(): Promise<Settings> {
+    return await invoke("get_settings");
+}
+
+// Tabs of sources we're newly hiding are closed with closeHiddenTabs, otherwise they keep running
+export async function updateSettings(settings: Settings, closeHiddenTabs: boolean = false) {
+    console.debug("[medyia] updating settings:", settings);
+    await invoke("update_settings", {settings, closeHiddenTabs});
+}
+
+// Our main window hides while our mini-player is open, its tabs keep playing
+export async function setMiniPlayer(enabled: boolean) {
+    console.debug("[medyia] setting mini player:", enabled);
+    await invoke("set_mini_player", {enabled});
+}
+
+// The tab floats in its own window showing only its video, switching back to it returns it
+export async function enterPictureInPicture(key: TabKey) {
+    console.debug("[medyia] entering picture in picture:", key);
+    await invoke("enter_picture_in_picture", {key});
+}
+
+export async function exitPictureInPicture() {
+    await invoke("exit_picture_in_picture");
+}
+
+export async function getNowPlayingExport(): Promise<NowPlayingExportConfig> {
+    return await invoke("get_now_playing_export");
+}
+
+export async function setNowPlayingExport(config: NowPlayingExportConfig) {
+    await invoke("set_now_playing_export", {config});
+}
+
 export async function emitBackendState() {
     console.debug("[medyia] getting backend state");
     await invoke("emit_backend_state");
@@ -70,3 +492,13 @@ export async function getSources(): Promise<MediaDefinition[]> {
     return await invoke('get_sources')
 }
 
+// Hidden sources too, in our sidebar order
+export async function getAllSources(): Promise<MediaDefinition[]> {
+    return await invoke('get_all_sources')
+}
+
+// Hide our tabs so our landing page shows, they keep playing in the background
+export async function showHome() {
+    await invoke('show_home');
+}
+
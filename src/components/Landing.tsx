@@ -1,13 +1,15 @@
 import {MediaSource} from "../commands.ts";
 import {useContext} from "react";
 import {MediaSourcesContext} from "../utils.tsx";
+import {SourceSettings} from "./SourceSettings.tsx";
 
 
 interface LandingProps {
     onOpenService: (id: MediaSource) => void;
+    openTabCount: (id: MediaSource) => number;
 }
 
-export function Landing({onOpenService}: LandingProps) {
+export function Landing({onOpenService, openTabCount}: LandingProps) {
     let sources = useContext(MediaSourcesContext);
 
     return (
@@ -30,6 +32,7 @@ export function Landing({onOpenService}: LandingProps) {
                     </button>
                 ))}
             </div>
+            <SourceSettings openTabCount={openTabCount}/>
         </div>
     );
 }
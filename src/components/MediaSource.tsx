@@ -38,6 +38,14 @@ export function MediaSourceController() {
         };
     }, []);
 
+    const openTabCount = useCallback((source: MediaSource) => {
+        const state = sourcesState[source];
+        if (!state) {
+            return 0;
+        }
+        return state.type == 'multi' ? state.tabs.length : (state.tab ? 1 : 0);
+    }, [sourcesState]);
+
     const currentSourceState = Object.values(sourcesState).find((s) => {
         if (s.type == 'multi') {
             return s.tabs.some((t) => t.isActive)
@@ -50,11 +58,13 @@ export function MediaSourceController() {
         <MediaSourceBar
             currentSource={currentSourceState?.source ?? null}
             changeSource={changeSource}
+            openTabCount={openTabCount}
         />
         <div className="media-view">
             <MediaView
                 sourceState={currentSourceState}
                 changeSource={changeSource}
+                openTabCount={openTabCount}
             />
         </div>
     </div>);
@@ -63,11 +73,13 @@ export function MediaSourceController() {
 interface MediaViewProps {
     sourceState: MediaState | null,
     changeSource: (source: MediaSource) => void
+    openTabCount: (source: MediaSource) => number
 }
 
 function MediaView({
                        sourceState,
                        changeSource,
+                       openTabCount,
                    }: MediaViewProps) {
     console.log(sourceState);
     if (sourceState) {
@@ -84,6 +96,6 @@ function MediaView({
             return <></>;
         }
     } else {
-        return <Landing onOpenService={changeSource}/>;
+        return <Landing onOpenService={changeSource} openTabCount={openTabCount}/>;
     }
 }
\ No newline at end of file
@@ -1,4 +1,5 @@
-import {useSources} from "../utils.tsx";
+import {useAllSources} from "../utils.tsx";
+import * as commands from "../commands.ts";
 import {MediaSource} from "../commands.ts";
 
 import "./MediaSourceBar.css";
@@ -6,21 +7,66 @@ import "./MediaSourceBar.css";
 interface MediaSourceBarProps {
     currentSource: MediaSource | null;
     changeSource: (id: MediaSource) => void;
+    openTabCount: (id: MediaSource) => number;
 }
 
-export function MediaSourceBar({currentSource, changeSource}: MediaSourceBarProps) {
-    const sources = useSources();
+export function MediaSourceBar({currentSource, changeSource, openTabCount}: MediaSourceBarProps) {
+    const allSources = useAllSources();
+
+    const hideSource = async (id: MediaSource) => {
+        const name = allSources.get(id)?.name ?? id;
+        if (!confirm(`Hide ${name} from the sidebar?`)) {
+            return;
+        }
+
+        const tabCount = openTabCount(id);
+        const closeTabs = tabCount > 0
+            && confirm(`${name} has ${tabCount} open tab(s). Close them? Cancel keeps them running in the background.`);
+
+        const settings = await commands.getSettings();
+        await commands.updateSettings({
+            ...settings,
+            hiddenSources: [...settings.hiddenSources, id],
+        }, closeTabs);
+    };
+
+    // Hidden sources can't be switched to, so bring them back first
+    const showSource = async (id: MediaSource) => {
+        const name = allSources.get(id)?.name ?? id;
+        if (!confirm(`Show ${name} in the sidebar again?`)) {
+            return;
+        }
+
+        const settings = await commands.getSettings();
+        await commands.updateSettings({
+            ...settings,
+            hiddenSources: settings.hiddenSources.filter((hidden) => hidden != id),
+        });
+        changeSource(id);
+    };
 
     return (
         <div className="media-source-bar">
             <div style={{flexShrink: 0, height: '100px'}}/>
-            {[...sources.values()]
+            {[...allSources.values()]
+                // tabs we kept running for a hidden source still need a way back
+                .filter((source) => !source.hidden || openTabCount(source.id) > 0)
                 .map((source) => (
                     <button
                         key={source.id}
-                        onClick={() => changeSource(source.id)}
-                        className={`media-source-add-button ${(currentSource == source.id) ? 'media-source-add-button--active' : ''}`}
-                        title={`Open ${source.name}`}
+                        onClick={() => source.hidden ? showSource(source.id) : changeSource(source.id)}
+                        onContextMenu={(e) => {
+                            e.preventDefault();
+                            source.hidden ? showSource(source.id) : hideSource(source.id);
+                        }}
+                        className={[
+                            'media-source-add-button',
+                            currentSource == source.id ? 'media-source-add-button--active' : '',
+                            source.hidden ? 'media-source-add-button--hidden' : '',
+                        ].join(' ')}
+                        title={source.hidden
+                            ? `${source.name} is hidden but has open tabs`
+                            : `Open ${source.name}`}
                     >
                         <img
                             className="media-source-icon"
@@ -30,6 +76,14 @@ export function MediaSourceBar({currentSource, changeSource}: MediaSourceBarProp
                         />
                     </button>
                 ))}
+            <div style={{flexGrow: 1}}/>
+            <button
+                className="media-source-home-button"
+                onClick={() => commands.showHome()}
+                title="Manage sources"
+            >
+                ⚙
+            </button>
         </div>
     );
 }
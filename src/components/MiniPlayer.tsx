@@ -0,0 +1,112 @@
+import {useEffect, useState} from "react";
+import * as commands from "../commands.ts";
+import {AppState, Position, TabState} from "../commands.ts";
+import {listen, UnlistenFn} from "@tauri-apps/api/event";
+
+import './MiniPlayer.css'
+
+// The page only reports its position when asked, so we poll while we're showing a tab
+const POSITION_POLL_INTERVAL = 1000;
+
+function allTabs(state: AppState): TabState[] {
+    return Object.values(state.media).flatMap((s) => s.type == 'multi' ? s.tabs : (s.tab ? [s.tab] : []));
+}
+
+// Our mini-player follows the backend's choice, falling back to whatever's playing
+function miniPlayerTab(state: AppState): TabState | null {
+    const tabs = allTabs(state);
+    return tabs.find((t) => t.key == state.miniPlayerTab)
+        ?? tabs.find((t) => t.isPlaying)
+        ?? null;
+}
+
+export function MiniPlayer() {
+    const [tab, setTab] = useState<TabState | null>(null);
+    const [position, setPosition] = useState<Position | null>(null);
+
+    useEffect(() => {
+        let unlistenFn: UnlistenFn | null = null;
+        (async () => {
+            unlistenFn = await listen<AppState>('BACKEND_STATE_EVENT', (event) => {
+                setTab(miniPlayerTab(event.payload));
+            });
+            await commands.emitBackendState();
+        })();
+
+        //cleanup our listener
+        return () => {
+            unlistenFn && unlistenFn();
+        };
+    }, []);
+
+    const tabKey = tab?.key ?? null;
+    useEffect(() => {
+        setPosition(null);
+        if (tabKey == null) {
+            return;
+        }
+
+        const poll = async () => {
+            try {
+                setPosition(await commands.getPosition(tabKey));
+            } catch {
+                // the page may not have any media yet
+                setPosition(null);
+            }
+        };
+        poll();
+        const interval = setInterval(poll, POSITION_POLL_INTERVAL);
+
+        return () => clearInterval(interval);
+    }, [tabKey]);
+
+    const progress = position?.duration ? Math.min(1, position.currentTime / position.duration) : 0;
+
+    return (<div className="mini-player" data-tauri-drag-region>
+        {tab?.artwork ? (
+            <img className="mini-player-artwork" src={tab.artwork} alt="" draggable={false}/>
+        ) : (
+            <div className="mini-player-artwork mini-player-artwork--empty"/>
+        )}
+        <div className="mini-player-body" data-tauri-drag-region>
+            <div className="mini-player-title" title={tab?.title ?? undefined}>
+                {tab ? (tab.title ?? tab.displayName) : "Nothing playing"}
+            </div>
+            <div className="mini-player-artist">{tab?.artist ?? ""}</div>
+            <div
+                className="mini-player-progress"
+                onClick={(e) => {
+                    if (!tab || !position?.duration) {
+                        return;
+                    }
+                    const bounds = e.currentTarget.getBoundingClientRect();
+                    commands.seek(tab.key, (e.clientX - bounds.left) / bounds.width * position.duration);
+                }}
+            >
+                <div className="mini-player-progress-fill" style={{width: `${progress * 100}%`}}/>
+            </div>
+            <div className="mini-player-controls">
+                <button disabled={!tab} onClick={() => tab && commands.previousTrack(tab.key)} title="Previous">
+                    {"⏮"}
+                </button>
+                <button
+                    disabled={!tab}
+                    onClick={() => tab && (tab.isPlaying ? commands.pauseTab(tab.key) : commands.playTab(tab.key))}
+                    title={tab?.isPlaying ? "Pause" : "Play"}
+                >
+                    {tab?.isPlaying ? "⏸" : "▶"}
+                </button>
+                <button disabled={!tab} onClick={() => tab && commands.nextTrack(tab.key)} title="Next">
+                    {"⏭"}
+                </button>
+                <button
+                    className="mini-player-expand"
+                    onClick={() => commands.setMiniPlayer(false)}
+                    title="Back to the full window"
+                >
+                    {"⤢"}
+                </button>
+            </div>
+        </div>
+    </div>);
+}
@@ -0,0 +1,130 @@
+import {useEffect, useState} from "react";
+import * as commands from "../commands.ts";
+import {MediaSource} from "../commands.ts";
+import {useAllSources} from "../utils.tsx";
+
+import "./SourceSettings.css";
+
+interface SourceSettingsProps {
+    openTabCount: (id: MediaSource) => number;
+}
+
+interface SourceRow {
+    id: MediaSource;
+    // Empty uses the source's own name
+    name: string;
+    hidden: boolean;
+}
+
+export function SourceSettings({openTabCount}: SourceSettingsProps) {
+    const allSources = useAllSources();
+    const [rows, setRows] = useState<SourceRow[]>([]);
+    const [error, setError] = useState<string | null>(null);
+
+    // Start over from our saved settings whenever they change
+    useEffect(() => {
+        (async () => {
+            const settings = await commands.getSettings();
+            setRows([...allSources.values()].map((source) => ({
+                id: source.id,
+                name: settings.sourceNames[source.id] ?? "",
+                hidden: source.hidden,
+            })));
+            setError(null);
+        })();
+    }, [allSources]);
+
+    const updateRow = (index: number, update: Partial<SourceRow>) => {
+        setRows(rows.map((row, i) => i == index ? {...row, ...update} : row));
+    };
+
+    const move = (index: number, offset: number) => {
+        const target = index + offset;
+        if (target < 0 || target >= rows.length) {
+            return;
+        }
+        const reordered = [...rows];
+        [reordered[index], reordered[target]] = [reordered[target], reordered[index]];
+        setRows(reordered);
+    };
+
+    const save = async () => {
+        const settings = await commands.getSettings();
+        const newlyHidden = rows.filter((row) => row.hidden && !settings.hiddenSources.includes(row.id));
+        const tabCount = newlyHidden.reduce((count, row) => count + openTabCount(row.id), 0);
+        const closeTabs = tabCount > 0
+            && confirm(`The sources you're hiding have ${tabCount} open tab(s). Close them? Cancel keeps them running in the background.`);
+
+        const sourceNames: Partial<Record<MediaSource, string>> = {};
+        for (const row of rows) {
+            if (row.name.trim()) {
+                sourceNames[row.id] = row.name.trim();
+            }
+        }
+
+        try {
+            await commands.updateSettings({
+                ...settings,
+                sourceOrder: rows.map((row) => row.id),
+                hiddenSources: rows.filter((row) => row.hidden).map((row) => row.id),
+                sourceNames,
+            }, closeTabs);
+            setError(null);
+        } catch (e) {
+            setError(String(e));
+        }
+    };
+
+    return (
+        <div className="source-settings">
+            <span className="landing-title">Sources</span>
+            <ol className="source-settings-list">
+                {rows.map((row, index) => {
+                    const source = allSources.get(row.id);
+                    return (
+                        <li key={row.id} className="source-settings-row">
+                            <img
+                                className="source-settings-icon"
+                                src={source?.iconUrl}
+                                alt=""
+                                draggable={false}
+                            />
+                            <input
+                                className="source-settings-name"
+                                value={row.name}
+                                placeholder="Default name"
+                                onChange={(e) => updateRow(index, {name: e.target.value})}
+                            />
+                            <label className="source-settings-visible">
+                                <input
+                                    type="checkbox"
+                                    checked={!row.hidden}
+                                    onChange={(e) => updateRow(index, {hidden: !e.target.checked})}
+                                />
+                                Show
+                            </label>
+                            <button
+                                className="source-settings-move"
+                                disabled={index == 0}
+                                onClick={() => move(index, -1)}
+                                title="Move up"
+                            >
+                                ↑
+                            </button>
+                            <button
+                                className="source-settings-move"
+                                disabled={index == rows.length - 1}
+                                onClick={() => move(index, 1)}
+                                title="Move down"
+                            >
+                                ↓
+                            </button>
+                        </li>
+                    );
+                })}
+            </ol>
+            {error && <span className="source-settings-error">{error}</span>}
+            <button className="source-settings-save" onClick={save}>Save</button>
+        </div>
+    );
+}
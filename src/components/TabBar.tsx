@@ -1,7 +1,6 @@
 import {TabItem} from "./TabItem";
 import {MediaSource, TabKey, TabState} from "../commands.ts";
-import {useContext} from "react";
-import {MediaSourcesContext} from "../utils.tsx";
+import {useAllSources} from "../utils.tsx";
 
 interface TabBarProps {
     source: MediaSource,
@@ -18,7 +17,8 @@ export function TabBar({
                            onSwitchTab,
                            onCloseTab,
                        }: TabBarProps) {
-    const sourceDefinition = useContext(MediaSourcesContext).get(source)!;
+    // a hidden source's tabs can still be showing
+    const sourceDefinition = useAllSources().get(source)!;
 
     return (
         <div className="tab-bar">
@@ -1,6 +1,5 @@
-import {TabState} from "../commands.ts";
-import {useContext} from "react";
-import {MediaSourcesContext} from "../utils.tsx";
+import {TabState, toggleTabMute} from "../commands.ts";
+import {useAllSources} from "../utils.tsx";
 
 interface TabItemProps {
     tab: TabState;
@@ -9,7 +8,7 @@ interface TabItemProps {
 }
 
 export function TabItem({tab, onSelect, onClose}: TabItemProps) {
-    const iconUrl = useContext(MediaSourcesContext).get(tab.source)!.iconUrl;
+    const iconUrl = useAllSources().get(tab.source)!.iconUrl;
 
     return (
         <button
@@ -27,6 +26,18 @@ export function TabItem({tab, onSelect, onClose}: TabItemProps) {
                 <img className="tab-icon" src={iconUrl} alt="" draggable={false}/>
             )}
             <span className="tab-name">{tab.displayName}</span>
+            {(tab.audible || tab.muted) && (
+                <span
+                    className="tab-audio"
+                    title={tab.muted ? "Unmute tab" : "Mute tab"}
+                    onClick={(e) => {
+                        e.stopPropagation();
+                        toggleTabMute(tab.key);
+                    }}
+                >
+          {tab.muted ? "\u{1F507}" : "\u{1F50A}"}
+        </span>
+            )}
             <span
                 className="tab-close"
                 onClick={(e) => {
@@ -0,0 +1,9 @@
+import React from "react";
+import ReactDOM from "react-dom/client";
+import {MiniPlayer} from "./components/MiniPlayer.tsx";
+
+ReactDOM.createRoot(document.getElementById("root") as HTMLElement).render(
+    <React.StrictMode>
+        <MiniPlayer/>
+    </React.StrictMode>,
+);
@@ -3,6 +3,13 @@ import {MediaDefinition, MediaSource} from "./commands.ts";
 
 export const MediaSourcesContext = createContext<Map<MediaSource, MediaDefinition>>(new Map());
 
+// Hidden sources too, for our settings and tabs that outlive their source being hidden
+export const AllMediaSourcesContext = createContext<Map<MediaSource, MediaDefinition>>(new Map());
+
 export function useSources() {
     return useContext(MediaSourcesContext);
+}
+
+export function useAllSources() {
+    return useContext(AllMediaSourcesContext);
 }
\ No newline at end of file
@@ -12,6 +12,15 @@ export default defineConfig(async () => ({
   //
   // 1. prevent Vite from obscuring rust errors
   clearScreen: false,
+  // our mini-player window is its own page
+  build: {
+    rollupOptions: {
+      input: {
+        main: "index.html",
+        miniPlayer: "mini-player.html",
+      },
+    },
+  },
   // 2. tauri expects a fixed port, fail if that port is not available
   server: {
     port: 1420,